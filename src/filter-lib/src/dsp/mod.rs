@@ -0,0 +1,246 @@
+use crate::la::Complex;
+use crate::netlist::Scalar;
+
+use std::f64::consts::PI;
+
+mod polyphase;
+
+pub use polyphase::{Decimator, Interpolator};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window
+{
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    /// Very flat passband - best for reading amplitudes
+    FlatTop,
+}
+
+impl Window
+{
+    pub fn coefficients(&self, len: usize) -> Vec<Scalar>
+    {
+        // Generalised cosine windows:
+        // w[n] = a0 - a1.cos(2.pi.n/N) + a2.cos(4.pi.n/N) - ...
+        let terms: &[Scalar] = match self
+        {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::Blackman => &[0.42, 0.5, 0.08],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368],
+        };
+
+        (0..len)
+            .map(|n|
+            {
+                let x = 2.0 * PI * (n as Scalar) / (len as Scalar);
+                terms.iter().enumerate()
+                    .map(|(k, a)| if k % 2 == 0 { a * (x * k as Scalar).cos() } else { -a * (x * k as Scalar).cos() })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Single-sided amplitude spectrum
+pub struct Spectrum
+{
+    pub frequencies: Vec<Scalar>,
+    /// Peak amplitude of the sinusoid at each frequency
+    pub magnitudes: Vec<Scalar>,
+    /// Phase in degrees, relative to a cosine
+    pub phases: Vec<Scalar>,
+}
+
+impl Spectrum
+{
+    pub fn magnitudes_db(&self) -> Vec<Scalar>
+    {
+        self.magnitudes.iter().map(|m| 20.0 * m.log10()).collect()
+    }
+}
+
+/// Spectrum of uniformly sampled `values`, windowed and
+/// corrected for the window's coherent gain so a sinusoid
+/// centred on a bin reads its true amplitude
+pub fn spectrum(values: &[Scalar], sample_rate: Scalar, window: Window) -> Spectrum
+{
+    let len = values.len();
+    let coefficients = window.coefficients(len);
+    let coherent_gain = coefficients.iter().sum::<Scalar>();
+
+    let mut data = values.iter().zip(coefficients.iter())
+        .map(|(v, w)| Complex::new(v * w, 0.0))
+        .collect::<Vec<_>>();
+    fft(&mut data);
+
+    let bins = len / 2 + 1;
+    let mut frequencies = Vec::with_capacity(bins);
+    let mut magnitudes = Vec::with_capacity(bins);
+    let mut phases = Vec::with_capacity(bins);
+
+    for (bin, value) in data.iter().take(bins).enumerate()
+    {
+        // Everything but DC and Nyquist has
+        // its energy split with a negative bin
+        let scale = if (bin == 0) || (2 * bin == len) { 1.0 } else { 2.0 };
+
+        frequencies.push((bin as Scalar) * sample_rate / (len as Scalar));
+        magnitudes.push(scale * value.norm() / coherent_gain);
+        phases.push(value.arg().to_degrees());
+    }
+
+    Spectrum { frequencies, magnitudes, phases }
+}
+
+/// In-place forward DFT of any length - radix-2 for
+/// powers of two, otherwise Bluestein's algorithm
+pub fn fft(data: &mut [Complex<Scalar>])
+{
+    let len = data.len();
+    if len <= 1
+    {
+        return;
+    }
+
+    if len.is_power_of_two()
+    {
+        fft_radix2(data, false);
+        return;
+    }
+
+    // Bluestein: express the DFT as a convolution
+    // with a chirp, evaluated with power-of-two FFTs
+
+    let padded = (2 * len - 1).next_power_of_two();
+    let chirp = (0..len)
+        .map(|n|
+        {
+            // n^2 mod 2N keeps the angle accurate for large n
+            let n2 = ((n as u128 * n as u128) % (2 * len as u128)) as Scalar;
+            Complex::from_polar(1.0, -PI * n2 / (len as Scalar))
+        })
+        .collect::<Vec<_>>();
+
+    let mut a = vec![Complex::new(0.0, 0.0); padded];
+    for n in 0..len
+    {
+        a[n] = data[n] * chirp[n];
+    }
+
+    let mut b = vec![Complex::new(0.0, 0.0); padded];
+    b[0] = chirp[0].conj();
+    for n in 1..len
+    {
+        b[n] = chirp[n].conj();
+        b[padded - n] = chirp[n].conj();
+    }
+
+    fft_radix2(&mut a, false);
+    fft_radix2(&mut b, false);
+    for (a, b) in a.iter_mut().zip(b.iter())
+    {
+        *a *= b;
+    }
+    fft_radix2(&mut a, true);
+
+    let scale = 1.0 / (padded as Scalar);
+    for n in 0..len
+    {
+        data[n] = a[n] * chirp[n] * scale;
+    }
+}
+
+fn fft_radix2(data: &mut [Complex<Scalar>], inverse: bool)
+{
+    let len = data.len();
+
+    // Bit reversal permutation
+
+    let mut j = 0;
+    for i in 1..len
+    {
+        let mut bit = len >> 1;
+        while j & bit != 0
+        {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j
+        {
+            data.swap(i, j);
+        }
+    }
+
+    // Butterflies
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= len
+    {
+        let step = Complex::from_polar(1.0, sign * 2.0 * PI / (size as Scalar));
+        for start in (0..len).step_by(size)
+        {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..size / 2
+            {
+                let even = data[start + k];
+                let odd = data[start + k + size / 2] * w;
+                data[start + k] = even + odd;
+                data[start + k + size / 2] = even - odd;
+                w *= step;
+            }
+        }
+        size <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn fft_matches_dft()
+    {
+        for len in [1, 2, 8, 12, 17]
+        {
+            let input = (0..len)
+                .map(|n| Complex::new((n as Scalar * 0.7).sin(), (n as Scalar * 1.3).cos()))
+                .collect::<Vec<_>>();
+
+            let mut output = input.clone();
+            fft(&mut output);
+
+            for (k, actual) in output.iter().enumerate()
+            {
+                let expected = input.iter().enumerate()
+                    .map(|(n, x)| x * Complex::from_polar(1.0, -2.0 * PI * (k * n) as Scalar / len as Scalar))
+                    .sum::<Complex<Scalar>>();
+                assert!((actual - expected).norm() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn windowed_sine_amplitude()
+    {
+        let values = (0..1000)
+            .map(|n| 3.0 * (2.0 * PI * 50.0 * (n as Scalar) / 1000.0).cos())
+            .collect::<Vec<_>>();
+
+        for window in [Window::Rectangular, Window::Hann, Window::FlatTop]
+        {
+            let spectrum = spectrum(&values, 1000.0, window);
+            assert!((spectrum.frequencies[50] - 50.0).abs() < 1e-9);
+            assert!((spectrum.magnitudes[50] - 3.0).abs() < 1e-6);
+        }
+    }
+}
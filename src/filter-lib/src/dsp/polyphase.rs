@@ -0,0 +1,275 @@
+use crate::netlist::Scalar;
+use super::Window;
+
+use std::f64::consts::PI;
+
+/// Taps either side of the centre of the anti-aliasing
+/// filter, in samples at the lower rate - which is also
+/// its delay
+const HALF_LENGTH: usize = 16;
+/// Cutoff of the anti-aliasing filter, as a fraction of
+/// the lower rate's Nyquist frequency
+const CUTOFF: Scalar = 0.9;
+
+/// Windowed-sinc lowpass with unity gain for changing
+/// rate by `factor`, split into `factor` phases - phase p has taps
+/// p, p + factor, p + 2.factor, ...
+fn phases(factor: usize) -> Vec<Vec<Scalar>>
+{
+    let len = 2 * HALF_LENGTH * factor + 1;
+    let centre = (HALF_LENGTH * factor) as Scalar;
+    let cutoff = CUTOFF * 0.5 / (factor as Scalar);
+
+    // Symmetric Blackman window - the periodic one a sample shorter
+    let window = Window::Blackman.coefficients(len - 1);
+
+    let mut taps = (0..len)
+        .map(|i|
+        {
+            let x = 2.0 * cutoff * (i as Scalar - centre);
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            2.0 * cutoff * sinc * window[i % (len - 1)]
+        })
+        .collect::<Vec<_>>();
+
+    let gain = taps.iter().sum::<Scalar>();
+    taps.iter_mut().for_each(|t| *t /= gain);
+
+    (0..factor)
+        .map(|p| (0..=2 * HALF_LENGTH).map(|k| taps.get(k * factor + p).copied().unwrap_or(0.0)).collect())
+        .collect()
+}
+
+/// Lowpass filters a signal and keeps one sample in
+/// `factor`, so nothing above the new Nyquist frequency
+/// aliases. Each output only evaluates the taps it needs.
+pub struct Decimator
+{
+    phases: Vec<Vec<Scalar>>,
+    /// Recent input of each phase, a ring per phase
+    history: Vec<Scalar>,
+    /// Newest slot in every ring
+    position: usize,
+}
+
+impl Decimator
+{
+    /// None for a factor of zero
+    pub fn new(factor: usize) -> Option<Self>
+    {
+        if factor == 0
+        {
+            return None;
+        }
+        let phases = phases(factor);
+        let history = vec![0.0; factor * phases[0].len()];
+        Some(Decimator { phases, history, position: 0 })
+    }
+
+    pub fn factor(&self) -> usize
+    {
+        self.phases.len()
+    }
+
+    /// Output samples between an input and its effect on the output
+    pub fn delay(&self) -> usize
+    {
+        HALF_LENGTH
+    }
+
+    /// Settles the filter as if the input had always been `value`
+    pub fn prime(&mut self, value: Scalar)
+    {
+        self.history.iter_mut().for_each(|h| *h = value);
+    }
+
+    /// Takes `factor` input samples, oldest first, and
+    /// returns the next output sample - None if `input`
+    /// isn't one frame
+    pub fn process(&mut self, input: &[Scalar]) -> Option<Scalar>
+    {
+        let factor = self.factor();
+        let len = self.phases[0].len();
+        if input.len() != factor
+        {
+            return None;
+        }
+
+        self.position = (self.position + 1) % len;
+
+        // The newest sample belongs to phase 0
+        for (j, sample) in input.iter().enumerate()
+        {
+            self.history[(factor - 1 - j) * len + self.position] = *sample;
+        }
+
+        let mut result = 0.0;
+        for (taps, history) in self.phases.iter().zip(self.history.chunks_exact(len))
+        {
+            for (k, tap) in taps.iter().enumerate()
+            {
+                result += tap * history[(self.position + len - k) % len];
+            }
+        }
+        Some(result)
+    }
+
+    /// Decimates a whole signal, so output k is the filtered
+    /// input k.factor with no delay. It's extended with its
+    /// first and last values to fill the filter.
+    pub fn decimate(&mut self, values: &[Scalar]) -> Vec<Scalar>
+    {
+        let (Some(first), Some(last)) = (values.first(), values.last()) else { return Vec::new(); };
+        let (factor, delay) = (self.factor(), self.delay());
+        self.prime(*first);
+
+        let padded = std::iter::repeat_n(first, factor - 1)
+            .chain(values.iter())
+            .chain(std::iter::repeat_n(last, delay * factor))
+            .copied()
+            .collect::<Vec<_>>();
+
+        padded.chunks_exact(factor)
+            .flat_map(|frame| self.process(frame))
+            .skip(delay)
+            .take(values.len() / factor)
+            .collect()
+    }
+}
+
+/// Raises the sample rate by `factor`, filtering out the
+/// images so the result is smooth - e.g. to drive an
+/// oversampled simulation from audio
+pub struct Interpolator
+{
+    /// Each normalised to unity gain
+    phases: Vec<Vec<Scalar>>,
+    /// Recent input, a ring
+    history: Vec<Scalar>,
+    position: usize,
+}
+
+impl Interpolator
+{
+    /// None for a factor of zero
+    pub fn new(factor: usize) -> Option<Self>
+    {
+        if factor == 0
+        {
+            return None;
+        }
+        let mut phases = phases(factor);
+        for taps in phases.iter_mut()
+        {
+            let gain = taps.iter().sum::<Scalar>();
+            taps.iter_mut().for_each(|t| *t /= gain);
+        }
+        let history = vec![0.0; phases[0].len()];
+        Some(Interpolator { phases, history, position: 0 })
+    }
+
+    pub fn factor(&self) -> usize
+    {
+        self.phases.len()
+    }
+
+    /// Input samples between an input and its effect on the output
+    pub fn delay(&self) -> usize
+    {
+        HALF_LENGTH
+    }
+
+    /// Settles the filter as if the input had always been `value`
+    pub fn prime(&mut self, value: Scalar)
+    {
+        self.history.iter_mut().for_each(|h| *h = value);
+    }
+
+    /// Takes one input sample and fills `output` with
+    /// `factor` samples, oldest first, the last at the
+    /// input's time - the frames a `Decimator` takes.
+    /// None if `output` isn't one frame.
+    pub fn process(&mut self, input: Scalar, output: &mut [Scalar]) -> Option<()>
+    {
+        let len = self.history.len();
+        if output.len() != self.factor()
+        {
+            return None;
+        }
+
+        self.position = (self.position + 1) % len;
+        self.history[self.position] = input;
+
+        // The frame ends at the input sample - the samples
+        // before it come from the later phases, a sample back
+        let factor = self.factor();
+        for (i, output) in output.iter_mut().enumerate()
+        {
+            let phase = (i + 1) % factor;
+            let back = if phase == 0 { 0 } else { 1 };
+            *output = self.phases[phase].iter().enumerate()
+                .map(|(k, tap)| tap * self.history[(self.position + 2 * len - k - back) % len])
+                .sum();
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn sine(frequency: Scalar, len: usize) -> Vec<Scalar>
+    {
+        (0..len).map(|n| (2.0 * PI * frequency * n as Scalar).sin()).collect()
+    }
+
+    fn peak(values: &[Scalar]) -> Scalar
+    {
+        values.iter().fold(0.0, |m, v| m.max(v.abs()))
+    }
+
+    #[test]
+    fn decimator_rejects_aliases()
+    {
+        // Frequencies as a fraction of the oversampled rate,
+        // with 4x oversampling the output Nyquist is 0.125
+        let input = sine(0.05, 4000);
+        let passed = Decimator::new(4).unwrap().decimate(&input);
+        assert_eq!(passed.len(), 1000);
+        assert!((100..900).all(|k| (passed[k] - input[4 * k]).abs() < 1e-3));
+
+        let rejected = Decimator::new(4).unwrap().decimate(&sine(0.2, 4000));
+        assert!(peak(&rejected[100..900]) < 1e-4);
+
+        assert!(Decimator::new(0).is_none());
+        assert_eq!(Decimator::new(4).unwrap().process(&[0.0; 3]), None);
+    }
+
+    #[test]
+    fn interpolation_round_trip()
+    {
+        let input = sine(0.1, 500);
+        let mut interpolator = Interpolator::new(3).unwrap();
+        let mut decimator = Decimator::new(3).unwrap();
+        let mut frame = [0.0; 3];
+
+        let output = input.iter()
+            .map(|x|
+            {
+                interpolator.process(*x, &mut frame).unwrap();
+                decimator.process(&frame).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let delay = interpolator.delay() + decimator.delay();
+        for n in 100..500
+        {
+            assert!((output[n] - input[n - delay]).abs() < 1e-4);
+        }
+
+        assert!(Interpolator::new(0).is_none());
+        assert_eq!(interpolator.process(0.0, &mut [0.0; 4]), None);
+    }
+}
@@ -9,6 +9,7 @@ pub struct Graph
 
 impl Graph
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         let trace_count = 0;
@@ -23,6 +24,36 @@ impl Graph
     }
 
     pub fn add_trace(&mut self, values: &[f64], range: f64, label: &str, units: &str)
+    {
+        let color = self.add_legend(label, range, units);
+        self.add_path(values, range, color, 1.0);
+        self.trace_count += 1;
+    }
+
+    /// Overlays a family of related traces, e.g. the results
+    /// of a `.STEP` sweep. They share one legend entry and
+    /// colour, fading from the first member to the last,
+    /// and each is labelled at its right hand end.
+    pub fn add_trace_family(&mut self, family: &[(&str, &[f64])], range: f64, label: &str, units: &str)
+    {
+        let color = self.add_legend(label, range, units);
+
+        for (i, (member_label, values)) in family.iter().enumerate()
+        {
+            let opacity = 1.0 - 0.7 * (i as f64) / (family.len().max(2) - 1) as f64;
+            self.add_path(values, range, color, opacity);
+
+            if let Some(last) = values.last()
+            {
+                self.svg.push_str(&format!(r#"<text x="{}" y="{}" fill="{}" fill-opacity="{}" text-anchor="end" dominant-baseline="auto" font-family="monospace">{}</text>"#,
+                    self.width - 5, self.y(*last, range) - 5.0, color, opacity, member_label));
+            }
+        }
+
+        self.trace_count += 1;
+    }
+
+    fn add_legend(&mut self, label: &str, range: f64, units: &str) -> &'static str
     {
         let color_index = self.trace_count % COLORS.len();
         let color = COLORS[color_index];
@@ -35,6 +66,11 @@ impl Graph
         self.svg.push_str(&format!(r#"{} (±{}{})</text>"#,
             label, range, units));
 
+        color
+    }
+
+    fn add_path(&mut self, values: &[f64], range: f64, color: &str, opacity: f64)
+    {
         self.svg.push_str(r#"<path d=""#);
         for (i, val) in values.iter().enumerate()
         {
@@ -45,17 +81,21 @@ impl Graph
             };
 
             let width = self.width as f64;
-            let height = self.height as f64;
             let i = i as f64;
             let len = values.len() as f64;
 
             let x = width * i / len;
-            let y = (height * 0.5) - (height * 0.5 * val / range);
+            let y = self.y(*val, range);
 
             self.svg.push_str(&format!("{}{} {}", char, x, y));
         }
-        self.svg.push_str(&format!(r#"" stroke="{}" stroke-width="3" fill="transparent"/>"#, color));
-        self.trace_count += 1;
+        self.svg.push_str(&format!(r#"" stroke="{}" stroke-opacity="{}" stroke-width="3" fill="transparent"/>"#, color, opacity));
+    }
+
+    fn y(&self, val: f64, range: f64) -> f64
+    {
+        let height = self.height as f64;
+        (height * 0.5) - (height * 0.5 * val / range)
     }
 
     pub fn to_svg(mut self) -> String
@@ -65,10 +105,35 @@ impl Graph
     }
 }
 
+#[allow(clippy::redundant_static_lifetimes)]
 const COLORS: [&'static str;4] =
 [
     "darkseagreen",
     "coral",
     "cornflowerblue",
     "blueviolet",
-];
\ No newline at end of file
+];
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn trace_family()
+    {
+        let mut graph = Graph::new();
+        graph.add_trace(&[0.0, 1.0], 5.0, "V(1)", "V");
+        graph.add_trace_family(&[("R1=1k", &[0.0, 1.0]), ("R1=2k", &[0.0, 2.0]), ("R1=4k", &[0.0, 4.0])], 5.0, "V(2)", "V");
+        let svg = graph.to_svg();
+
+        // One legend entry for the family, in the next colour,
+        // and a label at the end of each member fading out
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert_eq!(svg.matches("<path").count(), 5);
+        assert_eq!(svg.matches(r#"<text x="1495""#).count(), 3);
+        assert_eq!(svg.matches(r#"fill="coral" fill-opacity="1" "#).count(), 1);
+        assert!(svg.contains(r#"stroke="coral" stroke-opacity="0.3"#));
+        assert!(svg.contains(">R1=4k</text>"));
+    }
+}
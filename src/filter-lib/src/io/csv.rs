@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use crate::la::Complex;
+use crate::netlist::{Scalar, SimulationOptions, Unit};
+use crate::sim::{Axis, Metadata, Signal, SignalKind, SimulationError, SimulationResults};
+
+/// How AC results are split into columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComplexFormat
+{
+    /// "mag(V_2)" and "phase(V_2)", in degrees
+    MagnitudePhase,
+    /// "re(V_2)" and "im(V_2)"
+    RealImaginary,
+}
+
+/// Layout of a delimited table - a header row naming each
+/// column and its unit, e.g. "V_2 (V)", then a row per point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvFormat
+{
+    pub delimiter: char,
+    /// Significant digits
+    pub precision: usize,
+    pub complex: ComplexFormat,
+}
+
+impl CsvFormat
+{
+    pub const CSV: CsvFormat = CsvFormat { delimiter: ',', precision: 6, complex: ComplexFormat::MagnitudePhase };
+    pub const TSV: CsvFormat = CsvFormat { delimiter: '\t', ..CsvFormat::CSV };
+}
+
+impl Default for CsvFormat
+{
+    fn default() -> Self
+    {
+        CsvFormat::CSV
+    }
+}
+
+#[derive(Debug)]
+pub enum CsvError
+{
+    Io(std::io::Error),
+    /// Lines count from 1, the header
+    Invalid{line: usize, reason: String},
+}
+
+impl Display for CsvError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            CsvError::Io(error) => write!(f, "{}", error),
+            CsvError::Invalid { line, reason } => write!(f, "invalid table, line {} - {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for CsvError
+{
+}
+
+impl From<std::io::Error> for CsvError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        CsvError::Io(error)
+    }
+}
+
+/// Time, then every signal
+pub fn write_transient(out: &mut impl Write, results: &SimulationResults, format: &CsvFormat) -> std::io::Result<()>
+{
+    let mut columns = vec![(header("time", "s"), results.points())];
+    columns.extend(results.signals().iter().map(|signal| (header(&signal.name, &signal.unit.to_string()), &signal.values[..])));
+    write_table(out, &columns, format)
+}
+
+/// Frequency, then two columns for every signal
+pub fn write_ac(out: &mut impl Write, results: &SimulationResults<Complex<Scalar>>, format: &CsvFormat) -> std::io::Result<()>
+{
+    let parts = results.signals().iter()
+        .flat_map(|signal|
+        {
+            let unit = signal.unit.to_string();
+            let (first, second): (Vec<_>, Vec<_>) = match format.complex
+            {
+                ComplexFormat::MagnitudePhase => signal.values.iter().map(|v| (v.norm(), v.arg().to_degrees())).unzip(),
+                ComplexFormat::RealImaginary => signal.values.iter().map(|v| (v.re, v.im)).unzip(),
+            };
+            let (first_name, second_name, second_unit) = match format.complex
+            {
+                ComplexFormat::MagnitudePhase => ("mag", "phase", "deg".to_owned()),
+                ComplexFormat::RealImaginary => ("re", "im", unit.clone()),
+            };
+            [
+                (header(&format!("{}({})", first_name, signal.name), &unit), first),
+                (header(&format!("{}({})", second_name, signal.name), &second_unit), second),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut columns = vec![(header("frequency", "Hz"), results.points())];
+    columns.extend(parts.iter().map(|(name, values)| (name.clone(), &values[..])));
+    write_table(out, &columns, format)
+}
+
+/// The swept source's value, then every result - each
+/// must have a value for every point of the sweep
+pub fn write_dc(out: &mut impl Write, source: &str, sweep: &[Scalar], values: &BTreeMap<String, Vec<Scalar>>, format: &CsvFormat) -> std::io::Result<()>
+{
+    let mut columns = vec![(header(source, "V"), sweep)];
+    columns.extend(values.iter().map(|(name, values)| (header(name, &unit_of(name).to_string()), &values[..])));
+    write_table(out, &columns, format)
+}
+
+/// Reads back `write_transient`
+pub fn read_transient(input: impl BufRead, format: &CsvFormat) -> Result<SimulationResults, CsvError>
+{
+    let (mut columns, mut values) = read_table(input, format)?;
+    let points = axis(&mut columns, &mut values, "time")?;
+
+    let signals = columns.into_iter().zip(values)
+        .map(|((name, unit), values)| Signal { name, unit, kind: SignalKind::of(unit), values })
+        .collect();
+
+    SimulationResults::new(Axis::Time, points.clone(), signals, metadata(&points, format)).map_err(invalid_results)
+}
+
+/// Reads back `write_ac`, written with the same `format.complex`
+pub fn read_ac(input: impl BufRead, format: &CsvFormat) -> Result<SimulationResults<Complex<Scalar>>, CsvError>
+{
+    let (mut columns, mut values) = read_table(input, format)?;
+    let points = axis(&mut columns, &mut values, "frequency")?;
+
+    let (first_name, second_name) = match format.complex
+    {
+        ComplexFormat::MagnitudePhase => ("mag(", "phase("),
+        ComplexFormat::RealImaginary => ("re(", "im("),
+    };
+
+    let mut signals = Vec::new();
+    for (pair, values) in columns.chunks(2).zip(values.chunks(2))
+    {
+        let invalid = |reason: String| CsvError::Invalid { line: 1, reason };
+
+        let name = pair[0].0.strip_prefix(first_name).and_then(|n| n.strip_suffix(')'))
+            .ok_or_else(|| invalid(format!("expected {}signal) but found \"{}\"", first_name, pair[0].0)))?;
+        let expected = format!("{}{})", second_name, name);
+        if pair.get(1).map(|(second, _)| second) != Some(&expected)
+        {
+            return Err(invalid(format!("expected \"{}\" after \"{}\"", expected, pair[0].0)));
+        }
+
+        let unit = pair[0].1;
+        let values = values[0].iter().zip(values[1].iter())
+            .map(|(a, b)| match format.complex
+            {
+                ComplexFormat::MagnitudePhase => Complex::from_polar(*a, b.to_radians()),
+                ComplexFormat::RealImaginary => Complex::new(*a, *b),
+            })
+            .collect();
+        signals.push(Signal { name: name.to_owned(), unit, kind: SignalKind::of(unit), values });
+    }
+
+    let mut metadata = metadata(&points, format);
+    metadata.step = None;
+    SimulationResults::new(Axis::Frequency, points, signals, metadata).map_err(invalid_results)
+}
+
+/// The swept source's name and values, and every result
+pub type DcTable = (String, Vec<Scalar>, BTreeMap<String, Vec<Scalar>>);
+
+/// Reads back `write_dc`
+pub fn read_dc(input: impl BufRead, format: &CsvFormat) -> Result<DcTable, CsvError>
+{
+    let (mut columns, mut values) = read_table(input, format)?;
+    if columns.is_empty()
+    {
+        return Err(CsvError::Invalid { line: 1, reason: "no columns".to_owned() });
+    }
+    let (source, _) = columns.remove(0);
+    let sweep = values.remove(0);
+
+    Ok((source, sweep, columns.into_iter().map(|(name, _)| name).zip(values).collect()))
+}
+
+fn header(name: &str, unit: &str) -> String
+{
+    if unit.is_empty() { name.to_owned() } else { format!("{} ({})", name, unit) }
+}
+
+fn unit_of(name: &str) -> Unit
+{
+    if name.starts_with("I_") { Unit::AMPS } else { Unit::VOLTS }
+}
+
+fn write_table(out: &mut impl Write, columns: &[(String, &[Scalar])], format: &CsvFormat) -> std::io::Result<()>
+{
+    let rows = columns.first().map_or(0, |(_, values)| values.len());
+    if let Some((name, values)) = columns.iter().find(|(_, values)| values.len() != rows)
+    {
+        let message = format!("\"{}\" has {} values but \"{}\" has {}", name, values.len(), columns[0].0, rows);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+    }
+
+    let delimiter = format.delimiter.to_string();
+    let digits = format.precision.max(1) - 1;
+
+    let names = columns.iter().map(|(name, _)| quote(name, format.delimiter)).collect::<Vec<_>>();
+    writeln!(out, "{}", names.join(&delimiter))?;
+
+    for row in 0..rows
+    {
+        let values = columns.iter().map(|(_, values)| format!("{:.*e}", digits, values[row])).collect::<Vec<_>>();
+        writeln!(out, "{}", values.join(&delimiter))?;
+    }
+    Ok(())
+}
+
+/// Quoted if it has the delimiter or quotes in it -
+/// probe names such as "V(2,3)" have commas
+fn quote(field: &str, delimiter: char) -> String
+{
+    if field.contains(delimiter) || field.contains('"')
+    {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else
+    {
+        field.to_owned()
+    }
+}
+
+fn invalid_results(error: SimulationError) -> CsvError
+{
+    CsvError::Invalid { line: 1, reason: error.to_string() }
+}
+
+fn split(line: &str, delimiter: char) -> Result<Vec<String>, String>
+{
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next()
+    {
+        match c
+        {
+            '"' if quoted && (chars.peek() == Some(&'"')) =>
+            {
+                chars.next();
+                field.push('"');
+            },
+            '"' if quoted || field.is_empty() => quoted = !quoted,
+            c if (c == delimiter) && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted
+    {
+        return Err("unterminated quotes".to_owned());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Each column's name and unit, and its values
+type Table = (Vec<(String, Unit)>, Vec<Vec<Scalar>>);
+
+fn read_table(input: impl BufRead, format: &CsvFormat) -> Result<Table, CsvError>
+{
+    let mut lines = input.lines();
+    let header = lines.next().ok_or(CsvError::Invalid { line: 1, reason: "no header".to_owned() })??;
+    let invalid = |line: usize| move |reason: String| CsvError::Invalid { line, reason };
+
+    let columns = split(&header, format.delimiter).map_err(invalid(1))?.into_iter()
+        .map(|column| match column.strip_suffix(')').and_then(|c| c.rsplit_once(" ("))
+        {
+            Some((name, unit)) => Ok((name.to_owned(), unit.parse::<Unit>().unwrap_or(Unit::NONE))),
+            None => Ok((column, Unit::NONE)),
+        })
+        .collect::<Result<Vec<_>, CsvError>>()?;
+
+    let mut values = vec![Vec::new(); columns.len()];
+    for (index, line) in lines.enumerate()
+    {
+        let line_number = index + 2;
+        let line = line?;
+        if line.trim().is_empty()
+        {
+            continue;
+        }
+
+        let fields = split(&line, format.delimiter).map_err(invalid(line_number))?;
+        if fields.len() != columns.len()
+        {
+            return Err(invalid(line_number)(format!("expected {} values but found {}", columns.len(), fields.len())));
+        }
+        for (column, field) in values.iter_mut().zip(fields)
+        {
+            column.push(field.trim().parse::<Scalar>().map_err(|_| invalid(line_number)(format!("invalid value \"{}\"", field)))?);
+        }
+    }
+
+    Ok((columns, values))
+}
+
+/// Takes the first column, which must be `name`
+fn axis(columns: &mut Vec<(String, Unit)>, values: &mut Vec<Vec<Scalar>>, name: &str) -> Result<Vec<Scalar>, CsvError>
+{
+    if columns.first().map(|(first, _)| first.as_str()) != Some(name)
+    {
+        return Err(CsvError::Invalid { line: 1, reason: format!("the first column must be {}", name) });
+    }
+    let points = values.remove(0);
+    columns.remove(0);
+
+    if points.windows(2).any(|p| p[1] <= p[0])
+    {
+        return Err(CsvError::Invalid { line: 1, reason: format!("{} must be increasing", name) });
+    }
+    Ok(points)
+}
+
+/// Evenly spaced points keep their step, to the precision written
+fn metadata(points: &[Scalar], format: &CsvFormat) -> Metadata
+{
+    let step = match (points.first(), points.last())
+    {
+        (Some(first), Some(last)) if points.len() > 1 => Some((last - first) / (points.len() - 1) as Scalar),
+        _ => None,
+    };
+    let tolerance = 10.0_f64.powi(1 - format.precision as i32);
+    let even = step.filter(|step| points.windows(2).all(|p| ((p[1] - p[0]) - step).abs() <= tolerance * p[1].abs().max(*step)));
+
+    let options = SimulationOptions { precision: format.precision, ..SimulationOptions::default() };
+    Metadata { method: None, step: even, options }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::{Netlist, Probe};
+    use crate::sim::ac::AcSimulation;
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn round_trip()
+    {
+        let netlist = "V1 1 0 sin(1000*t) AC 1\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+        let close = |a: Scalar, b: Scalar| (a - b).abs() <= 1e-8 * a.abs().max(1e-3);
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.set_probes(&["V(1,2)", "I(R1)"].map(|p| p.parse::<Probe>().unwrap())).unwrap();
+        let transient = simulation.simulate(1e-5, 50).unwrap();
+
+        for format in [CsvFormat::CSV, CsvFormat::TSV]
+        {
+            let format = CsvFormat { precision: 12, ..format };
+            let mut text = Vec::new();
+            write_transient(&mut text, &transient, &format).unwrap();
+            let header = String::from_utf8(text.clone()).unwrap().lines().next().unwrap().to_owned();
+            assert_eq!(header, ["time (s)", &quote("V(1,2) (V)", format.delimiter), "I(R1) (A)"].join(&format.delimiter.to_string()));
+
+            let read = read_transient(&text[..], &format).unwrap();
+            assert_eq!(read.signal("V(1,2)").unwrap().unit, Unit::VOLTS);
+            assert_eq!(read.signal("I(R1)").unwrap().kind, SignalKind::Current);
+            assert!(close(read.metadata().step.unwrap(), 1e-5));
+            assert!(read["I(R1)"].iter().zip(transient["I(R1)"].iter()).all(|(a, b)| close(*a, *b)));
+        }
+
+        let frequencies = [10.0, 100.0, 1000.0];
+        let ac = AcSimulation::new(&netlist).unwrap().simulate(&frequencies).unwrap();
+        for complex in [ComplexFormat::MagnitudePhase, ComplexFormat::RealImaginary]
+        {
+            let format = CsvFormat { precision: 12, complex, ..CsvFormat::CSV };
+            let mut text = Vec::new();
+            write_ac(&mut text, &ac, &format).unwrap();
+            let read = read_ac(&text[..], &format).unwrap();
+            assert_eq!(read.points(), &frequencies);
+            assert!(read["V_2"].iter().zip(ac["V_2"].iter()).all(|(a, b)| (a - b).norm() < 1e-9));
+        }
+
+        let values = BTreeMap::from([("V_2".to_owned(), vec![0.5, 1.0]), ("I_V1".to_owned(), vec![-1e-3, -2e-3])]);
+        let mut text = Vec::new();
+        write_dc(&mut text, "V1", &[1.0, 2.0], &values, &CsvFormat::default()).unwrap();
+        assert_eq!(String::from_utf8(text.clone()).unwrap(), "V1 (V),I_V1 (A),V_2 (V)\n1.00000e0,-1.00000e-3,5.00000e-1\n2.00000e0,-2.00000e-3,1.00000e0\n");
+        assert_eq!(read_dc(&text[..], &CsvFormat::default()).unwrap(), ("V1".to_owned(), vec![1.0, 2.0], values));
+
+        let short = BTreeMap::from([("V_2".to_owned(), vec![0.5])]);
+        let mut text = Vec::new();
+        let error = write_dc(&mut text, "V1", &[1.0, 2.0], &short, &CsvFormat::default()).unwrap_err();
+        assert_eq!((error.kind(), text.len()), (std::io::ErrorKind::InvalidInput, 0));
+
+        assert!(matches!(read_transient(&b"time (s),V_1 (V)\n0,1\n1e-3\n"[..], &CsvFormat::CSV), Err(CsvError::Invalid { line: 3, .. })));
+    }
+}
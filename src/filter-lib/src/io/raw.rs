@@ -0,0 +1,484 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use crate::la::Complex;
+use crate::netlist::{Scalar, SimulationOptions, Unit};
+use crate::sim::{Axis, Metadata, Signal, SignalKind, SimulationError, SimulationResults};
+
+/// SPICE3 raw files, as written by ngspice and LTspice
+/// and read by their waveform viewers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat
+{
+    Ascii,
+    /// Little endian doubles
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum RawError
+{
+    Io(std::io::Error),
+    Invalid{reason: String},
+}
+
+impl Display for RawError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            RawError::Io(error) => write!(f, "{}", error),
+            RawError::Invalid { reason } => write!(f, "invalid raw file - {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RawError
+{
+}
+
+impl From<std::io::Error> for RawError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        RawError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawData
+{
+    Real(SimulationResults),
+    Complex(SimulationResults<Complex<Scalar>>),
+}
+
+/// One analysis from a raw file
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawPlot
+{
+    pub title: String,
+    /// e.g. "Transient Analysis"
+    pub plotname: String,
+    /// Name of the first variable, e.g. "time"
+    /// or the swept source
+    pub scale: String,
+    pub data: RawData,
+}
+
+pub fn write_transient(out: &mut impl Write, title: &str, results: &SimulationResults, format: RawFormat) -> std::io::Result<()>
+{
+    let variables = variables("time", "time", results.signals());
+    write_plot(out, title, "Transient Analysis", false, &variables, results.len(),
+        |point, variable| Complex::new(value(results, point, variable, |v| *v), 0.0), format)
+}
+
+pub fn write_ac(out: &mut impl Write, title: &str, results: &SimulationResults<Complex<Scalar>>, format: RawFormat) -> std::io::Result<()>
+{
+    let variables = variables("frequency", "frequency", results.signals());
+    write_plot(out, title, "AC Analysis", true, &variables, results.len(),
+        |point, variable| match variable
+        {
+            0 => Complex::new(results.points()[point], 0.0),
+            _ => results.signals()[variable - 1].values[point],
+        }, format)
+}
+
+/// The swept source, then every result - each
+/// must have a value for every point of the sweep
+pub fn write_dc(out: &mut impl Write, title: &str, source: &str, sweep: &[Scalar], values: &BTreeMap<String, Vec<Scalar>>, format: RawFormat) -> std::io::Result<()>
+{
+    if let Some((name, column)) = values.iter().find(|(_, column)| column.len() != sweep.len())
+    {
+        let message = format!("\"{}\" has {} values but the sweep has {} points", name, column.len(), sweep.len());
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+    }
+
+    let mut variables = vec![(source.to_owned(), "voltage")];
+    variables.extend(values.keys().map(|name| (name.clone(), if name.starts_with("I_") { "current" } else { "voltage" })));
+    let columns = values.values().collect::<Vec<_>>();
+
+    write_plot(out, title, "DC transfer characteristic", false, &variables, sweep.len(),
+        |point, variable| Complex::new(if variable == 0 { sweep[point] } else { columns[variable - 1][point] }, 0.0), format)
+}
+
+/// Every plot in the file, in order
+pub fn read(input: &mut impl Read) -> Result<Vec<RawPlot>, RawError>
+{
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut plots = Vec::new();
+    let mut pos = 0;
+    loop
+    {
+        while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace() || (*b == 0))
+        {
+            pos += 1;
+        }
+        if pos >= bytes.len()
+        {
+            return Ok(plots);
+        }
+        plots.push(read_plot(&bytes, &mut pos)?);
+    }
+}
+
+fn variables<T>(scale: &str, kind: &'static str, signals: &[Signal<T>]) -> Vec<(String, &'static str)>
+{
+    let mut variables = vec![(scale.to_owned(), kind)];
+    variables.extend(signals.iter().map(|signal| (signal.name.clone(), kind_name(signal.kind))));
+    variables
+}
+
+fn value<T>(results: &SimulationResults<T>, point: usize, variable: usize, real: impl Fn(&T) -> Scalar) -> Scalar
+    where T: Clone
+{
+    match variable
+    {
+        0 => results.points()[point],
+        _ => real(&results.signals()[variable - 1].values[point]),
+    }
+}
+
+fn kind_name(kind: SignalKind) -> &'static str
+{
+    match kind
+    {
+        SignalKind::Voltage => "voltage",
+        SignalKind::Current => "current",
+        SignalKind::Power => "power",
+        SignalKind::Other => "notype",
+    }
+}
+
+fn unit_of(kind: &str) -> Unit
+{
+    match kind
+    {
+        "voltage" => Unit::VOLTS,
+        "current" => Unit::AMPS,
+        "power" => Unit::WATTS,
+        _ => Unit::NONE,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_plot(out: &mut impl Write, title: &str, plotname: &str, complex: bool, variables: &[(String, &str)], points: usize, value: impl Fn(usize, usize) -> Complex<Scalar>, format: RawFormat) -> std::io::Result<()>
+{
+    writeln!(out, "Title: {}", title)?;
+    writeln!(out, "Plotname: {}", plotname)?;
+    writeln!(out, "Flags: {}", if complex { "complex" } else { "real" })?;
+    writeln!(out, "No. Variables: {}", variables.len())?;
+    writeln!(out, "No. Points: {}", points)?;
+    writeln!(out, "Variables:")?;
+    for (i, (name, kind)) in variables.iter().enumerate()
+    {
+        writeln!(out, "\t{}\t{}\t{}", i, name, kind)?;
+    }
+
+    match format
+    {
+        RawFormat::Ascii =>
+        {
+            writeln!(out, "Values:")?;
+            for point in 0..points
+            {
+                for variable in 0..variables.len()
+                {
+                    // Shortest form that reads back exactly
+                    let v = value(point, variable);
+                    let text = if complex { format!("{:e},{:e}", v.re, v.im) } else { format!("{:e}", v.re) };
+                    match variable
+                    {
+                        0 => writeln!(out, " {}\t{}", point, text)?,
+                        _ => writeln!(out, "\t{}", text)?,
+                    }
+                }
+            }
+        },
+        RawFormat::Binary =>
+        {
+            writeln!(out, "Binary:")?;
+            for point in 0..points
+            {
+                for variable in 0..variables.len()
+                {
+                    let v = value(point, variable);
+                    out.write_all(&v.re.to_le_bytes())?;
+                    if complex
+                    {
+                        out.write_all(&v.im.to_le_bytes())?;
+                    }
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+/// The next header line - LTspice writes them in UTF-16
+fn next_line(bytes: &[u8], pos: &mut usize, wide: bool) -> Option<String>
+{
+    if *pos >= bytes.len()
+    {
+        return None;
+    }
+
+    let line = if wide
+    {
+        let units = bytes[*pos..].chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|u| *u != u16::from(b'\n'))
+            .collect::<Vec<_>>();
+        *pos += 2 * (units.len() + 1);
+        String::from_utf16_lossy(&units)
+    }
+    else
+    {
+        let length = bytes[*pos..].iter().position(|b| *b == b'\n').unwrap_or(bytes.len() - *pos);
+        let line = String::from_utf8_lossy(&bytes[*pos..*pos + length]).into_owned();
+        *pos += length + 1;
+        line
+    };
+    Some(line.trim_end_matches('\r').to_owned())
+}
+
+fn read_plot(bytes: &[u8], pos: &mut usize) -> Result<RawPlot, RawError>
+{
+    let invalid = |reason: &str| RawError::Invalid { reason: reason.to_owned() };
+    let wide = bytes.get(*pos + 1) == Some(&0);
+
+    let mut title = String::new();
+    let mut plotname = String::new();
+    let mut flags = Vec::new();
+    let mut count = None;
+    let mut points = None;
+    let mut variables: Vec<(String, String)> = Vec::new();
+    let mut in_variables = false;
+
+    let binary = loop
+    {
+        let line = next_line(bytes, pos, wide).ok_or_else(|| invalid("no values"))?;
+        let trimmed = line.trim();
+        if trimmed.is_empty()
+        {
+            continue;
+        }
+
+        // Variable lines are "index name type", with
+        // perhaps other parameters after
+        if in_variables && trimmed.starts_with(|c: char| c.is_ascii_digit())
+        {
+            let fields = trimmed.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 3
+            {
+                return Err(invalid(&format!("invalid variable \"{}\"", trimmed)));
+            }
+            variables.push((fields[1].to_owned(), fields[2].to_lowercase()));
+            continue;
+        }
+
+        let (key, value) = trimmed.split_once(':').ok_or_else(|| invalid(&format!("invalid header \"{}\"", trimmed)))?;
+        let value = value.trim();
+        in_variables = false;
+        match key.trim().to_lowercase().as_str()
+        {
+            "title" => title = value.to_owned(),
+            "plotname" => plotname = value.to_owned(),
+            "flags" => flags = value.split_whitespace().map(|f| f.to_lowercase()).collect(),
+            "no. variables" => count = Some(value.parse::<usize>().map_err(|_| invalid("invalid number of variables"))?),
+            "no. points" => points = Some(value.parse::<usize>().map_err(|_| invalid("invalid number of points"))?),
+            "variables" =>
+            {
+                in_variables = true;
+                let fields = value.split_whitespace().collect::<Vec<_>>();
+                if fields.len() >= 3
+                {
+                    variables.push((fields[1].to_owned(), fields[2].to_lowercase()));
+                }
+            },
+            "values" => break false,
+            "binary" => break true,
+            _ => (),
+        }
+    };
+
+    let points = points.ok_or_else(|| invalid("no number of points"))?;
+    if variables.is_empty() || count.is_some_and(|count| count != variables.len())
+    {
+        return Err(invalid("the variables don't match their number"));
+    }
+
+    let flag = |name: &str| flags.iter().any(|f| f == name);
+    if flag("fastaccess")
+    {
+        return Err(invalid("fastaccess files are not supported"));
+    }
+    let complex = flag("complex");
+
+    let values = if binary
+    {
+        // LTspice writes all but the scale of real plots as
+        // floats, unless the flags say "double"
+        let floats = !complex && flag("forward") && !flag("double");
+        let sizes = (0..variables.len())
+            .map(|v| if complex { 16 } else if floats && (v > 0) { 4 } else { 8 })
+            .collect::<Vec<usize>>();
+        let size = sizes.iter().sum::<usize>();
+
+        let available = points.min((bytes.len() - (*pos).min(bytes.len())) / size);
+        let mut values = Vec::with_capacity(available);
+        for _ in 0..available
+        {
+            let mut point = Vec::with_capacity(variables.len());
+            for size in sizes.iter()
+            {
+                let at = &bytes[*pos..*pos + size];
+                point.push(match size
+                {
+                    4 => Complex::new(f32::from_le_bytes(at.try_into().unwrap()) as Scalar, 0.0),
+                    8 => Complex::new(f64::from_le_bytes(at.try_into().unwrap()), 0.0),
+                    _ => Complex::new(f64::from_le_bytes(at[..8].try_into().unwrap()), f64::from_le_bytes(at[8..].try_into().unwrap())),
+                });
+                *pos += size;
+            }
+            values.push(point);
+        }
+        values
+    }
+    else
+    {
+        let parse = |text: &str| -> Result<Complex<Scalar>, RawError>
+        {
+            let number = |n: &str| n.trim().parse::<Scalar>().map_err(|_| invalid(&format!("invalid value \"{}\"", text)));
+            match text.split_once(',')
+            {
+                Some((re, im)) => Ok(Complex::new(number(re)?, number(im)?)),
+                None => Ok(Complex::new(number(text)?, 0.0)),
+            }
+        };
+
+        // A run that stopped early has fewer points than it says
+        let mut values = Vec::with_capacity(points);
+        'points: for _ in 0..points
+        {
+            let mut point = Vec::with_capacity(variables.len());
+            while point.len() < variables.len()
+            {
+                let Some(line) = next_line(bytes, pos, wide) else { break 'points };
+                if let Some(text) = line.split_whitespace().last()
+                {
+                    point.push(parse(text)?);
+                }
+            }
+            values.push(point);
+        }
+        values
+    };
+
+    let (scale, scale_kind) = variables.remove(0);
+    let axis = match scale_kind.as_str()
+    {
+        "time" => Axis::Time,
+        "frequency" => Axis::Frequency,
+        _ => Axis::Sweep,
+    };
+    // LTspice uses the sign of the time for its own purposes
+    let axis_points = values.iter()
+        .map(|point| if axis == Axis::Time { point[0].re.abs() } else { point[0].re })
+        .collect::<Vec<_>>();
+
+    let step = match (axis, axis_points.first(), axis_points.last())
+    {
+        (Axis::Frequency, _, _) => None,
+        (_, Some(first), Some(last)) if axis_points.len() > 1 =>
+        {
+            let step = (last - first) / (axis_points.len() - 1) as Scalar;
+            Some(step).filter(|step| axis_points.windows(2).all(|p| ((p[1] - p[0]) - step).abs() <= 1e-9 * step.abs()))
+        },
+        _ => None,
+    };
+    let metadata = Metadata { method: None, step, options: SimulationOptions::default() };
+
+    let signals = variables.iter().enumerate()
+        .map(|(i, (name, kind))|
+        {
+            let unit = unit_of(kind);
+            Signal { name: name.clone(), unit, kind: SignalKind::of(unit), values: values.iter().map(|point| point[i + 1]).collect() }
+        })
+        .collect::<Vec<_>>();
+
+    let invalid = |error: SimulationError| RawError::Invalid { reason: error.to_string() };
+    let data = if complex
+    {
+        RawData::Complex(SimulationResults::new(axis, axis_points, signals, metadata).map_err(invalid)?)
+    }
+    else
+    {
+        let signals = signals.iter().map(|signal| signal.with_values(signal.values.iter().map(|v| v.re).collect())).collect();
+        RawData::Real(SimulationResults::new(axis, axis_points, signals, metadata).map_err(invalid)?)
+    };
+
+    Ok(RawPlot { title, plotname, scale, data })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::{Netlist, Probe};
+    use crate::sim::ac::AcSimulation;
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn round_trip()
+    {
+        let netlist = "V1 1 0 sin(1000*t) AC 1\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.set_probes(&["V(1,2)", "I(R1)"].map(|p| p.parse::<Probe>().unwrap())).unwrap();
+        let transient = simulation.simulate(1e-5, 50).unwrap();
+        let ac = AcSimulation::new(&netlist).unwrap().simulate(&[10.0, 100.0, 1000.0]).unwrap();
+        let values = BTreeMap::from([("V_2".to_owned(), vec![0.5, 1.0]), ("I_V1".to_owned(), vec![-1e-3, -2e-3])]);
+
+        for format in [RawFormat::Ascii, RawFormat::Binary]
+        {
+            // Several plots can follow each other in one file
+            let mut file = Vec::new();
+            write_transient(&mut file, "rc", &transient, format).unwrap();
+            write_ac(&mut file, "rc", &ac, format).unwrap();
+            write_dc(&mut file, "rc", "V1", &[1.0, 2.0], &values, format).unwrap();
+            let error = write_dc(&mut Vec::new(), "rc", "V1", &[1.0, 2.0, 3.0], &values, format).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+            let plots = read(&mut &file[..]).unwrap();
+            assert_eq!(plots.len(), 3);
+            assert_eq!((plots[0].title.as_str(), plots[0].plotname.as_str(), plots[0].scale.as_str()), ("rc", "Transient Analysis", "time"));
+
+            let RawData::Real(read_transient) = &plots[0].data else { panic!("transient is real") };
+            assert_eq!(read_transient.points(), transient.points());
+            assert_eq!(read_transient["V(1,2)"], transient["V(1,2)"]);
+            assert_eq!(read_transient.signal("I(R1)").unwrap().kind, SignalKind::Current);
+            assert!((read_transient.metadata().step.unwrap() - 1e-5).abs() < 1e-15);
+
+            let RawData::Complex(read_ac) = &plots[1].data else { panic!("AC is complex") };
+            assert_eq!((read_ac.axis(), read_ac.points()), (Axis::Frequency, ac.points()));
+            assert_eq!(read_ac["V_2"], ac["V_2"]);
+
+            let RawData::Real(read_dc) = &plots[2].data else { panic!("DC is real") };
+            assert_eq!((read_dc.axis(), plots[2].scale.as_str()), (Axis::Sweep, "V1"));
+            assert_eq!(read_dc["I_V1"], values["I_V1"]);
+        }
+
+        // As ngspice writes them
+        let ngspice = "Title: * rc\nDate: Sat Oct 17 12:00:00  2026\nPlotname: AC Analysis\nFlags: complex\nNo. Variables: 2\nNo. Points: 2\n\
+            Variables:\n\t0\tfrequency\tfrequency grid=3\n\t1\tv(2)\tvoltage\nValues:\n 0\t1.000000000000000e+01,0.000000000000000e+00\n\t9.960e-01,-6.259e-02\n\n\
+             1\t1.000000000000000e+02,0.000000000000000e+00\n\t7.170e-01,-4.505e-01\n\n";
+        let plots = read(&mut ngspice.as_bytes()).unwrap();
+        let RawData::Complex(results) = &plots[0].data else { panic!("AC is complex") };
+        assert_eq!(results["v(2)"][1], Complex::new(7.170e-01, -4.505e-01));
+
+        assert!(read(&mut &b"Title: x\nNo. Points: 1\nValues:\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use crate::netlist::Scalar;
+use crate::sim::SimulationResults;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat
+{
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+}
+
+/// How volts map to samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WavScaling
+{
+    /// The loudest sample of all the channels at full scale
+    Normalise,
+    /// Volts at full scale - PCM clips beyond it
+    Fixed(Scalar),
+}
+
+#[derive(Debug)]
+pub enum WavError
+{
+    Io(std::io::Error),
+    Invalid{reason: String},
+}
+
+impl Display for WavError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            WavError::Io(error) => write!(f, "{}", error),
+            WavError::Invalid { reason } => write!(f, "invalid WAV - {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WavError
+{
+}
+
+impl From<std::io::Error> for WavError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        WavError::Io(error)
+    }
+}
+
+/// Audio with each channel's samples between
+/// -1.0 and 1.0 at full scale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav
+{
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<Scalar>>,
+}
+
+impl Wav
+{
+    /// Samples in each channel
+    pub fn len(&self) -> usize
+    {
+        self.channels.first().map_or(0, |channel| channel.len())
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+/// PCM (8 to 32 bits) or float WAV files
+pub fn read(input: &mut impl Read) -> Result<Wav, WavError>
+{
+    let invalid = |reason: &str| WavError::Invalid { reason: reason.to_owned() };
+    let u16_at = |bytes: &[u8], at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at = |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    if (bytes.len() < 12) || (&bytes[0..4] != b"RIFF") || (&bytes[8..12] != b"WAVE")
+    {
+        return Err(invalid("not a RIFF WAVE file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len()
+    {
+        // A recording that was cut short can have
+        // less data than its chunk says
+        let size = u32_at(&bytes, pos + 4) as usize;
+        let body = &bytes[pos + 8..(pos + 8 + size).min(bytes.len())];
+        match &bytes[pos..pos + 4]
+        {
+            b"fmt " => format = Some(body),
+            b"data" => data = Some(body),
+            _ => (),
+        }
+        pos += 8 + size + (size & 1);
+    }
+
+    let format = format.filter(|f| f.len() >= 16).ok_or_else(|| invalid("no format chunk"))?;
+    let data = data.ok_or_else(|| invalid("no data chunk"))?;
+
+    let mut tag = u16_at(format, 0);
+    let channels = u16_at(format, 2) as usize;
+    let sample_rate = u32_at(format, 4);
+    let bits = u16_at(format, 14);
+    if (tag == 0xFFFE) && (format.len() >= 26)
+    {
+        // WAVE_FORMAT_EXTENSIBLE - the sub-format
+        // GUID starts with the actual tag
+        tag = u16_at(format, 24);
+    }
+
+    match (tag, bits)
+    {
+        (1, 8 | 16 | 24 | 32) | (3, 32 | 64) => (),
+        _ => return Err(invalid(&format!("unsupported format {} with {} bits", tag, bits))),
+    }
+    if (channels == 0) || (sample_rate == 0)
+    {
+        return Err(invalid("no channels or sample rate"));
+    }
+
+    let size = (bits / 8) as usize;
+    let sample = |at: &[u8]| -> Scalar
+    {
+        match (tag, bits)
+        {
+            (1, 8) => (at[0] as Scalar - 128.0) / 128.0,
+            (1, 16) => i16::from_le_bytes([at[0], at[1]]) as Scalar / 32768.0,
+            (1, 24) => (i32::from_le_bytes([0, at[0], at[1], at[2]]) >> 8) as Scalar / 8388608.0,
+            (1, _) => i32::from_le_bytes(at.try_into().unwrap()) as Scalar / 2147483648.0,
+            (_, 32) => f32::from_le_bytes(at.try_into().unwrap()) as Scalar,
+            _ => f64::from_le_bytes(at.try_into().unwrap()),
+        }
+    };
+
+    let mut result = vec![Vec::with_capacity(data.len() / (size * channels)); channels];
+    for frame in data.chunks_exact(size * channels)
+    {
+        for (channel, at) in result.iter_mut().zip(frame.chunks_exact(size))
+        {
+            channel.push(sample(at));
+        }
+    }
+
+    Ok(Wav { sample_rate, channels: result })
+}
+
+/// PCM samples beyond full scale are clipped
+pub fn write(out: &mut impl Write, wav: &Wav, format: SampleFormat) -> Result<(), WavError>
+{
+    let (tag, bits): (u16, u16) = match format
+    {
+        SampleFormat::Pcm16 => (1, 16),
+        SampleFormat::Pcm24 => (1, 24),
+        SampleFormat::Pcm32 => (1, 32),
+        SampleFormat::Float32 => (3, 32),
+    };
+
+    if wav.channels.iter().any(|channel| channel.len() != wav.len())
+    {
+        return Err(WavError::Invalid { reason: "every channel needs the same number of samples".to_owned() });
+    }
+    let too_big = |what: &str| WavError::Invalid { reason: format!("too {} for a WAV file", what) };
+    let channels = u16::try_from(wav.channels.len()).map_err(|_| too_big("many channels"))?;
+    let block = channels.checked_mul(bits / 8).ok_or_else(|| too_big("many channels"))?;
+    let byte_rate = wav.sample_rate.checked_mul(block as u32).ok_or_else(|| too_big("fast"))?;
+    let size = u32::try_from(wav.len() * block as usize).ok()
+        .filter(|size| *size <= u32::MAX - 44)
+        .ok_or_else(|| too_big("long"))?;
+    let pad = size & 1;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + size + pad).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&tag.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&wav.sample_rate.to_le_bytes())?;
+    out.write_all(&byte_rate.to_le_bytes())?;
+    out.write_all(&block.to_le_bytes())?;
+    out.write_all(&bits.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&size.to_le_bytes())?;
+    for i in 0..wav.len()
+    {
+        for channel in wav.channels.iter()
+        {
+            let value = channel[i];
+            let clipped = value.clamp(-1.0, 1.0);
+            match format
+            {
+                SampleFormat::Pcm16 => out.write_all(&((clipped * 32767.0).round() as i16).to_le_bytes())?,
+                SampleFormat::Pcm24 => out.write_all(&((clipped * 8388607.0).round() as i32).to_le_bytes()[..3])?,
+                SampleFormat::Pcm32 => out.write_all(&((clipped * 2147483647.0).round() as i32).to_le_bytes())?,
+                SampleFormat::Float32 => out.write_all(&(value as f32).to_le_bytes())?,
+            }
+        }
+    }
+    if pad != 0
+    {
+        out.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Each named signal as a channel - the results must be
+/// evenly spaced, so resample adaptive results first
+pub fn write_signals(out: &mut impl Write, results: &SimulationResults, names: &[&str], format: SampleFormat, scaling: WavScaling) -> Result<(), WavError>
+{
+    let invalid = |reason: String| WavError::Invalid { reason };
+
+    let step = results.metadata().step.ok_or_else(|| invalid("the results must be evenly spaced - resample them first".to_owned()))?;
+    let sample_rate = (1.0 / step).round();
+    if !(1.0..=(u32::MAX as Scalar)).contains(&sample_rate)
+    {
+        return Err(invalid(format!("a step of {}s isn't an audio sample rate", step)));
+    }
+
+    let signals = names.iter()
+        .map(|name| results.get(name).ok_or_else(|| invalid(format!("no signal \"{}\"", name))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let full_scale = match scaling
+    {
+        WavScaling::Normalise => signals.iter().flat_map(|values| values.iter()).fold(0.0, |peak: Scalar, v| peak.max(v.abs())),
+        WavScaling::Fixed(volts) if volts > 0.0 => volts,
+        WavScaling::Fixed(volts) => return Err(invalid(format!("full scale of {}V must be positive", volts))),
+    };
+    // Silence stays silent
+    let full_scale = if full_scale > 0.0 { full_scale } else { 1.0 };
+
+    let channels = signals.iter().map(|values| values.iter().map(|v| v / full_scale).collect()).collect();
+    write(out, &Wav { sample_rate: sample_rate as u32, channels }, format)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::{Netlist, Probe};
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn round_trip_and_source()
+    {
+        let wav = Wav { sample_rate: 8000, channels: vec![vec![0.0, 0.5, -0.25, 1.0], vec![0.125, -1.0, 0.75, 0.0]] };
+        for (format, tolerance) in [(SampleFormat::Pcm16, 1e-4), (SampleFormat::Pcm24, 1e-6), (SampleFormat::Pcm32, 1e-9), (SampleFormat::Float32, 0.0)]
+        {
+            let mut file = Vec::new();
+            write(&mut file, &wav, format).unwrap();
+            let read = read(&mut &file[..]).unwrap();
+            assert_eq!((read.sample_rate, read.channels.len(), read.len()), (8000, 2, 4));
+            assert!(read.channels.iter().flatten().zip(wav.channels.iter().flatten()).all(|(a, b)| (a - b).abs() <= tolerance));
+        }
+        assert!(read(&mut &b"RIFF\0\0\0\0WAVE"[..]).is_err());
+        let fast = Wav { sample_rate: u32::MAX, channels: vec![vec![0.0]; 2] };
+        assert!(matches!(write(&mut Vec::new(), &fast, SampleFormat::Pcm16), Err(WavError::Invalid { .. })));
+        let wide = Wav { sample_rate: 8000, channels: vec![Vec::new(); 1 << 15] };
+        assert!(matches!(write(&mut Vec::new(), &wide, SampleFormat::Pcm32), Err(WavError::Invalid { .. })));
+
+        // Play the second channel through an RC at four
+        // times the file's rate
+        let path = std::env::temp_dir().join(format!("filter-wav-source-{}.wav", std::process::id()));
+        write(&mut std::fs::File::create(&path).unwrap(), &wav, SampleFormat::Float32).unwrap();
+        let netlist = format!("V1 1 0 WAV(\"{}\", chan=1, scale=2)\nR1 1 2 1k\nC1 2 0 1n", path.display())
+            .parse::<Netlist>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.set_probes(&["V(1)", "V(2)"].map(|p| p.parse::<Probe>().unwrap())).unwrap();
+        let results = simulation.simulate(1.0 / 32000.0, 16).unwrap();
+        let input = &results["V(1)"];
+        assert!((input[1] - 2.0 * (0.125 - 0.25 * 1.125)).abs() < 1e-6);
+        assert!((input[4] + 2.0).abs() < 1e-6);
+        assert!(input[15].abs() < 1e-6);
+
+        let mut file = Vec::new();
+        write_signals(&mut file, &results, &["V(1)", "V(2)"], SampleFormat::Pcm16, WavScaling::Normalise).unwrap();
+        let read = read(&mut &file[..]).unwrap();
+        assert_eq!((read.sample_rate, read.len()), (32000, 16));
+        assert!((read.channels[0][4] + 1.0).abs() < 1e-4);
+
+        assert!(write_signals(&mut Vec::new(), &results, &["V(3)"], SampleFormat::Pcm16, WavScaling::Fixed(1.0)).is_err());
+        assert!("V1 1 0 WAV(\"/no/such/file.wav\")".parse::<Netlist>().is_err());
+        let error = format!("V1 1 0 WAV(\"{}, chan=1)", path.display()).parse::<Netlist>().unwrap_err();
+        assert!(format!("{:?}", error).contains("Unterminated quoted string"));
+    }
+}
@@ -1,9 +1,15 @@
 use std::collections::{BTreeMap, HashMap};
-use nalgebra::{DMatrix, DVector};
+use nalgebra::{ComplexField, DMatrix, DVector, DVectorViewMut, Dyn};
+use nalgebra::linalg::LU;
+
+mod sparse;
+
+pub use nalgebra::Complex;
+pub use sparse::SparseSolver;
 
 #[derive(Clone, Copy)]
 pub struct EquationIndex(usize);
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct VariableIndex(usize);
 
 impl EquationIndex
@@ -16,12 +22,36 @@ impl EquationIndex
 
 impl VariableIndex
 {
+    pub fn from_index(i: usize) -> Self
+    {
+        VariableIndex(i)
+    }
+
     pub fn into_index(&self) -> usize
     {
         self.0
     }
 }
 
+/// Which solver a simulation uses for its linear systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SolverBackend
+{
+    /// Dense LU every solve - best for small circuits
+    #[default]
+    Dense,
+    /// Sparse LU, reusing the pivot order between solves
+    Sparse,
+}
+
+/// Coefficients and constants of a system being filled
+pub trait Matrix<T = f64>
+{
+    fn dim(&self) -> usize;
+    fn coef(&mut self, eq: EquationIndex, var: VariableIndex) -> &mut T;
+    fn constant(&mut self, eq: EquationIndex) -> &mut T;
+}
+
 pub struct System
 {
     variables_in_order: Vec<String>,
@@ -34,6 +64,16 @@ impl System
         Solver::new(self.variables_in_order.len())
     }
 
+    pub fn new_complex_solver(&self) -> Solver<Complex<f64>>
+    {
+        Solver::new(self.variables_in_order.len())
+    }
+
+    pub fn new_sparse_solver(&self) -> SparseSolver
+    {
+        SparseSolver::new(self.variables_in_order.len())
+    }
+
     pub fn dim(&self) -> usize
     {
         self.variables_in_order.len()
@@ -77,13 +117,15 @@ impl System
     }
 }
 
-pub struct Solver
+pub struct Solver<T = f64>
+    where T: ComplexField
 {
-    a: DMatrix<f64>,
-    b: DVector<f64>,
+    a: DMatrix<T>,
+    b: DVector<T>,
 }
 
-impl Solver
+impl<T> Solver<T>
+    where T: ComplexField
 {
     pub fn new(dim: usize) -> Self
     {
@@ -97,21 +139,131 @@ impl Solver
         self.b.len()
     }
 
-    pub fn coef<'a>(&'a mut self, eq: EquationIndex, var: VariableIndex) -> &'a mut f64
+    #[allow(clippy::needless_lifetimes)]
+    pub fn coef<'a>(&'a mut self, eq: EquationIndex, var: VariableIndex) -> &'a mut T
     {
         &mut self.a[(eq.0, var.0)]
     }
 
-    pub fn constant<'a>(&'a mut self, eq: EquationIndex) -> &'a mut f64
+    #[allow(clippy::needless_lifetimes)]
+    pub fn constant<'a>(&'a mut self, eq: EquationIndex) -> &'a mut T
     {
         &mut self.b[eq.0]
     }
 
-    pub fn solve(self) -> Option<Vec<f64>>
+    pub fn solve(self) -> Option<Vec<T>>
     {
         let lu = self.a.lu();
         lu.solve(&self.b).map(|mut m| m.as_mut_slice().into())
     }
+
+    /// Factors the coefficients so the same system can be
+    /// solved for many sets of constants.
+    /// Returns None if the system is singular.
+    pub fn factor(self) -> Option<LuFactors<T>>
+    {
+        let lu = self.a.lu();
+        if lu.is_invertible()
+        {
+            Some(LuFactors { lu })
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Solves the transposed system A' x = b.
+    ///
+    /// With b selecting a single output, x gives the
+    /// sensitivity of that output to every equation's
+    /// constant - i.e. the adjoint network.
+    pub fn solve_transposed(self) -> Option<Vec<T>>
+    {
+        let lu = self.a.transpose().lu();
+        lu.solve(&self.b).map(|mut m| m.as_mut_slice().into())
+    }
+}
+
+impl Solver<f64>
+{
+    /// Variables and equations involved in a singular system -
+    /// those with a significant part in the null space of the
+    /// coefficients, or in the null space of their transpose
+    /// (equation i is reported as variable i).
+    /// Empty if the system isn't singular.
+    pub fn singular_variables(&self) -> Vec<VariableIndex>
+    {
+        let svd = self.a.clone().svd(true, true);
+        let largest = svd.singular_values.max();
+        let tolerance = largest.max(1.0) * (self.dim() as f64) * f64::EPSILON * 1e3;
+
+        let (Some(u), Some(v_t)) = (&svd.u, &svd.v_t) else { return Vec::new(); };
+        let mut involved = vec![false; self.dim()];
+
+        for (i, singular) in svd.singular_values.iter().enumerate()
+        {
+            if *singular > tolerance
+            {
+                continue;
+            }
+            for j in 0..self.dim()
+            {
+                if (v_t[(i, j)].abs() > 1e-3) || (u[(j, i)].abs() > 1e-3)
+                {
+                    involved[j] = true;
+                }
+            }
+        }
+
+        involved.iter().enumerate()
+            .filter(|(_, involved)| **involved)
+            .map(|(i, _)| VariableIndex(i))
+            .collect()
+    }
+}
+
+pub struct LuFactors<T = f64>
+    where T: ComplexField
+{
+    lu: LU<T, Dyn, Dyn>,
+}
+
+impl<T> LuFactors<T>
+    where T: ComplexField
+{
+    pub fn solve(&self, constants: &[T]) -> Option<Vec<T>>
+    {
+        let b = DVector::from_column_slice(constants);
+        self.lu.solve(&b).map(|mut m| m.as_mut_slice().into())
+    }
+
+    /// Replaces `values`, the constants, with the solution
+    /// without allocating. Returns false if singular.
+    pub fn solve_in_place(&self, values: &mut [T]) -> bool
+    {
+        let len = values.len();
+        self.lu.solve_mut(&mut DVectorViewMut::from_slice(values, len))
+    }
+}
+
+impl<T> Matrix<T> for Solver<T>
+    where T: ComplexField
+{
+    fn dim(&self) -> usize
+    {
+        Solver::dim(self)
+    }
+
+    fn coef(&mut self, eq: EquationIndex, var: VariableIndex) -> &mut T
+    {
+        Solver::coef(self, eq, var)
+    }
+
+    fn constant(&mut self, eq: EquationIndex) -> &mut T
+    {
+        Solver::constant(self, eq)
+    }
 }
 
 pub struct Builder
@@ -123,6 +275,7 @@ pub struct Builder
 
 impl Builder
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         Builder
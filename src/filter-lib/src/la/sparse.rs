@@ -0,0 +1,431 @@
+use nalgebra::ComplexField;
+use super::{EquationIndex, Matrix, VariableIndex};
+
+/// Smallest pivot accepted, relative to the
+/// largest entry in its column
+const PIVOT_TOLERANCE: f64 = 1e-3;
+/// A refactorisation whose pivots drop below this,
+/// relative to their column, is redone with new pivots
+const REFACTOR_TOLERANCE: f64 = 1e-10;
+
+/// Sparse LU solver for systems that are solved
+/// many times with the same pattern of coefficients.
+///
+/// The first solve picks pivots (Markowitz ordering
+/// with threshold pivoting) and records every operation
+/// of the elimination, including fill-in. Later solves
+/// just replay those operations on the new values,
+/// only picking new pivots if the pattern grows or a
+/// pivot becomes too small.
+pub struct SparseSolver<T = f64>
+    where T: ComplexField<RealField = f64>
+{
+    /// Per equation - (variable, index into values), sorted by variable
+    rows: Vec<Vec<(usize, usize)>>,
+    values: Vec<T>,
+    constants: Vec<T>,
+    factorisation: Option<Factorisation>,
+    /// Matrix values followed by fill-ins, factored in place
+    work: Vec<T>,
+    /// Largest magnitude in each column, for refactoring
+    column_max: Vec<f64>,
+    /// Times new pivots have been picked
+    pivotings: usize,
+}
+
+/// Pivot sequence and elimination operations
+struct Factorisation
+{
+    pivots: Vec<Pivot>,
+    /// Number of fill-in entries after the matrix values
+    fill: usize,
+}
+
+struct Pivot
+{
+    row: usize,
+    col: usize,
+    /// Index of the pivot in the work values
+    index: usize,
+    /// Rows eliminated by this pivot - (row, index of the multiplier)
+    lower: Vec<(usize, usize)>,
+    /// Rest of the pivot row - (col, index)
+    upper: Vec<(usize, usize)>,
+    /// Entry updated for each lower/upper pair, row major
+    updates: Vec<usize>,
+}
+
+impl<T> SparseSolver<T>
+    where T: ComplexField<RealField = f64>
+{
+    pub fn new(dim: usize) -> Self
+    {
+        SparseSolver
+        {
+            rows: vec![Vec::new(); dim],
+            values: Vec::new(),
+            constants: vec![T::zero(); dim],
+            factorisation: None,
+            work: Vec::new(),
+            column_max: vec![0.0; dim],
+            pivotings: 0,
+        }
+    }
+
+    /// Zeros every coefficient and constant, keeping
+    /// the pattern and pivots for the next solve
+    pub fn clear(&mut self)
+    {
+        self.values.iter_mut().for_each(|v| *v = T::zero());
+        self.constants.iter_mut().for_each(|v| *v = T::zero());
+    }
+
+    /// Number of coefficients in the pattern
+    pub fn entries(&self) -> usize
+    {
+        self.values.len()
+    }
+
+    /// Times new pivots have been picked, rather than
+    /// the last ones reused
+    pub fn pivotings(&self) -> usize
+    {
+        self.pivotings
+    }
+
+    pub fn solve(&mut self) -> Option<Vec<T>>
+    {
+        if !self.factor()
+        {
+            return None;
+        }
+        self.solve_factored(&self.constants)
+    }
+
+    /// Factors the current coefficients, reusing the pivots
+    /// if possible. Returns false if the system is singular.
+    pub fn factor(&mut self) -> bool
+    {
+        if !self.refactor()
+        {
+            self.pivotings += 1;
+            self.factorisation = self.factor_with_pivoting();
+        }
+        self.factorisation.is_some()
+    }
+
+    /// Solves for `constants` with the last factorisation,
+    /// which is kept until the coefficients are factored again
+    pub fn solve_factored(&self, constants: &[T]) -> Option<Vec<T>>
+    {
+        let mut b = constants.to_vec();
+        let mut x = vec![T::zero(); self.dim()];
+
+        if self.solve_factored_into(&mut b, &mut x) && x.iter().all(|v| v.clone().is_finite())
+        {
+            Some(x)
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Solves for the constants in `b`, which are overwritten,
+    /// into `x` without allocating. Returns false if there's
+    /// no factorisation.
+    pub fn solve_factored_into(&self, b: &mut [T], x: &mut [T]) -> bool
+    {
+        let Some(factorisation) = self.factorisation.as_ref() else { return false; };
+        let work = &self.work;
+
+        // Forward substitution through L
+
+        for pivot in factorisation.pivots.iter()
+        {
+            let value = b[pivot.row].clone();
+            for (row, index) in pivot.lower.iter()
+            {
+                b[*row] -= work[*index].clone() * value.clone();
+            }
+        }
+
+        // Back substitution through U
+
+        for pivot in factorisation.pivots.iter().rev()
+        {
+            let mut value = b[pivot.row].clone();
+            for (col, index) in pivot.upper.iter()
+            {
+                value -= work[*index].clone() * x[*col].clone();
+            }
+            x[pivot.col] = value / work[pivot.index].clone();
+        }
+
+        true
+    }
+
+    /// Replays the existing factorisation on the current values.
+    /// Returns false if there isn't one, or its pivots are no
+    /// longer good enough.
+    fn refactor(&mut self) -> bool
+    {
+        self.update_column_max();
+        let Some(factorisation) = &self.factorisation else { return false; };
+
+        self.work.clear();
+        self.work.extend(self.values.iter().cloned());
+        self.work.extend((0..factorisation.fill).map(|_| T::zero()));
+
+        let column_max = &self.column_max;
+        let work = &mut self.work;
+
+        for pivot in factorisation.pivots.iter()
+        {
+            // Written so a NaN pivot fails too
+            let value = work[pivot.index].clone();
+            let magnitude = value.clone().modulus();
+            if magnitude.is_nan() || (magnitude <= REFACTOR_TOLERANCE * column_max[pivot.col])
+            {
+                return false;
+            }
+
+            for (i, (_, lower)) in pivot.lower.iter().enumerate()
+            {
+                let multiplier = work[*lower].clone() / value.clone();
+                work[*lower] = multiplier.clone();
+
+                for ((_, upper), target) in pivot.upper.iter().zip(pivot.updates[i * pivot.upper.len()..].iter())
+                {
+                    let update = multiplier.clone() * work[*upper].clone();
+                    work[*target] -= update;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Gaussian elimination choosing each pivot to keep
+    /// the fill-in low - the smallest Markowitz count
+    /// (r - 1)(c - 1) amongst entries large enough to be
+    /// numerically stable. Every operation is recorded.
+    fn factor_with_pivoting(&mut self) -> Option<Factorisation>
+    {
+        let dim = self.dim();
+
+        self.work.clear();
+        self.work.extend(self.values.iter().cloned());
+
+        // Active entries of the reduced matrix - by row (sorted by
+        // col) and the rows that have an entry in each column
+
+        let mut rows = self.rows.clone();
+        let mut cols = vec![Vec::new(); dim];
+        for (row, entries) in rows.iter().enumerate()
+        {
+            for (col, _) in entries.iter()
+            {
+                cols[*col].push(row);
+            }
+        }
+
+        let mut col_done = vec![false; dim];
+        let mut pivots = Vec::with_capacity(dim);
+
+        for _ in 0..dim
+        {
+            // Choose the pivot
+
+            let mut best: Option<(usize, usize, usize, usize)> = None;
+
+            for (col, col_rows) in cols.iter().enumerate()
+            {
+                if col_done[col]
+                {
+                    continue;
+                }
+
+                let magnitude = |row: usize| rows[row].iter()
+                    .find(|(c, _)| *c == col)
+                    .map(|(_, index)| self.work[*index].clone().modulus())
+                    .unwrap_or(0.0);
+
+                let largest = col_rows.iter().map(|r| magnitude(*r)).fold(0.0, f64::max);
+                if largest == 0.0
+                {
+                    continue;
+                }
+
+                for row in col_rows.iter()
+                {
+                    if magnitude(*row) < PIVOT_TOLERANCE * largest
+                    {
+                        continue;
+                    }
+
+                    let count = (rows[*row].len() - 1) * (col_rows.len() - 1);
+                    let index = rows[*row].iter().find(|(c, _)| *c == col).unwrap().1;
+
+                    if best.map(|b| count < b.2).unwrap_or(true)
+                    {
+                        best = Some((*row, col, count, index));
+                    }
+                }
+            }
+
+            let (row, col, _, index) = best?;
+
+            // Eliminate the column from every other active row
+
+            let upper = rows[row].iter().copied().filter(|(c, _)| *c != col).collect::<Vec<_>>();
+            let mut lower = Vec::new();
+            let mut updates = Vec::new();
+
+            for other in cols[col].clone()
+            {
+                if other == row
+                {
+                    continue;
+                }
+
+                let position = rows[other].iter().position(|(c, _)| *c == col).unwrap();
+                let (_, lower_index) = rows[other].remove(position);
+
+                let multiplier = self.work[lower_index].clone() / self.work[index].clone();
+                self.work[lower_index] = multiplier.clone();
+
+                for (upper_col, upper_index) in upper.iter()
+                {
+                    let target = match rows[other].binary_search_by_key(upper_col, |(c, _)| *c)
+                    {
+                        Ok(position) => rows[other][position].1,
+                        Err(position) =>
+                        {
+                            // Fill-in
+                            let target = self.work.len();
+                            self.work.push(T::zero());
+                            rows[other].insert(position, (*upper_col, target));
+                            cols[*upper_col].push(other);
+                            target
+                        },
+                    };
+
+                    let update = multiplier.clone() * self.work[*upper_index].clone();
+                    self.work[target] -= update;
+                    updates.push(target);
+                }
+
+                lower.push((other, lower_index));
+            }
+
+            // Remove the pivot row and column from the active matrix
+
+            for (upper_col, _) in upper.iter()
+            {
+                cols[*upper_col].retain(|r| *r != row);
+            }
+            rows[row].clear();
+            cols[col].clear();
+            col_done[col] = true;
+
+            pivots.push(Pivot { row, col, index, lower, upper, updates });
+        }
+
+        let fill = self.work.len() - self.values.len();
+        Some(Factorisation { pivots, fill })
+    }
+
+    fn update_column_max(&mut self)
+    {
+        self.column_max.iter_mut().for_each(|m| *m = 0.0);
+        for entries in self.rows.iter()
+        {
+            for (col, index) in entries.iter()
+            {
+                self.column_max[*col] = self.column_max[*col].max(self.values[*index].clone().modulus());
+            }
+        }
+    }
+}
+
+impl<T> Matrix<T> for SparseSolver<T>
+    where T: ComplexField<RealField = f64>
+{
+    fn dim(&self) -> usize
+    {
+        self.constants.len()
+    }
+
+    fn coef(&mut self, eq: EquationIndex, var: VariableIndex) -> &mut T
+    {
+        let row = &mut self.rows[eq.0];
+
+        let index = match row.binary_search_by_key(&var.0, |(c, _)| *c)
+        {
+            Ok(position) => row[position].1,
+            Err(position) =>
+            {
+                // New entry - the pattern has changed,
+                // so the pivots need choosing again
+                let index = self.values.len();
+                self.values.push(T::zero());
+                row.insert(position, (var.0, index));
+                self.factorisation = None;
+                index
+            },
+        };
+
+        &mut self.values[index]
+    }
+
+    fn constant(&mut self, eq: EquationIndex) -> &mut T
+    {
+        &mut self.constants[eq.0]
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::la::Solver;
+
+    fn fill<M: Matrix>(matrix: &mut M, scale: f64)
+    {
+        // Tridiagonal-ish system with a zero on the diagonal
+        // and an off-band coupling, so pivoting is needed
+        let entries = [(0, 1, 2.0), (0, 3, 1.0), (1, 0, 1.0), (1, 1, 4.0), (1, 2, -1.0),
+                       (2, 1, -1.0), (2, 2, 4.0), (2, 3, -1.0), (3, 0, 3.0), (3, 2, -1.0), (3, 3, 4.0)];
+        for (eq, var, value) in entries
+        {
+            *matrix.coef(EquationIndex::from_index(eq), VariableIndex::from_index(var)) = value * scale;
+        }
+        for eq in 0..4
+        {
+            *matrix.constant(EquationIndex::from_index(eq)) = (eq + 1) as f64;
+        }
+    }
+
+    #[test]
+    fn matches_dense()
+    {
+        let mut sparse = SparseSolver::new(4);
+
+        for scale in [1.0, 2.0, -0.5]
+        {
+            let mut dense = Solver::new(4);
+            fill(&mut dense, scale);
+            let expected = dense.solve().unwrap();
+
+            sparse.clear();
+            fill(&mut sparse, scale);
+            let actual = sparse.solve().unwrap();
+
+            for (a, e) in actual.iter().zip(expected.iter())
+            {
+                assert!((a - e).abs() < 1e-12);
+            }
+        }
+    }
+}
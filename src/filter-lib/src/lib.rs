@@ -1,4 +1,6 @@
+pub mod dsp;
 pub mod graph;
+pub mod io;
 pub mod la;
 pub mod netlist;
 pub mod sim;
@@ -0,0 +1,84 @@
+use super::{NodeName, Scalar, Sweep};
+
+#[derive(Debug, Clone)]
+pub enum Analysis
+{
+    Op,
+    Dc(DcAnalysis),
+    Ac(AcAnalysis),
+    Transient(TransientAnalysis),
+    Noise(NoiseAnalysis),
+}
+
+/// `.DC source start stop increment`
+#[derive(Debug, Clone)]
+pub struct DcAnalysis
+{
+    pub source: String,
+    pub sweep: Sweep,
+}
+
+/// `.AC DEC|OCT|LIN points start stop`
+#[derive(Debug, Clone)]
+pub struct AcAnalysis
+{
+    pub sweep: Sweep,
+}
+
+/// `.TRAN step stop [UIC]`
+#[derive(Debug, Clone)]
+pub struct TransientAnalysis
+{
+    pub step: Scalar,
+    pub stop: Scalar,
+    /// Start from the initial conditions rather
+    /// than the operating point
+    pub uic: bool,
+}
+
+impl TransientAnalysis
+{
+    /// Points from t=0 up to and including t=stop
+    pub fn steps(&self) -> usize
+    {
+        (self.stop / self.step).round() as usize + 1
+    }
+}
+
+/// `.NOISE V(output[,reference]) source sweep`
+#[derive(Debug, Clone)]
+pub struct NoiseAnalysis
+{
+    pub output: NodeName,
+    pub reference: NodeName,
+    pub input: String,
+    pub sweep: Sweep,
+}
+
+/// `.FOUR fundamental [harmonics [periods]] V(output[,reference])...`
+///
+/// Evaluated over the last `periods` of the transient run
+#[derive(Debug, Clone)]
+pub struct FourierAnalysis
+{
+    pub fundamental: Scalar,
+    pub harmonics: usize,
+    pub periods: usize,
+    pub outputs: Vec<(NodeName, NodeName)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepTarget
+{
+    Parameter(String),
+    Device(String),
+    Temperature,
+}
+
+/// `.STEP PARAM name sweep`, `.STEP device sweep` or `.STEP TEMP sweep`
+#[derive(Debug, Clone)]
+pub struct Step
+{
+    pub target: StepTarget,
+    pub sweep: Sweep,
+}
@@ -1,17 +1,19 @@
-use super::{Exp, NodeName, Value};
+use super::{DiodeModel, Exp, NodeName, Scalar, Value};
 
 #[derive(Debug, Clone)]
 pub enum Device
 {
-    Voltage{name: String, plus: NodeName, minus: NodeName, voltage: Exp},
-    Resistor{name: String, plus: NodeName, minus: NodeName, resistance: Value},
-    Capacitor{name: String, plus: NodeName, minus: NodeName, capacitance: Value},
-    Diode{name: String, plus: NodeName, minus: NodeName},
+    Voltage{name: String, plus: NodeName, minus: NodeName, voltage: Exp, ac_magnitude: Scalar, ac_phase: Scalar},
+    Resistor{name: String, plus: NodeName, minus: NodeName, resistance: Value, tc1: Scalar, tc2: Scalar},
+    /// `ic` is the initial voltage used with `.TRAN ... UIC`
+    Capacitor{name: String, plus: NodeName, minus: NodeName, capacitance: Value, ic: Option<Scalar>},
+    Diode{name: String, plus: NodeName, minus: NodeName, model: DiodeModel},
     Vcvs{name: String, plus: NodeName, minus: NodeName, control_plus: NodeName, control_minus: NodeName, gain: Value},
 }
 
 impl Device
 {
+    #[allow(clippy::needless_lifetimes)]
     pub fn name<'a>(&'a self) -> &'a str
     {
         match self
@@ -24,6 +26,29 @@ impl Device
         }
     }
 
+    /// The device's component value, if it has one
+    pub fn value(&self) -> Option<&Value>
+    {
+        match self
+        {
+            Self::Resistor { resistance, .. } => Some(resistance),
+            Self::Capacitor { capacitance, .. } => Some(capacitance),
+            Self::Vcvs { gain, .. } => Some(gain),
+            Self::Voltage { .. } | Self::Diode { .. } => None,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> Option<&mut Value>
+    {
+        match self
+        {
+            Self::Resistor { resistance, .. } => Some(resistance),
+            Self::Capacitor { capacitance, .. } => Some(capacitance),
+            Self::Vcvs { gain, .. } => Some(gain),
+            Self::Voltage { .. } | Self::Diode { .. } => None,
+        }
+    }
+
     pub fn nodes(&self) -> Vec<NodeName>
     {
         match self
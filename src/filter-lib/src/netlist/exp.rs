@@ -1,5 +1,11 @@
+use std::sync::Arc;
+use crate::io::wav;
 use super::parser::{Parser, ParseError, Token, TokenKind};
 
+/// Limit on the `PULSE` periods given as breakpoints, so
+/// a tiny period can't make millions of them
+const MAX_PULSE_PERIODS: usize = 10000;
+
 #[derive(Clone, Debug)]
 pub enum Exp
 {
@@ -8,6 +14,33 @@ pub enum Exp
     Product(Vec<Box<Exp>>),
     Sin(Box<Exp>),
     Time,
+    /// `PULSE(v1 v2 [delay [rise [fall [width [period]]]]])` -
+    /// zero rise/fall times switch instantly
+    Pulse
+    {
+        initial: f64,
+        pulsed: f64,
+        delay: f64,
+        rise: f64,
+        fall: f64,
+        width: f64,
+        period: f64,
+    },
+    /// `PWL(t1 v1 t2 v2 ...)` - holds the first and last
+    /// values outside the given times
+    Pwl(Vec<(f64, f64)>),
+    /// `WAV("file.wav", chan=0, scale=1)` - one channel of a
+    /// WAV file, relative to the working directory, with full
+    /// scale at `scale` volts. Linearly interpolated between
+    /// samples and silent after the file ends.
+    Wav
+    {
+        path: String,
+        channel: usize,
+        scale: f64,
+        sample_rate: f64,
+        samples: Arc<Vec<f64>>,
+    },
 }
 
 impl Exp
@@ -21,9 +54,127 @@ impl Exp
             Exp::Product(factors) => factors.iter().map(|f| f.calc(time)).product(),
             Exp::Sin(freq) => (time * freq.calc(time) * 0.5 * std::f64::consts::FRAC_1_PI).sin(),
             Exp::Time => time,
+            Exp::Pulse { initial, pulsed, delay, rise, fall, width, period } =>
+            {
+                if time < *delay
+                {
+                    return *initial;
+                }
+
+                let mut local = time - delay;
+                if period.is_finite() && (*period > 0.0)
+                {
+                    local %= period;
+                }
+
+                if local < *rise
+                {
+                    initial + (pulsed - initial) * local / rise
+                }
+                else if local < rise + width
+                {
+                    *pulsed
+                }
+                else if local < rise + width + fall
+                {
+                    pulsed + (initial - pulsed) * (local - rise - width) / fall
+                }
+                else
+                {
+                    *initial
+                }
+            },
+            Exp::Pwl(points) =>
+            {
+                let index = points.partition_point(|(t, _)| *t <= time);
+
+                if index == 0
+                {
+                    points[0].1
+                }
+                else if index >= points.len()
+                {
+                    points[points.len() - 1].1
+                }
+                else
+                {
+                    let (t0, v0) = points[index - 1];
+                    let (t1, v1) = points[index];
+                    v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+                }
+            },
+            Exp::Wav { scale, sample_rate, samples, .. } =>
+            {
+                let position = (time * sample_rate).max(0.0);
+                let index = position.floor() as usize;
+
+                match (samples.get(index), samples.get(index + 1))
+                {
+                    (Some(a), Some(b)) => scale * (a + (b - a) * (position - index as f64)),
+                    (Some(a), None) => scale * a,
+                    _ => 0.0,
+                }
+            },
+        }
+    }
+
+    /// Times in `start < t <= stop` where the waveform
+    /// has a corner, so a simulation can land on them
+    pub fn breakpoints(&self, start: f64, stop: f64) -> Vec<f64>
+    {
+        let mut result = Vec::new();
+
+        match self
+        {
+            Exp::Value(_) | Exp::Time | Exp::Wav { .. } => (),
+            Exp::Sum(children) | Exp::Product(children) =>
+            {
+                for child in children.iter()
+                {
+                    result.extend(child.breakpoints(start, stop));
+                }
+            },
+            Exp::Sin(child) => result.extend(child.breakpoints(start, stop)),
+            Exp::Pulse { delay, rise, fall, width, period, .. } =>
+            {
+                let corners = [0.0, *rise, rise + width, rise + width + fall];
+                let add_period = |result: &mut Vec<f64>, offset: f64|
+                {
+                    result.extend(corners.iter().map(|c| offset + c).filter(|t| (*t > start) && (*t <= stop)));
+                };
+
+                if !period.is_finite() || (*period <= 0.0)
+                {
+                    add_period(&mut result, *delay);
+                }
+                else
+                {
+                    // Only the periods overlapping start to stop,
+                    // and no more than MAX_PULSE_PERIODS of them
+                    let first = ((start - delay - corners[3]) / period).floor().max(0.0);
+                    for i in 0..MAX_PULSE_PERIODS
+                    {
+                        let offset = delay + (first + i as f64) * period;
+                        if offset > stop
+                        {
+                            break;
+                        }
+                        add_period(&mut result, offset);
+                    }
+                }
+            },
+            Exp::Pwl(points) =>
+            {
+                result.extend(points.iter().map(|(t, _)| *t).filter(|t| (*t > start) && (*t <= stop)));
+            },
         }
+
+        result.sort_by(|a, b| a.total_cmp(b));
+        result.dedup();
+        result
     }
 
+    #[allow(clippy::redundant_closure)]
     pub fn parse(parser: &mut Parser) -> Result<Exp, ParseError>
     {
         let mut terms = Vec::new();
@@ -55,6 +206,7 @@ impl Exp
         }
     }
 
+    #[allow(clippy::redundant_closure)]
     fn parse_term(parser: &mut Parser) -> Result<Exp, ParseError>
     {
         let mut factors = Vec::new();
@@ -104,7 +256,7 @@ impl Exp
             },
             Token::Ident(ident) =>
             {
-                match ident.as_ref()
+                match ident.to_lowercase().as_ref()
                 {
                     "sin" =>
                     {
@@ -119,10 +271,126 @@ impl Exp
                         let _ = parser.expect(TokenKind::Ident);
                         Ok(Exp::Time)
                     },
+                    "pulse" =>
+                    {
+                        let _ = parser.expect(TokenKind::Ident);
+                        let args = Exp::parse_arguments(parser)?;
+                        if (args.len() < 2) || (args.len() > 7)
+                        {
+                            return Err(location.into_error_named("PULSE expects 2 to 7 values: v1 v2 [delay [rise [fall [width [period]]]]]".to_owned()));
+                        }
+                        if args.iter().skip(2).any(|a| *a < 0.0)
+                        {
+                            return Err(location.into_error_named("PULSE times can't be negative".to_owned()));
+                        }
+
+                        let arg = |index: usize, default: f64| args.get(index).copied().unwrap_or(default);
+                        Ok(Exp::Pulse
+                        {
+                            initial: args[0],
+                            pulsed: args[1],
+                            delay: arg(2, 0.0),
+                            rise: arg(3, 0.0),
+                            fall: arg(4, 0.0),
+                            width: arg(5, f64::INFINITY),
+                            period: arg(6, f64::INFINITY),
+                        })
+                    },
+                    "pwl" =>
+                    {
+                        let _ = parser.expect(TokenKind::Ident);
+                        let args = Exp::parse_arguments(parser)?;
+                        if args.is_empty() || (args.len() % 2 != 0)
+                        {
+                            return Err(location.into_error_named("PWL expects time/value pairs".to_owned()));
+                        }
+
+                        let points = args.chunks(2).map(|pair| (pair[0], pair[1])).collect::<Vec<_>>();
+                        if points.windows(2).any(|pair| pair[1].0 <= pair[0].0)
+                        {
+                            return Err(location.into_error_named("PWL times must be increasing".to_owned()));
+                        }
+                        Ok(Exp::Pwl(points))
+                    },
+                    "wav" =>
+                    {
+                        let _ = parser.expect(TokenKind::Ident);
+                        Exp::parse_wav(parser)
+                    },
                     _ => Err(location.into_error_named(format!("Unknown function/variable \"{}\"", ident)))
                 }
             },
             _ => Err(location.into_error_named("Expected expression factor".to_owned()))
         }
     }
-}
\ No newline at end of file
+
+    /// Parses `("file.wav" [chan=n] [scale=v])` and
+    /// reads the file - commas are optional
+    fn parse_wav(parser: &mut Parser) -> Result<Exp, ParseError>
+    {
+        parser.expect_symbol('(')?;
+        let location = parser.cur_location();
+        let path = parser.expect_string()?;
+
+        let mut channel = 0;
+        let mut scale = 1.0;
+        while !parser.is_symbol(')')
+        {
+            if parser.is_symbol(',')
+            {
+                parser.expect_symbol(',')?;
+                continue;
+            }
+
+            let parameter_location = parser.cur_location();
+            let parameter = parser.expect_ident()?;
+            parser.expect_symbol('=')?;
+            let value_location = parser.cur_location();
+            let value = parser.expect_value()?;
+
+            match parameter.to_lowercase().as_ref()
+            {
+                "chan" =>
+                {
+                    if (value < 0.0) || (value.fract() != 0.0)
+                    {
+                        return Err(value_location.into_error_named("WAV channel must be a whole number from 0".to_owned()));
+                    }
+                    channel = value as usize;
+                },
+                "scale" => scale = value,
+                _ => return Err(parameter_location.into_error_named(format!("Unknown WAV parameter \"{}\" - expected chan or scale", parameter))),
+            }
+        }
+        parser.expect_symbol(')')?;
+
+        let wav = std::fs::File::open(&path)
+            .map_err(wav::WavError::Io)
+            .and_then(|mut file| wav::read(&mut file))
+            .map_err(|err| location.clone().into_error_named(format!("Can't read \"{}\": {}", path, err)))?;
+        let samples = wav.channels.into_iter().nth(channel)
+            .ok_or_else(|| location.into_error_named(format!("\"{}\" has no channel {}", path, channel)))?;
+
+        Ok(Exp::Wav { path, channel, scale, sample_rate: wav.sample_rate as f64, samples: Arc::new(samples) })
+    }
+
+    /// Parses `(value value ...)` - commas are optional
+    fn parse_arguments(parser: &mut Parser) -> Result<Vec<f64>, ParseError>
+    {
+        let mut args = Vec::new();
+
+        parser.expect_symbol('(')?;
+        while !parser.is_symbol(')')
+        {
+            if parser.is_symbol(',')
+            {
+                parser.expect_symbol(',')?;
+                continue;
+            }
+            args.push(parser.expect_value()?);
+        }
+        parser.expect_symbol(')')?;
+
+        Ok(args)
+    }
+}
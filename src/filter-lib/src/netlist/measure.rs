@@ -0,0 +1,431 @@
+use super::{NodeName, ParseError, Probe, Scalar};
+use super::parser::{ParseLocation, Parser, Token, TokenKind};
+use super::probe::parse_node;
+
+/// `.MEAS TRAN|AC name ...` - a value measured from
+/// the results of a transient run or an AC sweep
+#[derive(Debug, Clone)]
+pub struct Measure
+{
+    pub name: String,
+    pub analysis: MeasureAnalysis,
+    pub measurement: Measurement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasureAnalysis
+{
+    Transient,
+    Ac,
+}
+
+/// Part of a complex AC node voltage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcPart
+{
+    /// `VM(...)` or `V(...)`
+    Magnitude,
+    /// `VDB(...)`
+    Decibels,
+    /// `VP(...)` - degrees, unwrapped across the sweep
+    Phase,
+    /// `VR(...)`
+    Real,
+    /// `VI(...)`
+    Imaginary,
+}
+
+/// What's measured - a probe in a transient run, e.g.
+/// `V(2)` or `P(R1)`, or part of a node voltage in an
+/// AC sweep, e.g. `VDB(2)` or `VP(2,3)`
+#[derive(Debug, Clone)]
+pub enum MeasureSignal
+{
+    Probe(Probe),
+    Ac{part: AcPart, node: NodeName, reference: NodeName},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge
+{
+    Rise,
+    Fall,
+    Cross,
+}
+
+/// Where a signal crosses a value - `signal=value` after
+/// WHEN, `signal VAL=value` after TRIG and TARG. Then
+/// optionally which crossing counts, `RISE|FALL|CROSS=n|LAST`
+/// (default `CROSS=1`), and `TD=delay` to ignore any before.
+#[derive(Debug, Clone)]
+pub struct Crossing
+{
+    pub signal: MeasureSignal,
+    pub value: Scalar,
+    pub edge: Edge,
+    /// Counting from 1 - None for the last
+    pub number: Option<usize>,
+    pub delay: Scalar,
+}
+
+/// A time, or a frequency in an AC sweep
+#[derive(Debug, Clone)]
+pub enum MeasurePoint
+{
+    /// `AT=x`
+    At(Scalar),
+    When(Crossing),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statistic
+{
+    Average,
+    Rms,
+    Min,
+    Max,
+    PeakToPeak,
+    /// Integral over time (or frequency)
+    Integral,
+}
+
+#[derive(Debug, Clone)]
+pub enum Measurement
+{
+    /// `FIND signal AT=x` or `FIND signal WHEN crossing` -
+    /// the signal's value at that point
+    Find{signal: MeasureSignal, point: MeasurePoint},
+    /// `WHEN crossing` - the time or frequency it happens
+    When(Crossing),
+    /// `DERIV signal AT=x` or `DERIV signal WHEN crossing` -
+    /// the signal's slope at that point
+    Derivative{signal: MeasureSignal, point: MeasurePoint},
+    /// `TRIG point TARG point` - from one point to the
+    /// other, e.g. a rise time or a delay
+    TriggerTarget{trigger: MeasurePoint, target: MeasurePoint},
+    /// `AVG|RMS|MIN|MAX|PP|INTEG signal [FROM=x] [TO=x]`
+    Statistic{statistic: Statistic, signal: MeasureSignal, from: Option<Scalar>, to: Option<Scalar>},
+    /// `BW V(out[,ref])` - AC only. Width of the band within
+    /// 3dB of the peak gain, from 0Hz if that's where it starts.
+    Bandwidth{node: NodeName, reference: NodeName},
+    /// `PEAK V(out[,ref])` - AC only. Maximum gain in dB.
+    PeakGain{node: NodeName, reference: NodeName},
+    /// `PM V(out[,ref])` - AC only, of a loop gain. 180 degrees
+    /// plus its phase where the gain falls through 0dB.
+    PhaseMargin{node: NodeName, reference: NodeName},
+}
+
+/// Nodes and devices referred to, checked once
+/// the whole netlist has been read
+struct References<'a>
+{
+    analysis: MeasureAnalysis,
+    nodes: &'a mut Vec<(NodeName, ParseLocation)>,
+    devices: &'a mut Vec<(String, ParseLocation)>,
+}
+
+impl Measure
+{
+    /// Parses the rest of a `.MEAS` line, adding the nodes
+    /// and devices it refers to to `node_refs` and `device_refs`
+    pub fn parse(parser: &mut Parser, node_refs: &mut Vec<(NodeName, ParseLocation)>, device_refs: &mut Vec<(String, ParseLocation)>) -> Result<Measure, ParseError>
+    {
+        let location = parser.cur_location();
+        let analysis = match parser.expect_ident()?.to_uppercase().as_ref()
+        {
+            "TRAN" => MeasureAnalysis::Transient,
+            "AC" => MeasureAnalysis::Ac,
+            _ => return Err(location.into_error_named("Expected TRAN or AC".to_owned())),
+        };
+
+        let name = parser.expect_ident()?;
+
+        let mut refs = References { analysis, nodes: node_refs, devices: device_refs };
+
+        let location = parser.cur_location();
+        let kind = parser.expect_ident()?.to_uppercase();
+
+        let statistic = match kind.as_ref()
+        {
+            "AVG" => Some(Statistic::Average),
+            "RMS" => Some(Statistic::Rms),
+            "MIN" => Some(Statistic::Min),
+            "MAX" => Some(Statistic::Max),
+            "PP" => Some(Statistic::PeakToPeak),
+            "INTEG" => Some(Statistic::Integral),
+            _ => None,
+        };
+
+        let measurement = match (kind.as_ref(), statistic)
+        {
+            (_, Some(statistic)) =>
+            {
+                let signal = refs.signal(parser)?;
+                let (mut from, mut to) = (None, None);
+                while let Token::Ident(ident) = parser.peek().clone()
+                {
+                    let bound = match ident.to_uppercase().as_ref()
+                    {
+                        "FROM" => &mut from,
+                        "TO" => &mut to,
+                        _ => break,
+                    };
+                    parser.expect_ident()?;
+                    parser.expect_symbol('=')?;
+                    *bound = Some(parser.expect_value()?);
+                }
+                if let (Some(from), Some(to)) = (from, to)
+                {
+                    if to <= from
+                    {
+                        return Err(location.into_error_named("TO must be after FROM".to_owned()));
+                    }
+                }
+                Measurement::Statistic { statistic, signal, from, to }
+            },
+            ("FIND", _) =>
+            {
+                let signal = refs.signal(parser)?;
+                Measurement::Find { signal, point: refs.point(parser)? }
+            },
+            ("DERIV", _) =>
+            {
+                let signal = refs.signal(parser)?;
+                Measurement::Derivative { signal, point: refs.point(parser)? }
+            },
+            ("WHEN", _) => Measurement::When(refs.crossing(parser, true)?),
+            ("TRIG", _) =>
+            {
+                let trigger = refs.trigger(parser)?;
+                let targ_location = parser.cur_location();
+                if !parser.expect_ident()?.eq_ignore_ascii_case("TARG")
+                {
+                    return Err(targ_location.into_error_named("Expected TARG".to_owned()));
+                }
+                Measurement::TriggerTarget { trigger, target: refs.trigger(parser)? }
+            },
+            ("BW", _) | ("PEAK", _) | ("PM", _) =>
+            {
+                if analysis != MeasureAnalysis::Ac
+                {
+                    return Err(location.into_error_named(format!("{} is only for .MEAS AC", kind)));
+                }
+                let (node, reference) = refs.voltage(parser)?;
+                match kind.as_ref()
+                {
+                    "BW" => Measurement::Bandwidth { node, reference },
+                    "PEAK" => Measurement::PeakGain { node, reference },
+                    _ => Measurement::PhaseMargin { node, reference },
+                }
+            },
+            _ => return Err(location.into_error_named(format!("Unknown measurement \"{}\" - expected FIND, WHEN, DERIV, TRIG, AVG, RMS, MIN, MAX, PP, INTEG, BW, PEAK or PM", kind))),
+        };
+
+        Ok(Measure { name, analysis, measurement })
+    }
+}
+
+impl<'a> References<'a>
+{
+    fn signal(&mut self, parser: &mut Parser) -> Result<MeasureSignal, ParseError>
+    {
+        if self.analysis == MeasureAnalysis::Ac
+        {
+            let location = parser.cur_location();
+            let part = match parser.expect_ident()?.to_uppercase().as_ref()
+            {
+                "V" | "VM" => AcPart::Magnitude,
+                "VDB" => AcPart::Decibels,
+                "VP" => AcPart::Phase,
+                "VR" => AcPart::Real,
+                "VI" => AcPart::Imaginary,
+                _ => return Err(location.into_error_named("Expected AC output VM, VDB, VP, VR or VI(node[,reference])".to_owned())),
+            };
+            let (node, reference) = self.nodes(parser)?;
+            return Ok(MeasureSignal::Ac { part, node, reference });
+        }
+
+        let location = parser.cur_location();
+        let probe = Probe::parse(parser)?;
+        for term in probe.terms()
+        {
+            match term
+            {
+                Probe::Voltage { node, reference } =>
+                {
+                    self.nodes.push((node.clone(), location.clone()));
+                    if *reference != NodeName::gnd()
+                    {
+                        self.nodes.push((reference.clone(), location.clone()));
+                    }
+                },
+                Probe::Current(device) | Probe::Power(device) =>
+                {
+                    self.devices.push((device.clone(), location.clone()));
+                },
+                _ => (),
+            }
+        }
+        Ok(MeasureSignal::Probe(probe))
+    }
+
+    fn voltage(&mut self, parser: &mut Parser) -> Result<(NodeName, NodeName), ParseError>
+    {
+        let location = parser.cur_location();
+        if !parser.expect_ident()?.eq_ignore_ascii_case("V")
+        {
+            return Err(location.into_error_named("Expected voltage output V(node[,reference])".to_owned()));
+        }
+        self.nodes(parser)
+    }
+
+    fn nodes(&mut self, parser: &mut Parser) -> Result<(NodeName, NodeName), ParseError>
+    {
+        // (node) or (node,reference)
+
+        parser.expect_symbol('(')?;
+        let location = parser.cur_location();
+        let node = parse_node(parser)?;
+        self.nodes.push((node.clone(), location));
+
+        let reference = if parser.is_symbol(',')
+        {
+            parser.expect_symbol(',')?;
+            let location = parser.cur_location();
+            let reference = parse_node(parser)?;
+            self.nodes.push((reference.clone(), location));
+            reference
+        }
+        else
+        {
+            NodeName::gnd()
+        };
+        parser.expect_symbol(')')?;
+
+        Ok((node, reference))
+    }
+
+    /// `AT=x` or `WHEN signal=value ...`
+    fn point(&mut self, parser: &mut Parser) -> Result<MeasurePoint, ParseError>
+    {
+        let location = parser.cur_location();
+        match parser.expect_ident()?.to_uppercase().as_ref()
+        {
+            "AT" =>
+            {
+                parser.expect_symbol('=')?;
+                Ok(MeasurePoint::At(parser.expect_value()?))
+            },
+            "WHEN" => Ok(MeasurePoint::When(self.crossing(parser, true)?)),
+            _ => Err(location.into_error_named("Expected AT=x or WHEN".to_owned())),
+        }
+    }
+
+    /// `AT=x` or `signal VAL=value ...`
+    fn trigger(&mut self, parser: &mut Parser) -> Result<MeasurePoint, ParseError>
+    {
+        if matches!(parser.peek(), Token::Ident(ident) if ident.eq_ignore_ascii_case("AT"))
+        {
+            return self.point(parser);
+        }
+        Ok(MeasurePoint::When(self.crossing(parser, false)?))
+    }
+
+    fn crossing(&mut self, parser: &mut Parser, equals: bool) -> Result<Crossing, ParseError>
+    {
+        let signal = self.signal(parser)?;
+        if !equals
+        {
+            let location = parser.cur_location();
+            if !parser.expect_ident()?.eq_ignore_ascii_case("VAL")
+            {
+                return Err(location.into_error_named("Expected VAL=value".to_owned()));
+            }
+        }
+        parser.expect_symbol('=')?;
+        let value = parser.expect_value()?;
+
+        let mut edge = Edge::Cross;
+        let mut number = Some(1);
+        let mut delay = 0.0;
+
+        while let Token::Ident(ident) = parser.peek().clone()
+        {
+            let upper = ident.to_uppercase();
+            edge = match upper.as_ref()
+            {
+                "RISE" => Edge::Rise,
+                "FALL" => Edge::Fall,
+                "CROSS" => Edge::Cross,
+                "TD" =>
+                {
+                    parser.expect_ident()?;
+                    parser.expect_symbol('=')?;
+                    delay = parser.expect_value()?;
+                    continue;
+                },
+                _ => break,
+            };
+
+            parser.expect_ident()?;
+            parser.expect_symbol('=')?;
+            let location = parser.cur_location();
+            number = match parser.peek().clone()
+            {
+                Token::Ident(last) if last.eq_ignore_ascii_case("LAST") =>
+                {
+                    parser.expect_ident()?;
+                    None
+                },
+                Token::Integer(number) if number > 0 =>
+                {
+                    parser.expect(TokenKind::Integer)?;
+                    Some(number)
+                },
+                _ => return Err(location.into_error_named(format!("Expected {}=n (from 1) or {}=LAST", upper, upper))),
+            };
+        }
+
+        Ok(Crossing { signal, value, edge, number, delay })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn parse(line: &str) -> Result<Measure, ParseError>
+    {
+        let mut parser = Parser::new(line.to_owned());
+        let (mut nodes, mut devices) = (Vec::new(), Vec::new());
+        let measure = Measure::parse(&mut parser, &mut nodes, &mut devices)?;
+        parser.expect(TokenKind::Newline)?;
+        Ok(measure)
+    }
+
+    #[test]
+    fn parse_forms()
+    {
+        let measure = parse("TRAN rise TRIG V(2) VAL=0.1 RISE=2 TARG V(2) VAL=0.9 RISE=LAST TD=1m").unwrap();
+        assert_eq!(measure.name, "rise");
+        let Measurement::TriggerTarget { trigger: MeasurePoint::When(trigger), target: MeasurePoint::When(target) } = measure.measurement else { panic!() };
+        assert_eq!((trigger.value, trigger.edge, trigger.number), (0.1, Edge::Rise, Some(2)));
+        assert_eq!((target.value, target.edge, target.number, target.delay), (0.9, Edge::Rise, None, 1e-3));
+
+        let measure = parse("TRAN v FIND I(R1)*1k WHEN V(2)=-0.5 FALL=1").unwrap();
+        assert!(matches!(measure.measurement, Measurement::Find { point: MeasurePoint::When(Crossing { value, edge: Edge::Fall, .. }), .. } if value == -0.5));
+
+        let measure = parse("TRAN p RMS V(2,3) FROM=1m TO=2m").unwrap();
+        assert!(matches!(measure.measurement, Measurement::Statistic { statistic: Statistic::Rms, from: Some(_), to: Some(_), .. }));
+
+        let measure = parse("AC f WHEN VDB(2)=-3").unwrap();
+        assert!(matches!(measure.measurement, Measurement::When(Crossing { signal: MeasureSignal::Ac { part: AcPart::Decibels, .. }, .. })));
+
+        assert!(parse("AC bw BW V(2)").is_ok());
+        assert!(parse("TRAN bw BW V(2)").is_err());
+        assert!(parse("AC v FIND V(2)+1 AT=1k").is_err());
+        assert!(parse("TRAN p AVG V(2) FROM=2m TO=1m").is_err());
+        assert!(parse("TRAN t WHEN V(2)=1 CROSS=0").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use super::parser::{Parser, ParseError};
+
+/// How reactive elements are integrated
+/// in transient simulations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegrationMethod
+{
+    /// First order - very stable but damps
+    /// high-Q circuits
+    BackwardEuler,
+    /// Second order and preserves oscillations,
+    /// with a backward Euler step whenever the
+    /// solution rings at the step size
+    #[default]
+    Trapezoidal,
+    /// Second order backward differentiation
+    /// formula (BDF2)
+    Gear,
+}
+
+impl IntegrationMethod
+{
+    /// Parses the value of `.OPTIONS METHOD=`
+    pub fn parse(parser: &mut Parser) -> Result<IntegrationMethod, ParseError>
+    {
+        let location = parser.cur_location();
+        let method = parser.expect_ident()?.to_uppercase();
+
+        match method.as_ref()
+        {
+            "EULER" | "BE" => Ok(IntegrationMethod::BackwardEuler),
+            "TRAP" | "TRAPEZOIDAL" => Ok(IntegrationMethod::Trapezoidal),
+            "GEAR" | "BDF2" => Ok(IntegrationMethod::Gear),
+            _ => Err(location.into_error_named(format!("Unknown integration method \"{}\" - expected EULER, TRAP or GEAR", method))),
+        }
+    }
+}
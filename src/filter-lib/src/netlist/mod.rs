@@ -1,15 +1,30 @@
+mod analysis;
 mod device;
 mod exp;
+mod measure;
+mod method;
+mod model;
+#[allow(clippy::module_inception)]
 mod netlist;
 mod nodename;
+mod options;
 mod parser;
+mod probe;
+mod sweep;
 mod value;
 
 pub type Scalar = f64;
 
+pub use analysis::{AcAnalysis, Analysis, DcAnalysis, FourierAnalysis, NoiseAnalysis, Step, StepTarget, TransientAnalysis};
 pub use device::Device;
 pub use exp::Exp;
-pub use netlist::Netlist;
+pub use measure::{AcPart, Crossing, Edge, Measure, MeasureAnalysis, MeasurePoint, MeasureSignal, Measurement, Statistic};
+pub use method::IntegrationMethod;
+pub use model::{DiodeModel, Model};
+pub use netlist::{Netlist, DEFAULT_TEMPERATURE};
 pub use nodename::NodeName;
+pub use options::{OptionError, SimulationOptions};
 pub use parser::ParseError;
-pub use value::Value;
\ No newline at end of file
+pub use probe::{Probe, Unit};
+pub use sweep::Sweep;
+pub use value::{Distribution, Tolerance, Value};
\ No newline at end of file
@@ -0,0 +1,124 @@
+use super::Scalar;
+use super::parser::{Parser, ParseError};
+
+#[derive(Debug, Clone)]
+pub struct DiodeModel
+{
+    /// Saturation current (A)
+    pub is: Scalar,
+    /// Emission coefficient
+    pub n: Scalar,
+    /// Flicker noise coefficient
+    pub kf: Scalar,
+    /// Flicker noise exponent
+    pub af: Scalar,
+    /// Saturation current temperature exponent
+    pub xti: Scalar,
+    /// Activation energy (eV)
+    pub eg: Scalar,
+}
+
+impl Default for DiodeModel
+{
+    fn default() -> Self
+    {
+        DiodeModel
+        {
+            is: 1e-12,
+            n: 1.5,
+            kf: 0.0,
+            af: 1.0,
+            xti: 3.0,
+            eg: 1.11,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Model
+{
+    Diode(DiodeModel),
+}
+
+impl Model
+{
+    /// Parses the remainder of a `.MODEL name type(PARAM=value ...)`
+    /// card, after the model name. The parentheses are optional.
+    pub fn parse(parser: &mut Parser) -> Result<Model, ParseError>
+    {
+        let location = parser.cur_location();
+        let kind = parser.expect_ident()?.to_uppercase();
+
+        let params = parse_params(parser)?;
+
+        match kind.as_ref()
+        {
+            "D" =>
+            {
+                let mut model = DiodeModel::default();
+                for (name, value, location) in params
+                {
+                    match name.as_ref()
+                    {
+                        "IS" => model.is = value,
+                        "N" => model.n = value,
+                        "KF" => model.kf = value,
+                        "AF" => model.af = value,
+                        "XTI" => model.xti = value,
+                        "EG" => model.eg = value,
+                        _ => return Err(location.into_error_named(format!("Unknown diode model parameter \"{}\"", name))),
+                    }
+                }
+
+                if (model.is <= 0.0) || (model.n <= 0.0)
+                {
+                    return Err(parser.cur_location().into_error_named("Diode model IS and N must be positive".to_owned()));
+                }
+
+                Ok(Model::Diode(model))
+            },
+            _ => Err(location.into_error_named(format!("Unknown model type \"{}\"", kind))),
+        }
+    }
+}
+
+fn parse_params(parser: &mut Parser) -> Result<Vec<(String, Scalar, super::parser::ParseLocation)>, ParseError>
+{
+    let mut params = Vec::new();
+
+    let bracketed = parser.is_symbol('(');
+    if bracketed
+    {
+        parser.expect_symbol('(')?;
+    }
+
+    loop
+    {
+        if bracketed && parser.is_symbol(')')
+        {
+            parser.expect_symbol(')')?;
+            break;
+        }
+        if parser.is_newline()
+        {
+            if bracketed
+            {
+                parser.expect_symbol(')')?;
+            }
+            break;
+        }
+        if parser.is_symbol(',')
+        {
+            parser.expect_symbol(',')?;
+            continue;
+        }
+
+        let location = parser.cur_location();
+        let name = parser.expect_ident()?.to_uppercase();
+        parser.expect_symbol('=')?;
+        let value = parser.expect_value()?;
+        params.push((name, value, location));
+    }
+
+    Ok(params)
+}
@@ -1,14 +1,26 @@
 use std::str::FromStr;
-use std::collections::HashSet;
-use super::{Device, Exp, ParseError, NodeName, Value};
-use super::parser::{Parser, Token, TokenKind};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use super::{AcAnalysis, Analysis, DcAnalysis, Device, FourierAnalysis, Exp, IntegrationMethod, Measure, MeasureAnalysis, Model, NoiseAnalysis, OptionError, ParseError, NodeName, Scalar, SimulationOptions, Step, StepTarget, Sweep, TransientAnalysis, Value};
+use super::parser::{ParseLocation, Parser, Token, TokenKind};
+use crate::sim::ZERO_CELSIUS;
 
 #[derive(Debug, Clone)]
 pub struct Netlist
 {
-    devices: Vec<Device>
+    devices: Vec<Device>,
+    analyses: Vec<Analysis>,
+    parameters: BTreeMap<String, Scalar>,
+    steps: Vec<Step>,
+    fourier: Vec<FourierAnalysis>,
+    measures: Vec<Measure>,
+    options: SimulationOptions,
+    initial_conditions: BTreeMap<String, Scalar>,
+    nodesets: BTreeMap<String, Scalar>,
 }
 
+/// Default for both `.TEMP` and `.OPTIONS TNOM` (Celsius)
+pub const DEFAULT_TEMPERATURE: Scalar = 27.0;
+
 impl Netlist
 {
     pub fn nodes(&self) -> HashSet<NodeName>
@@ -22,6 +34,171 @@ impl Netlist
     {
         &self.devices
     }
+
+    pub fn device(&self, name: &str) -> Option<&Device>
+    {
+        self.devices.iter().find(|d| d.name() == name)
+    }
+
+    pub fn analyses(&self) -> &Vec<Analysis>
+    {
+        &self.analyses
+    }
+
+    pub fn parameters(&self) -> &BTreeMap<String, Scalar>
+    {
+        &self.parameters
+    }
+
+    pub fn steps(&self) -> &Vec<Step>
+    {
+        &self.steps
+    }
+
+    pub fn fourier(&self) -> &Vec<FourierAnalysis>
+    {
+        &self.fourier
+    }
+
+    pub fn measures(&self) -> &Vec<Measure>
+    {
+        &self.measures
+    }
+
+    /// Simulator settings from `.OPTIONS` and `.TEMP`
+    pub fn options(&self) -> &SimulationOptions
+    {
+        &self.options
+    }
+
+    /// Circuit temperature from `.TEMP` (Celsius)
+    pub fn temperature(&self) -> Scalar
+    {
+        self.options.temperature
+    }
+
+    /// Temperature model parameters were measured
+    /// at, from `.OPTIONS TNOM` (Celsius)
+    pub fn nominal_temperature(&self) -> Scalar
+    {
+        self.options.nominal_temperature
+    }
+
+    /// Transient integration method, from `.OPTIONS METHOD`
+    pub fn method(&self) -> IntegrationMethod
+    {
+        self.options.method
+    }
+
+    /// Node voltages from `.IC` - held while finding the
+    /// transient's operating point, or used for capacitors
+    /// without `IC=` with `UIC`
+    pub fn initial_conditions(&self) -> &BTreeMap<String, Scalar>
+    {
+        &self.initial_conditions
+    }
+
+    /// Node voltages from `.NODESET` - where the
+    /// operating point search starts
+    pub fn nodesets(&self) -> &BTreeMap<String, Scalar>
+    {
+        &self.nodesets
+    }
+
+    /// A copy of this netlist with different settings,
+    /// if they're all in range
+    pub fn with_options(&self, options: SimulationOptions) -> Result<Netlist, OptionError>
+    {
+        options.validate()?;
+        let mut result = self.clone();
+        result.options = options;
+        Ok(result)
+    }
+
+    pub fn with_method(&self, method: IntegrationMethod) -> Result<Netlist, OptionError>
+    {
+        self.with_options(SimulationOptions { method, ..self.options.clone() })
+    }
+
+    /// Celsius - must be above absolute zero
+    pub fn with_temperature(&self, temperature: Scalar) -> Result<Netlist, OptionError>
+    {
+        self.with_options(SimulationOptions { temperature, ..self.options.clone() })
+    }
+
+    /// A copy of this netlist with a `.PARAM` changed,
+    /// and every value given as `{name}` updated
+    pub fn with_parameter(&self, name: &str, value: Scalar) -> Netlist
+    {
+        let mut result = self.clone();
+        result.parameters.insert(name.to_owned(), value);
+
+        for device in result.devices.iter_mut()
+        {
+            if let Some(device_value) = device.value_mut()
+            {
+                if device_value.parameter() == Some(name)
+                {
+                    device_value.set_value(value);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// A copy of this netlist with a device's value replaced -
+    /// for voltage sources this sets a constant voltage
+    pub fn with_device_value(&self, name: &str, value: Scalar) -> Netlist
+    {
+        let mut result = self.clone();
+
+        for device in result.devices.iter_mut()
+        {
+            if device.name() == name
+            {
+                if let Device::Voltage { voltage, .. } = device
+                {
+                    *voltage = Exp::Value(value);
+                }
+                else if let Some(device_value) = device.value_mut()
+                {
+                    device_value.set_value(value);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Names of all devices whose value has a tolerance
+    pub fn toleranced_devices(&self) -> Vec<String>
+    {
+        self.devices.iter()
+            .filter(|d| d.value().and_then(|v| v.tolerance()).is_some())
+            .map(|d| d.name().to_owned())
+            .collect()
+    }
+
+    /// A copy of this netlist with each named device's
+    /// value moved by a relative deviation
+    pub fn with_deviations(&self, deviations: &BTreeMap<String, Scalar>) -> Netlist
+    {
+        let mut result = self.clone();
+
+        for device in result.devices.iter_mut()
+        {
+            if let Some(deviation) = deviations.get(device.name())
+            {
+                if let Some(value) = device.value_mut()
+                {
+                    *value = value.deviated(*deviation);
+                }
+            }
+        }
+
+        result
+    }
 }
 
 impl FromStr for Netlist
@@ -33,6 +210,19 @@ impl FromStr for Netlist
         let mut parser = Parser::new(s.to_owned());
         let start_location = parser.cur_location();
         let mut devices = Vec::new();
+        let mut analyses = Vec::new();
+        let mut parameters = BTreeMap::new();
+        let mut parameter_refs = Vec::new();
+        let mut steps = Vec::new();
+        let mut fourier = Vec::new();
+        let mut measures = Vec::new();
+        let mut options = SimulationOptions::default();
+        let mut initial_conditions = BTreeMap::new();
+        let mut nodesets = BTreeMap::new();
+        let mut models = HashMap::new();
+        let mut model_refs = Vec::new();
+        let mut node_refs = Vec::new();
+        let mut device_refs = Vec::new();
         let mut device_names = HashSet::new();
         let mut node_names = HashSet::new();
         let gnd_node_name = NodeName::gnd();
@@ -49,6 +239,189 @@ impl FromStr for Netlist
                     let _ = parser.expect(TokenKind::Newline)?;
                     continue;
                 },
+                TokenKind::Symbol if parser.is_symbol('*') =>
+                {
+                    // Comment line
+                    parser.skip_line();
+                    continue;
+                },
+                TokenKind::Symbol if parser.is_symbol('.') =>
+                {
+                    parser.expect_symbol('.')?;
+                    let command_location = parser.cur_location();
+                    let command = parser.expect_ident()?.to_uppercase();
+
+                    match command.as_ref()
+                    {
+                        "END" =>
+                        {
+                            break;
+                        },
+                        "MODEL" =>
+                        {
+                            let name = parser.expect_ident()?;
+                            let model = Model::parse(&mut parser)?;
+                            if models.insert(name, model).is_some()
+                            {
+                                return Err(line_location.into_error_named("Duplicate model name".to_owned()));
+                            }
+                        },
+                        "PARAM" =>
+                        {
+                            while !parser.is_newline()
+                            {
+                                let name = parser.expect_ident()?;
+                                parser.expect_symbol('=')?;
+                                let value = parser.expect_value()?;
+                                parameters.insert(name, value);
+                            }
+                        },
+                        "STEP" =>
+                        {
+                            let target_location = parser.cur_location();
+                            let target = parser.expect_ident()?;
+                            let target = if target.eq_ignore_ascii_case("PARAM")
+                            {
+                                StepTarget::Parameter(parser.expect_ident()?)
+                            }
+                            else if target.eq_ignore_ascii_case("TEMP")
+                            {
+                                StepTarget::Temperature
+                            }
+                            else
+                            {
+                                StepTarget::Device(target)
+                            };
+
+                            if steps.iter().any(|(_, s): &(ParseLocation, Step)| s.target == target)
+                            {
+                                return Err(target_location.into_error_named("Duplicate .STEP target".to_owned()));
+                            }
+
+                            let sweep_location = parser.cur_location();
+                            let sweep = Sweep::parse_range(&mut parser)?;
+                            if (target == StepTarget::Temperature) && sweep.values().iter().any(|t| *t <= -ZERO_CELSIUS)
+                            {
+                                return Err(sweep_location.into_error_named("Temperature must be above absolute zero".to_owned()));
+                            }
+                            steps.push((target_location, Step { target, sweep }));
+                        },
+                        "TEMP" =>
+                        {
+                            options.temperature = parse_temperature(&mut parser)?;
+                        },
+                        "OPTIONS" | "OPTION" =>
+                        {
+                            while !parser.is_newline()
+                            {
+                                options.parse_option(&mut parser)?;
+                            }
+                        },
+                        "IC" =>
+                        {
+                            parse_node_voltages(&mut parser, &mut node_refs, &mut initial_conditions)?;
+                        },
+                        "NODESET" =>
+                        {
+                            parse_node_voltages(&mut parser, &mut node_refs, &mut nodesets)?;
+                        },
+                        "OP" =>
+                        {
+                            analyses.push((command_location, Analysis::Op));
+                        },
+                        "DC" =>
+                        {
+                            let source_location = parser.cur_location();
+                            let source = parser.expect_ident()?;
+                            let sweep = Sweep::parse_increment(&mut parser)?;
+                            analyses.push((source_location, Analysis::Dc(DcAnalysis { source, sweep })));
+                        },
+                        "AC" =>
+                        {
+                            let sweep = Sweep::parse_points(&mut parser)?;
+                            analyses.push((command_location, Analysis::Ac(AcAnalysis { sweep })));
+                        },
+                        "TRAN" =>
+                        {
+                            let step_location = parser.cur_location();
+                            let step = parser.expect_value()?;
+                            let stop = parser.expect_value()?;
+                            if (step <= 0.0) || (stop <= 0.0)
+                            {
+                                return Err(step_location.into_error_named("Transient step and stop time must be positive".to_owned()));
+                            }
+                            let uic = match parser.peek().clone()
+                            {
+                                Token::Ident(ident) if ident.eq_ignore_ascii_case("UIC") =>
+                                {
+                                    parser.expect_ident()?;
+                                    true
+                                },
+                                _ => false,
+                            };
+                            analyses.push((command_location, Analysis::Transient(TransientAnalysis { step, stop, uic })));
+                        },
+                        "FOUR" =>
+                        {
+                            let fundamental_location = parser.cur_location();
+                            let fundamental = parser.expect_value()?;
+                            if fundamental <= 0.0
+                            {
+                                return Err(fundamental_location.into_error_named("Fundamental frequency must be positive".to_owned()));
+                            }
+
+                            let mut counts = Vec::new();
+                            while let Token::Integer(count) = parser.peek().clone()
+                            {
+                                let count_location = parser.cur_location();
+                                parser.expect(TokenKind::Integer)?;
+                                if count == 0
+                                {
+                                    return Err(count_location.into_error_named("Harmonic and period counts must be positive".to_owned()));
+                                }
+                                if counts.len() == 2
+                                {
+                                    return Err(count_location.into_error_named("Expected output V(node[,reference])".to_owned()));
+                                }
+                                counts.push(count);
+                            }
+                            let harmonics = counts.first().copied().unwrap_or(9);
+                            let periods = counts.get(1).copied().unwrap_or(1);
+
+                            let mut outputs = Vec::new();
+                            while !parser.is_newline()
+                            {
+                                outputs.push(parse_voltage_output(&mut parser, &mut node_refs)?);
+                            }
+                            if outputs.is_empty()
+                            {
+                                return Err(parser.cur_location().into_error_named("Expected at least one output V(node[,reference])".to_owned()));
+                            }
+
+                            fourier.push((command_location, FourierAnalysis { fundamental, harmonics, periods, outputs }));
+                        },
+                        "MEAS" | "MEASURE" =>
+                        {
+                            let measure = Measure::parse(&mut parser, &mut node_refs, &mut device_refs)?;
+                            measures.push((command_location, measure));
+                        },
+                        "NOISE" =>
+                        {
+                            let (output, reference) = parse_voltage_output(&mut parser, &mut node_refs)?;
+                            let input_location = parser.cur_location();
+                            let input = parser.expect_ident()?;
+                            let sweep = Sweep::parse_points(&mut parser)?;
+
+                            analyses.push((input_location, Analysis::Noise(NoiseAnalysis { output, reference, input, sweep })));
+                        },
+                        _ =>
+                        {
+                            return Err(command_location.into_error_named(format!("Unknown command \".{}\"", command)));
+                        },
+                    }
+
+                    parser.expect(TokenKind::Newline)?;
+                },
                 TokenKind::Ident =>
                 {
                     let name = parser.expect_ident()?;
@@ -68,12 +441,18 @@ impl FromStr for Netlist
                         'C' =>
                         {
                             let (plus, minus, capacitance) = parse_two_terminal(&mut parser, &mut device_names, &mut node_names)?;
-                            devices.push(Device::Capacitor { name, plus, minus, capacitance });
+                            let ic = parse_initial_condition(&mut parser)?;
+                            devices.push(Device::Capacitor { name, plus, minus, capacitance, ic });
                         },
                         'D' =>
                         {
                             let (plus, minus) = parse_two_terminal_basic(&mut parser, &mut device_names, &mut node_names)?;
-                            devices.push(Device::Diode { name, plus, minus });
+                            if let Token::Ident(model) = parser.peek().clone()
+                            {
+                                model_refs.push((devices.len(), model, parser.cur_location()));
+                                parser.expect_ident()?;
+                            }
+                            devices.push(Device::Diode { name, plus, minus, model: Default::default() });
                         },
                         'E' =>
                         {
@@ -81,19 +460,21 @@ impl FromStr for Netlist
                             let minus = parse_node(&mut parser, &mut device_names, &mut node_names)?;
                             let control_plus = parse_node(&mut parser, &mut device_names, &mut node_names)?;
                             let control_minus = parse_node(&mut parser, &mut device_names, &mut node_names)?;
-                            let gain = Value::new(parser.expect_value()?);
+                            let gain = Value::parse(&mut parser)?;
 
                             devices.push(Device::Vcvs { name, plus, minus, control_plus, control_minus, gain})
                         },
                         'R' =>
                         {
                             let (plus, minus, resistance) = parse_two_terminal(&mut parser, &mut device_names, &mut node_names)?;
-                            devices.push(Device::Resistor { name, plus, minus, resistance });
+                            let (tc1, tc2) = parse_temperature_coefficients(&mut parser)?;
+                            devices.push(Device::Resistor { name, plus, minus, resistance, tc1, tc2 });
                         },
                         'V' =>
                         {
                             let (plus, minus, voltage) = parse_two_terminal_exp(&mut parser, &mut device_names, &mut node_names)?;
-                            devices.push(Device::Voltage { name, plus, minus, voltage });
+                            let (ac_magnitude, ac_phase) = parse_ac_spec(&mut parser)?;
+                            devices.push(Device::Voltage { name, plus, minus, voltage, ac_magnitude, ac_phase });
                         },
                         _ =>
                         {
@@ -101,6 +482,11 @@ impl FromStr for Netlist
                         },
                     }
 
+                    if let Some(parameter) = devices.last().and_then(Device::value).and_then(Value::parameter)
+                    {
+                        parameter_refs.push((devices.len() - 1, parameter.to_owned(), line_location));
+                    }
+
                     parser.expect(TokenKind::Newline)?;
                 },
                 _ =>
@@ -123,7 +509,134 @@ impl FromStr for Netlist
             return Err(start_location.into_error_named(format!("Must contain reference node \"{}\"", gnd_node_name)));
         }
 
-        Ok(Netlist{ devices })
+        for (index, model_name, location) in model_refs
+        {
+            match (models.get(&model_name), &mut devices[index])
+            {
+                (Some(Model::Diode(diode_model)), Device::Diode { model, .. }) =>
+                {
+                    *model = diode_model.clone();
+                },
+                (None, _) =>
+                {
+                    return Err(location.into_error_named(format!("Unknown model \"{}\"", model_name)));
+                },
+                _ =>
+                {
+                    return Err(location.into_error_named(format!("Model \"{}\" is the wrong type for this device", model_name)));
+                },
+            }
+        }
+
+        for (node, location) in node_refs
+        {
+            if !node_names.contains(node.name())
+            {
+                return Err(location.into_error_named(format!("Unknown node \"{}\"", node)));
+            }
+        }
+
+        for (device, location) in device_refs
+        {
+            if !device_names.contains(&device)
+            {
+                return Err(location.into_error_named(format!("Unknown device \"{}\"", device)));
+            }
+        }
+
+        for (index, parameter, location) in parameter_refs
+        {
+            match parameters.get(&parameter)
+            {
+                Some(value) => devices[index].value_mut().unwrap().set_value(*value),
+                None => return Err(location.into_error_named(format!("Unknown parameter \"{}\"", parameter))),
+            }
+        }
+
+        let mut checked_steps = Vec::new();
+        for (location, step) in steps
+        {
+            match &step.target
+            {
+                StepTarget::Parameter(name) =>
+                {
+                    if !parameters.contains_key(name)
+                    {
+                        return Err(location.into_error_named(format!("Unknown parameter \"{}\"", name)));
+                    }
+                },
+                StepTarget::Temperature => (),
+                StepTarget::Device(name) =>
+                {
+                    match devices.iter().find(|d| d.name() == name)
+                    {
+                        Some(Device::Voltage { .. }) => (),
+                        Some(device) if device.value().is_some() => (),
+                        Some(_) => return Err(location.into_error_named(format!("Device \"{}\" has no value to step", name))),
+                        None => return Err(location.into_error_named(format!("Unknown device \"{}\"", name))),
+                    }
+                },
+            }
+            checked_steps.push(step);
+        }
+
+        let has_transient = analyses.iter().any(|(_, a)| matches!(a, Analysis::Transient(_)));
+        let mut checked_fourier = Vec::new();
+        for (location, four) in fourier
+        {
+            if !has_transient
+            {
+                return Err(location.into_error_named(".FOUR requires a .TRAN analysis".to_owned()));
+            }
+            checked_fourier.push(four);
+        }
+
+        let has_ac = analyses.iter().any(|(_, a)| matches!(a, Analysis::Ac(_)));
+        let mut checked_measures = Vec::<Measure>::new();
+        for (location, measure) in measures
+        {
+            match measure.analysis
+            {
+                MeasureAnalysis::Transient if !has_transient => return Err(location.into_error_named(".MEAS TRAN requires a .TRAN analysis".to_owned())),
+                MeasureAnalysis::Ac if !has_ac => return Err(location.into_error_named(".MEAS AC requires a .AC analysis".to_owned())),
+                _ => (),
+            }
+            if checked_measures.iter().any(|m| m.name.eq_ignore_ascii_case(&measure.name))
+            {
+                return Err(location.into_error_named(format!("Duplicate measurement name \"{}\"", measure.name)));
+            }
+            checked_measures.push(measure);
+        }
+
+        let mut checked_analyses = Vec::new();
+        for (location, analysis) in analyses
+        {
+            match &analysis
+            {
+                Analysis::Op | Analysis::Ac(_) | Analysis::Transient(_) => (),
+                Analysis::Dc(dc) =>
+                {
+                    match devices.iter().find(|d| d.name() == dc.source)
+                    {
+                        Some(Device::Voltage { .. }) => (),
+                        Some(_) => return Err(location.into_error_named(format!("DC sweep source \"{}\" must be a voltage source", dc.source))),
+                        None => return Err(location.into_error_named(format!("Unknown DC sweep source \"{}\"", dc.source))),
+                    }
+                },
+                Analysis::Noise(noise) =>
+                {
+                    match devices.iter().find(|d| d.name() == noise.input)
+                    {
+                        Some(Device::Voltage { .. }) => (),
+                        Some(_) => return Err(location.into_error_named(format!("Noise input \"{}\" must be a voltage source", noise.input))),
+                        None => return Err(location.into_error_named(format!("Unknown noise input source \"{}\"", noise.input))),
+                    }
+                },
+            }
+            checked_analyses.push(analysis);
+        }
+
+        Ok(Netlist{ devices, analyses: checked_analyses, parameters, steps: checked_steps, fourier: checked_fourier, measures: checked_measures, options, initial_conditions, nodesets })
     }
 }
 
@@ -139,7 +652,7 @@ fn parse_two_terminal(parser: &mut Parser, device_names: &mut HashSet<String>, n
 {
     let plus = parse_node(parser, device_names, node_names)?;
     let minus = parse_node(parser, device_names, node_names)?;
-    let value = Value::new(parser.expect_value()?);
+    let value = Value::parse(parser)?;
 
     Ok((plus, minus, value))
 }
@@ -153,19 +666,161 @@ fn parse_two_terminal_exp(parser: &mut Parser, device_names: &mut HashSet<String
     Ok((plus, minus, exp))
 }
 
-fn parse_node(parser: &mut Parser, device_names: &mut HashSet<String>, node_names: &mut HashSet<String>) -> Result<NodeName, ParseError>
+fn parse_temperature(parser: &mut Parser) -> Result<Scalar, ParseError>
 {
     let location = parser.cur_location();
+    let temperature = parser.expect_value()?;
 
-    let name = match parser.peek().clone()
+    if temperature <= -ZERO_CELSIUS
     {
-        Token::Integer(int) =>
+        return Err(location.into_error_named("Temperature must be above absolute zero".to_owned()));
+    }
+
+    Ok(temperature)
+}
+
+fn parse_temperature_coefficients(parser: &mut Parser) -> Result<(Scalar, Scalar), ParseError>
+{
+    // Optional "TC1=a TC2=b" or "TC=a[,b]"
+
+    let mut tc1 = 0.0;
+    let mut tc2 = 0.0;
+
+    while let Token::Ident(ident) = parser.peek()
+    {
+        match ident.to_uppercase().as_ref()
         {
-            parser.expect(TokenKind::Integer)?;
-            format!("{}", int)
+            "TC1" =>
+            {
+                parser.expect_ident()?;
+                parser.expect_symbol('=')?;
+                tc1 = parser.expect_value()?;
+            },
+            "TC2" =>
+            {
+                parser.expect_ident()?;
+                parser.expect_symbol('=')?;
+                tc2 = parser.expect_value()?;
+            },
+            "TC" =>
+            {
+                parser.expect_ident()?;
+                parser.expect_symbol('=')?;
+                tc1 = parser.expect_value()?;
+                if parser.is_symbol(',')
+                {
+                    parser.expect_symbol(',')?;
+                    tc2 = parser.expect_value()?;
+                }
+            },
+            _ => break,
+        }
+    }
+
+    Ok((tc1, tc2))
+}
+
+fn parse_ac_spec(parser: &mut Parser) -> Result<(Scalar, Scalar), ParseError>
+{
+    // Optional small-signal stimulus "AC magnitude [phase]"
+
+    if let Token::Ident(ident) = parser.peek().clone()
+    {
+        if ident.eq_ignore_ascii_case("AC")
+        {
+            parser.expect_ident()?;
+            let magnitude = parser.expect_value()?;
+            let phase = match parser.peek().kind()
+            {
+                TokenKind::Integer | TokenKind::Value | TokenKind::Symbol => parser.expect_value()?,
+                _ => 0.0,
+            };
+            return Ok((magnitude, phase));
+        }
+    }
+
+    Ok((0.0, 0.0))
+}
+
+fn parse_initial_condition(parser: &mut Parser) -> Result<Option<Scalar>, ParseError>
+{
+    // Optional "IC=value"
+
+    match parser.peek()
+    {
+        Token::Ident(ident) if ident.eq_ignore_ascii_case("IC") =>
+        {
+            parser.expect_ident()?;
+            parser.expect_symbol('=')?;
+            Ok(Some(parser.expect_value()?))
         },
-        _ => return Err(location.into_error_named("Expected node name".to_owned())),
+        _ => Ok(None),
+    }
+}
+
+fn parse_node_voltages(parser: &mut Parser, node_refs: &mut Vec<(NodeName, ParseLocation)>, voltages: &mut BTreeMap<String, Scalar>) -> Result<(), ParseError>
+{
+    // V(node)=value ...
+
+    while !parser.is_newline()
+    {
+        let location = parser.cur_location();
+        let (node, reference) = parse_voltage_output(parser, node_refs)?;
+        if (reference != NodeName::gnd()) || (node == NodeName::gnd())
+        {
+            return Err(location.into_error_named("Expected V(node) of a node other than the reference".to_owned()));
+        }
+
+        parser.expect_symbol('=')?;
+        voltages.insert(node.name().to_owned(), parser.expect_value()?);
+    }
+
+    Ok(())
+}
+
+fn parse_voltage_output(parser: &mut Parser, node_refs: &mut Vec<(NodeName, ParseLocation)>) -> Result<(NodeName, NodeName), ParseError>
+{
+    // V(node) or V(node,reference)
+
+    let location = parser.cur_location();
+    let kind = parser.expect_ident()?;
+    if !kind.eq_ignore_ascii_case("V")
+    {
+        return Err(location.into_error_named("Expected voltage output V(node[,reference])".to_owned()));
+    }
+
+    parser.expect_symbol('(')?;
+    let output = parse_node_ref(parser, node_refs)?;
+    let reference = if parser.is_symbol(',')
+    {
+        parser.expect_symbol(',')?;
+        parse_node_ref(parser, node_refs)?
+    }
+    else
+    {
+        NodeName::gnd()
     };
+    parser.expect_symbol(')')?;
+
+    Ok((output, reference))
+}
+
+fn parse_node_ref(parser: &mut Parser, node_refs: &mut Vec<(NodeName, ParseLocation)>) -> Result<NodeName, ParseError>
+{
+    // A reference to a node from a command -
+    // checked once all devices have been parsed
+
+    let location = parser.cur_location();
+    let node = NodeName::new(parser.expect_node_name()?);
+    node_refs.push((node.clone(), location));
+    Ok(node)
+}
+
+fn parse_node(parser: &mut Parser, device_names: &mut HashSet<String>, node_names: &mut HashSet<String>) -> Result<NodeName, ParseError>
+{
+    let location = parser.cur_location();
+
+    let name = parser.expect_node_name()?;
 
     node_names.insert(name.clone());
 
@@ -175,4 +830,48 @@ fn parse_node(parser: &mut Parser, device_names: &mut HashSet<String>, node_name
     }
 
     Ok(NodeName::new(name))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Distribution;
+    use crate::sim::analysis::{self, AnalysisResults};
+
+    #[test]
+    fn named_nodes()
+    {
+        let netlist = "V1 in 0 2\nR1 in out 1k\nR2 out 0 1k\n.NODESET V(out)=1\n.FOUR 1k V(in,out)\n.OP\n.TRAN 1u 1m".parse::<Netlist>().unwrap();
+        assert_eq!(netlist.nodes(), HashSet::from(["0", "in", "out"].map(|name| NodeName::new(name.to_owned()))));
+        assert_eq!(netlist.nodesets()["out"], 1.0);
+        assert_eq!(netlist.fourier()[0].outputs, vec![(NodeName::new("in".to_owned()), NodeName::new("out".to_owned()))]);
+
+        let AnalysisResults::Op(values) = analysis::run(&netlist, &Analysis::Op).unwrap() else { panic!("operating point") };
+        assert!((values["V_out"] - 1.0).abs() < 1e-9);
+
+        // Numbers and names mix, but commands can only
+        // refer to nodes that a device connects to
+        assert!("V1 in 0 1\nR1 in 2 1k\nR2 2 0 1k".parse::<Netlist>().is_ok());
+        assert!("V1 in 0 1\nR1 in 0 1k\n.IC V(out)=1".parse::<Netlist>().is_err());
+        assert!("V1 in 0 1\nR1 in V1 1k".parse::<Netlist>().is_err());
+        assert!("V1 in 0 1\nR1 in 1.5 1k".parse::<Netlist>().is_err());
+    }
+
+    #[test]
+    fn deviations_keep_tolerance()
+    {
+        let netlist = "V1 1 0 1\nR1 1 2 {rload} tol=5%\nR2 2 0 1k tol=1% dist=uniform\n.PARAM rload=2k".parse::<Netlist>().unwrap();
+        let deviated = netlist.with_deviations(&BTreeMap::from([("R1".to_owned(), 0.05), ("R2".to_owned(), -0.01)]));
+
+        let value = |netlist: &Netlist, name: &str| netlist.device(name).unwrap().value().unwrap().clone();
+        assert!((value(&deviated, "R1").value() - 2100.0).abs() < 1e-9);
+        assert!((value(&deviated, "R2").value() - 990.0).abs() < 1e-9);
+
+        // So a trial can be deviated or stepped again
+        assert_eq!(deviated.toleranced_devices(), ["R1", "R2"]);
+        assert_eq!(value(&deviated, "R2").tolerance().unwrap().distribution, Distribution::Uniform);
+        assert_eq!(value(&deviated, "R1").parameter(), Some("rload"));
+        assert_eq!(value(&deviated.with_parameter("rload", 3e3), "R1").value(), 3e3);
+    }
+}
@@ -18,6 +18,7 @@ impl NodeName
         NodeName { name }
     }
 
+    #[allow(clippy::needless_lifetimes)]
     pub fn name<'a>(&'a self) -> &'a str
     {
         &self.name
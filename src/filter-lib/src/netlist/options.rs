@@ -0,0 +1,219 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use crate::la::SolverBackend;
+use super::{IntegrationMethod, Scalar, DEFAULT_TEMPERATURE};
+use super::parser::{Parser, ParseError};
+use crate::sim::ZERO_CELSIUS;
+
+/// Simulator settings - from `.OPTIONS` and `.TEMP` in a
+/// netlist, or set here and given to `Netlist::with_options`.
+/// Defaults are the usual SPICE ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationOptions
+{
+    /// Newton-Raphson converges when every unknown changes by
+    /// less than RELTOL of its value, plus VNTOL (V) for
+    /// voltages or ABSTOL (A) for currents
+    pub reltol: Scalar,
+    pub abstol: Scalar,
+    pub vntol: Scalar,
+    /// Conductance across every junction, so reverse biased
+    /// diodes never leave a node floating (S)
+    pub gmin: Scalar,
+    /// Newton-Raphson iterations allowed for an operating point
+    pub itl1: usize,
+    /// Newton-Raphson iterations allowed per transient step
+    pub itl4: usize,
+    pub method: IntegrationMethod,
+    /// Circuit temperature (Celsius)
+    pub temperature: Scalar,
+    /// Temperature model parameters were measured at (Celsius)
+    pub nominal_temperature: Scalar,
+    pub backend: SolverBackend,
+    /// Longest step an adaptive transient may take (s)
+    pub max_step: Option<Scalar>,
+    /// Significant digits when results are written out
+    pub precision: usize,
+}
+
+/// An option that's out of range
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionError
+{
+    pub option: &'static str,
+    pub reason: String,
+}
+
+impl Default for SimulationOptions
+{
+    fn default() -> Self
+    {
+        SimulationOptions
+        {
+            reltol: 1e-3,
+            abstol: 1e-12,
+            vntol: 1e-6,
+            gmin: 1e-12,
+            itl1: 200,
+            itl4: 100,
+            method: IntegrationMethod::default(),
+            temperature: DEFAULT_TEMPERATURE,
+            nominal_temperature: DEFAULT_TEMPERATURE,
+            backend: SolverBackend::default(),
+            max_step: None,
+            precision: 6,
+        }
+    }
+}
+
+impl SimulationOptions
+{
+    pub fn validate(&self) -> Result<(), OptionError>
+    {
+        let error = |option, reason: &str| Err(OptionError { option, reason: reason.to_owned() });
+        let positive = |value: Scalar| value.is_finite() && (value > 0.0);
+        let above_zero_kelvin = |celsius: Scalar| celsius.is_finite() && (celsius > -ZERO_CELSIUS);
+
+        if !(positive(self.reltol) && (self.reltol < 1.0))
+        {
+            return error("RELTOL", "must be between 0 and 1 - e.g. 1e-3 for 0.1%");
+        }
+        if !positive(self.abstol)
+        {
+            return error("ABSTOL", "must be a positive current");
+        }
+        if !positive(self.vntol)
+        {
+            return error("VNTOL", "must be a positive voltage");
+        }
+        if !(self.gmin.is_finite() && (self.gmin >= 0.0))
+        {
+            return error("GMIN", "must be a conductance of zero or more");
+        }
+        if self.itl1 == 0
+        {
+            return error("ITL1", "must allow at least one iteration");
+        }
+        if self.itl4 == 0
+        {
+            return error("ITL4", "must allow at least one iteration");
+        }
+        if !above_zero_kelvin(self.temperature)
+        {
+            return error("TEMP", "must be above absolute zero (-273.15)");
+        }
+        if !above_zero_kelvin(self.nominal_temperature)
+        {
+            return error("TNOM", "must be above absolute zero (-273.15)");
+        }
+        if self.max_step.is_some_and(|step| !positive(step))
+        {
+            return error("MAXSTEP", "must be a positive time");
+        }
+        if !(1..=17).contains(&self.precision)
+        {
+            return error("NUMDGT", "must be from 1 to 17 digits");
+        }
+        Ok(())
+    }
+
+    /// A value to the output precision
+    pub fn format(&self, value: Scalar) -> String
+    {
+        format!("{:.*e}", self.precision - 1, value)
+    }
+
+    /// Parses one `NAME=value` of an `.OPTIONS` card
+    pub fn parse_option(&mut self, parser: &mut Parser) -> Result<(), ParseError>
+    {
+        let location = parser.cur_location();
+        let option = parser.expect_ident()?.to_uppercase();
+        parser.expect_symbol('=')?;
+        let value_location = parser.cur_location();
+
+        let count = |parser: &mut Parser| -> Result<usize, ParseError>
+        {
+            let location = parser.cur_location();
+            let value = parser.expect_value()?;
+            if (value.fract() != 0.0) || (value < 0.0) || (value > u32::MAX as Scalar)
+            {
+                return Err(location.into_error_named(format!("{} must be a whole number", option)));
+            }
+            Ok(value as usize)
+        };
+
+        match option.as_ref()
+        {
+            "RELTOL" => self.reltol = parser.expect_value()?,
+            "ABSTOL" => self.abstol = parser.expect_value()?,
+            "VNTOL" => self.vntol = parser.expect_value()?,
+            "GMIN" => self.gmin = parser.expect_value()?,
+            "ITL1" => self.itl1 = count(parser)?,
+            "ITL4" => self.itl4 = count(parser)?,
+            "METHOD" => self.method = IntegrationMethod::parse(parser)?,
+            "TEMP" => self.temperature = parser.expect_value()?,
+            "TNOM" => self.nominal_temperature = parser.expect_value()?,
+            "SOLVER" =>
+            {
+                let solver = parser.expect_ident()?.to_uppercase();
+                self.backend = match solver.as_ref()
+                {
+                    "DENSE" => SolverBackend::Dense,
+                    "SPARSE" => SolverBackend::Sparse,
+                    _ => return Err(value_location.into_error_named(format!("Unknown solver \"{}\" - expected DENSE or SPARSE", solver))),
+                };
+            },
+            "MAXSTEP" => self.max_step = Some(parser.expect_value()?),
+            "NUMDGT" => self.precision = count(parser)?,
+            _ => return Err(location.into_error_named(format!(
+                "Unknown option \"{}\" - expected RELTOL, ABSTOL, VNTOL, GMIN, ITL1, ITL4, METHOD, TEMP, TNOM, SOLVER, MAXSTEP or NUMDGT", option))),
+        }
+
+        self.validate().map_err(|error| value_location.into_error_named(error.to_string()))
+    }
+}
+
+impl Display for OptionError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult
+    {
+        write!(f, "{} {}", self.option, self.reason)
+    }
+}
+
+impl std::error::Error for OptionError
+{
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Netlist;
+
+    #[test]
+    fn parse_and_validate()
+    {
+        let netlist = "R1 1 0 1k\n.OPTIONS RELTOL=1e-4 ITL4=50 METHOD=GEAR SOLVER=SPARSE MAXSTEP=1u NUMDGT=8\n.TEMP 50".parse::<Netlist>().unwrap();
+        let options = netlist.options();
+        assert_eq!(options.reltol, 1e-4);
+        assert_eq!(options.itl4, 50);
+        assert_eq!(options.method, IntegrationMethod::Gear);
+        assert_eq!(options.backend, SolverBackend::Sparse);
+        assert_eq!(options.max_step, Some(1e-6));
+        assert_eq!(options.temperature, 50.0);
+        assert_eq!(options.format(1.0 / 3.0), "3.3333333e-1");
+
+        for (card, message) in [("RELTOL=2", "RELTOL must be between 0 and 1"), ("ITL1=1.5", "ITL1 must be a whole number"), ("SOLVER=LU", "Unknown solver"), ("RTOL=1", "Unknown option")]
+        {
+            let error = format!("R1 1 0 1k\n.OPTIONS {}", card).parse::<Netlist>().unwrap_err();
+            assert!(format!("{:?}", error).contains(message));
+        }
+
+        let options = SimulationOptions { gmin: -1.0, ..SimulationOptions::default() };
+        assert_eq!(netlist.with_options(options).unwrap_err().option, "GMIN");
+        assert_eq!(netlist.with_temperature(-300.0).unwrap_err().option, "TEMP");
+        assert_eq!(netlist.with_temperature(-40.0).unwrap().options().temperature, -40.0);
+        assert_eq!(netlist.with_method(IntegrationMethod::BackwardEuler).unwrap().options().method, IntegrationMethod::BackwardEuler);
+        assert!("R1 1 0 1k\n.STEP TEMP LIST 27 -300".parse::<Netlist>().is_err());
+    }
+}
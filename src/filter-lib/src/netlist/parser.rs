@@ -4,6 +4,7 @@ pub enum TokenKind
     Integer,
     Value,
     Ident,
+    String,
     Symbol,
     Newline,
 }
@@ -14,6 +15,8 @@ pub enum Token
     Integer(usize),
     Value(f64),
     Ident(String),
+    /// Between double quotes, e.g. a file name
+    String(String),
     Symbol(char),
     Newline,
 }
@@ -27,6 +30,7 @@ impl Token
             Token::Integer(_) => TokenKind::Integer,
             Token::Value(_) => TokenKind::Value,
             Token::Ident(_) => TokenKind::Ident,
+            Token::String(_) => TokenKind::String,
             Token::Symbol(_) => TokenKind::Symbol,
             Token::Newline => TokenKind::Newline,
         }
@@ -41,7 +45,7 @@ pub enum ParseErrorCondition
     Io(std::io::Error),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseLocation
 {
     line_num: usize,
@@ -61,6 +65,7 @@ impl ParseLocation
         self.column
     }
 
+    #[allow(clippy::needless_lifetimes)]
     pub fn line<'a>(&'a self) -> &'a str
     {
         &self.line
@@ -139,11 +144,22 @@ impl Parser
         Ok(token)
     }
 
+    #[allow(clippy::needless_lifetimes)]
     pub fn peek<'a>(&'a self) -> &'a Token
     {
         &self.cur_line_tokens[self.cur_token]
     }
 
+    pub fn is_symbol(&self, symbol: char) -> bool
+    {
+        matches!(self.peek(), Token::Symbol(actual) if *actual == symbol)
+    }
+
+    pub fn is_newline(&self) -> bool
+    {
+        self.peek().kind() == TokenKind::Newline
+    }
+
     pub fn expect_ident(&mut self) -> Result<String, ParseError>
     {
         match self.peek().clone()
@@ -157,7 +173,54 @@ impl Parser
         }
     }
 
+    pub fn expect_string(&mut self) -> Result<String, ParseError>
+    {
+        match self.peek().clone()
+        {
+            Token::String(string) =>
+            {
+                self.advance();
+                Ok(string)
+            },
+            Token::Symbol('"') => Err(self.create_error_named("Unterminated quoted string".to_owned())),
+            _ => Err(self.create_error_named("Expected quoted string".to_owned()))
+        }
+    }
+
+    /// A number such as "2", or a name such as "in"
+    pub fn expect_node_name(&mut self) -> Result<String, ParseError>
+    {
+        match self.peek().clone()
+        {
+            Token::Integer(int) =>
+            {
+                self.advance();
+                Ok(format!("{}", int))
+            },
+            Token::Ident(ident) =>
+            {
+                self.advance();
+                Ok(ident)
+            },
+            _ => Err(self.create_error_named("Expected node name".to_owned()))
+        }
+    }
+
     pub fn expect_value(&mut self) -> Result<f64, ParseError>
+    {
+        if self.is_symbol('-')
+        {
+            self.advance();
+            return self.expect_unsigned_value().map(|v| -v);
+        }
+        if self.is_symbol('+')
+        {
+            self.advance();
+        }
+        self.expect_unsigned_value()
+    }
+
+    fn expect_unsigned_value(&mut self) -> Result<f64, ParseError>
     {
         match self.peek().clone()
         {
@@ -177,21 +240,26 @@ impl Parser
 
     pub fn expect_symbol(&mut self, symbol: char) -> Result<(), ParseError>
     {
-        match self.peek().clone()
+        if let Token::Symbol(actual) = self.peek().clone()
         {
-            Token::Symbol(actual) =>
+            if actual == symbol
             {
-                if actual == symbol
-                {
-                    self.advance();
-                    return Ok(())
-                }
-            },
-            _ => (),
+                self.advance();
+                return Ok(())
+            }
         }
         Err(self.create_error_named(format!("Expected '{}'", symbol)))
     }
 
+    pub fn skip_line(&mut self)
+    {
+        while !self.is_newline()
+        {
+            self.advance();
+        }
+        self.advance();
+    }
+
     pub fn cur_location(&self) -> ParseLocation
     {
         ParseLocation
@@ -270,25 +338,73 @@ fn tokenize_line(line: &str) -> (Vec<Token>, Vec<usize>)
                 i += 1;
             }
 
+            let mut is_integer = true;
+
             if (i < chars.len()) && (chars[i] == '.')
             {
                 num.push(chars[i]);
                 i += 1;
+                is_integer = false;
 
                 while (i < chars.len()) && chars[i].is_numeric()
                 {
                     num.push(chars[i]);
                     i += 1;
                 }
+            }
 
-                tokens.push(Token::Value(num.parse().unwrap()));
-                indexes.push(start_index);
+            // Optional exponent - only if it's
+            // followed by (signed) digits, otherwise
+            // the 'e' is treated as a unit below
+
+            if (i < chars.len()) && ((chars[i] == 'e') || (chars[i] == 'E'))
+            {
+                let mut j = i + 1;
+                if (j < chars.len()) && ((chars[j] == '+') || (chars[j] == '-'))
+                {
+                    j += 1;
+                }
+                if (j < chars.len()) && chars[j].is_ascii_digit()
+                {
+                    num.extend(&chars[i..j]);
+                    i = j;
+                    is_integer = false;
+
+                    while (i < chars.len()) && chars[i].is_ascii_digit()
+                    {
+                        num.push(chars[i]);
+                        i += 1;
+                    }
+                }
             }
-            else // integer
+
+            // Optional engineering multiplier and units,
+            // e.g. "10k", "4.7uF" or "1meg"
+
+            let mut scale = 1.0;
+
+            if (i < chars.len()) && chars[i].is_alphabetic()
+            {
+                let mut suffix = String::new();
+                while (i < chars.len()) && chars[i].is_alphabetic()
+                {
+                    suffix.push(chars[i].to_ascii_lowercase());
+                    i += 1;
+                }
+                scale = suffix_scale(&suffix);
+                is_integer = false;
+            }
+
+            if is_integer
             {
                 tokens.push(Token::Integer(num.parse().unwrap()));
                 indexes.push(start_index);
             }
+            else
+            {
+                tokens.push(Token::Value(num.parse::<f64>().unwrap() * scale));
+                indexes.push(start_index);
+            }
         }
         else if start.is_alphabetic()
         {
@@ -296,7 +412,7 @@ fn tokenize_line(line: &str) -> (Vec<Token>, Vec<usize>)
             ident.push(start);
             i += 1;
 
-            while (i < chars.len()) && chars[i].is_alphanumeric()
+            while (i < chars.len()) && (chars[i].is_alphanumeric() || (chars[i] == '_'))
             {
                 ident.push(chars[i]);
                 i += 1;
@@ -305,6 +421,27 @@ fn tokenize_line(line: &str) -> (Vec<Token>, Vec<usize>)
             tokens.push(Token::Ident(ident));
             indexes.push(start_index);
         }
+        else if start == '"'
+        {
+            // No escapes - an unterminated string is left
+            // as a lone quote symbol, which nothing accepts
+
+            let end = chars[i + 1..].iter().position(|c| *c == '"').map(|len| i + 1 + len);
+            match end
+            {
+                Some(end) =>
+                {
+                    tokens.push(Token::String(chars[i + 1..end].iter().collect()));
+                    i = end + 1;
+                },
+                None =>
+                {
+                    tokens.push(Token::Symbol('"'));
+                    i = chars.len();
+                },
+            }
+            indexes.push(start_index);
+        }
         else
         {
             i += 1;
@@ -318,4 +455,32 @@ fn tokenize_line(line: &str) -> (Vec<Token>, Vec<usize>)
     indexes.push(i);
 
     (tokens, indexes)
-}
\ No newline at end of file
+}
+
+fn suffix_scale(suffix: &str) -> f64
+{
+    // Standard SPICE multipliers - any
+    // trailing letters are units and ignored
+
+    if suffix.starts_with("meg")
+    {
+        return 1e6;
+    }
+    if suffix.starts_with("mil")
+    {
+        return 25.4e-6;
+    }
+
+    match suffix.chars().next()
+    {
+        Some('t') => 1e12,
+        Some('g') => 1e9,
+        Some('k') => 1e3,
+        Some('m') => 1e-3,
+        Some('u') => 1e-6,
+        Some('n') => 1e-9,
+        Some('p') => 1e-12,
+        Some('f') => 1e-15,
+        _ => 1.0,
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use super::{NodeName, ParseError, Scalar};
+use super::parser::{Parser, Token, TokenKind};
+
+/// A signal to record from a simulation - `V(2)`, `V(2,3)`,
+/// `I(R1)`, `P(D1)`, or an expression of them using + - * /
+/// and brackets, e.g. `V(4)/V(1)` or `(P(R1)+P(R2))*1000`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Probe
+{
+    Voltage{node: NodeName, reference: NodeName},
+    /// Current through a device, from plus to minus - except
+    /// voltage sources, where it's the current delivered
+    /// out of the plus terminal (as in "I_V1")
+    Current(String),
+    /// Power absorbed by a device
+    Power(String),
+    Value(Scalar),
+    Negate(Box<Probe>),
+    Sum(Box<Probe>, Box<Probe>),
+    Difference(Box<Probe>, Box<Probe>),
+    Product(Box<Probe>, Box<Probe>),
+    Quotient(Box<Probe>, Box<Probe>),
+}
+
+/// Units as powers of volts and amps - enough for
+/// anything built from voltages, currents and powers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Unit
+{
+    pub volts: i32,
+    pub amps: i32,
+}
+
+impl Unit
+{
+    pub const NONE: Unit = Unit { volts: 0, amps: 0 };
+    pub const VOLTS: Unit = Unit { volts: 1, amps: 0 };
+    pub const AMPS: Unit = Unit { volts: 0, amps: 1 };
+    pub const WATTS: Unit = Unit { volts: 1, amps: 1 };
+
+    fn times(self, other: Unit) -> Unit
+    {
+        Unit { volts: self.volts + other.volts, amps: self.amps + other.amps }
+    }
+
+    fn divided_by(self, other: Unit) -> Unit
+    {
+        Unit { volts: self.volts - other.volts, amps: self.amps - other.amps }
+    }
+}
+
+impl Display for Unit
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult
+    {
+        match (self.volts, self.amps)
+        {
+            (0, 0) => Ok(()),
+            (1, 0) => write!(f, "V"),
+            (0, 1) => write!(f, "A"),
+            (1, 1) => write!(f, "W"),
+            (1, -1) => write!(f, "Ohm"),
+            (-1, 1) => write!(f, "S"),
+            (volts, amps) =>
+            {
+                let power = |f: &mut Formatter<'_>, name: &str, power: i32| match power
+                {
+                    0 => Ok(()),
+                    1 => write!(f, "{}", name),
+                    _ => write!(f, "{}^{}", name, power),
+                };
+                power(f, "V", volts)?;
+                if (volts != 0) && (amps != 0)
+                {
+                    write!(f, ".")?;
+                }
+                power(f, "A", amps)
+            },
+        }
+    }
+}
+
+/// Reads back what `Display` writes
+impl FromStr for Unit
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "" => return Ok(Unit::NONE),
+            "Ohm" => return Ok(Unit { volts: 1, amps: -1 }),
+            "S" => return Ok(Unit { volts: -1, amps: 1 }),
+            "W" => return Ok(Unit::WATTS),
+            _ => (),
+        }
+
+        let mut unit = Unit::NONE;
+        for part in s.split('.')
+        {
+            let (name, power) = match part.split_once('^')
+            {
+                Some((name, power)) => (name, power.parse::<i32>().map_err(|_| format!("Invalid power in unit \"{}\"", s))?),
+                None => (part, 1),
+            };
+            match name
+            {
+                "V" => unit.volts = power,
+                "A" => unit.amps = power,
+                _ => return Err(format!("Unknown unit \"{}\"", s)),
+            }
+        }
+        Ok(unit)
+    }
+}
+
+impl Probe
+{
+    pub fn parse(parser: &mut Parser) -> Result<Probe, ParseError>
+    {
+        let location = parser.cur_location();
+        let mut result = Probe::parse_term(parser)?;
+
+        while parser.is_symbol('+') || parser.is_symbol('-')
+        {
+            let add = parser.is_symbol('+');
+            parser.expect(TokenKind::Symbol)?;
+            let other = Probe::parse_term(parser)?;
+
+            // A constant can be added to anything,
+            // otherwise the units must match
+            if !matches!(result, Probe::Value(_)) && !matches!(other, Probe::Value(_)) && (result.unit() != other.unit())
+            {
+                return Err(location.into_error_named(format!("Can't add or subtract \"{}\" and \"{}\" - the units are different", result, other)));
+            }
+
+            result = if add
+            {
+                Probe::Sum(Box::new(result), Box::new(other))
+            }
+            else
+            {
+                Probe::Difference(Box::new(result), Box::new(other))
+            };
+        }
+
+        Ok(result)
+    }
+
+    fn parse_term(parser: &mut Parser) -> Result<Probe, ParseError>
+    {
+        let mut result = Probe::parse_factor(parser)?;
+
+        while parser.is_symbol('*') || parser.is_symbol('/')
+        {
+            let multiply = parser.is_symbol('*');
+            parser.expect(TokenKind::Symbol)?;
+            let other = Probe::parse_factor(parser)?;
+
+            result = if multiply
+            {
+                Probe::Product(Box::new(result), Box::new(other))
+            }
+            else
+            {
+                Probe::Quotient(Box::new(result), Box::new(other))
+            };
+        }
+
+        Ok(result)
+    }
+
+    fn parse_factor(parser: &mut Parser) -> Result<Probe, ParseError>
+    {
+        let location = parser.cur_location();
+
+        match parser.peek().clone()
+        {
+            Token::Integer(_) | Token::Value(_) =>
+            {
+                Ok(Probe::Value(parser.expect_value()?))
+            },
+            Token::Symbol('-') =>
+            {
+                parser.expect_symbol('-')?;
+                Ok(Probe::Negate(Box::new(Probe::parse_factor(parser)?)))
+            },
+            Token::Symbol('(') =>
+            {
+                parser.expect_symbol('(')?;
+                let result = Probe::parse(parser)?;
+                parser.expect_symbol(')')?;
+                Ok(result)
+            },
+            Token::Ident(ident) =>
+            {
+                parser.expect_ident()?;
+                parser.expect_symbol('(')?;
+
+                let result = match ident.to_uppercase().as_ref()
+                {
+                    "V" =>
+                    {
+                        let node = parse_node(parser)?;
+                        let reference = if parser.is_symbol(',')
+                        {
+                            parser.expect_symbol(',')?;
+                            parse_node(parser)?
+                        }
+                        else
+                        {
+                            NodeName::gnd()
+                        };
+                        Probe::Voltage { node, reference }
+                    },
+                    "I" => Probe::Current(parser.expect_ident()?),
+                    "P" => Probe::Power(parser.expect_ident()?),
+                    _ => return Err(location.into_error_named(format!("Unknown probe \"{}\" - expected V, I or P", ident))),
+                };
+
+                parser.expect_symbol(')')?;
+                Ok(result)
+            },
+            _ => Err(location.into_error_named("Expected a probe V(node[,reference]), I(device), P(device) or value".to_owned())),
+        }
+    }
+
+    pub fn unit(&self) -> Unit
+    {
+        match self
+        {
+            Probe::Voltage { .. } => Unit::VOLTS,
+            Probe::Current(_) => Unit::AMPS,
+            Probe::Power(_) => Unit::WATTS,
+            Probe::Value(_) => Unit::NONE,
+            Probe::Negate(probe) => probe.unit(),
+            Probe::Sum(a, b) | Probe::Difference(a, b) => match **a
+            {
+                Probe::Value(_) => b.unit(),
+                _ => a.unit(),
+            },
+            Probe::Product(a, b) => a.unit().times(b.unit()),
+            Probe::Quotient(a, b) => a.unit().divided_by(b.unit()),
+        }
+    }
+
+    /// The V, I and P terms, in the order
+    /// `evaluate` asks for their values
+    pub fn terms(&self) -> Vec<&Probe>
+    {
+        match self
+        {
+            Probe::Voltage { .. } | Probe::Current(_) | Probe::Power(_) => vec![self],
+            Probe::Value(_) => Vec::new(),
+            Probe::Negate(probe) => probe.terms(),
+            Probe::Sum(a, b) | Probe::Difference(a, b) | Probe::Product(a, b) | Probe::Quotient(a, b) =>
+            {
+                let mut result = a.terms();
+                result.extend(b.terms());
+                result
+            },
+        }
+    }
+
+    /// Value of the expression, given the value of each
+    /// V, I and P term in turn
+    pub fn evaluate(&self, term: &mut impl FnMut() -> Scalar) -> Scalar
+    {
+        match self
+        {
+            Probe::Voltage { .. } | Probe::Current(_) | Probe::Power(_) => term(),
+            Probe::Value(value) => *value,
+            Probe::Negate(probe) => -probe.evaluate(term),
+            Probe::Sum(a, b) => a.evaluate(term) + b.evaluate(term),
+            Probe::Difference(a, b) => a.evaluate(term) - b.evaluate(term),
+            Probe::Product(a, b) => a.evaluate(term) * b.evaluate(term),
+            Probe::Quotient(a, b) => a.evaluate(term) / b.evaluate(term),
+        }
+    }
+
+    fn is_sum(&self) -> bool
+    {
+        matches!(self, Probe::Sum(..) | Probe::Difference(..))
+    }
+
+    fn is_product(&self) -> bool
+    {
+        matches!(self, Probe::Product(..) | Probe::Quotient(..))
+    }
+}
+
+pub fn parse_node(parser: &mut Parser) -> Result<NodeName, ParseError>
+{
+    Ok(NodeName::new(parser.expect_node_name()?))
+}
+
+impl FromStr for Probe
+{
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut parser = Parser::new(s.to_owned());
+        let probe = Probe::parse(&mut parser)?;
+        parser.expect(TokenKind::Newline)?;
+        Ok(probe)
+    }
+}
+
+impl Display for Probe
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult
+    {
+        // Brackets only where precedence needs them
+        let bracketed = |f: &mut Formatter<'_>, probe: &Probe, needed: bool| if needed
+        {
+            write!(f, "({})", probe)
+        }
+        else
+        {
+            write!(f, "{}", probe)
+        };
+
+        match self
+        {
+            Probe::Voltage { node, reference } if *reference == NodeName::gnd() => write!(f, "V({})", node),
+            Probe::Voltage { node, reference } => write!(f, "V({},{})", node, reference),
+            Probe::Current(device) => write!(f, "I({})", device),
+            Probe::Power(device) => write!(f, "P({})", device),
+            Probe::Value(value) => write!(f, "{}", value),
+            Probe::Negate(probe) =>
+            {
+                write!(f, "-")?;
+                bracketed(f, probe, probe.is_sum() || probe.is_product())
+            },
+            Probe::Sum(a, b) =>
+            {
+                write!(f, "{}+", a)?;
+                bracketed(f, b, false)
+            },
+            Probe::Difference(a, b) =>
+            {
+                write!(f, "{}-", a)?;
+                bracketed(f, b, b.is_sum())
+            },
+            Probe::Product(a, b) =>
+            {
+                bracketed(f, a, a.is_sum())?;
+                write!(f, "*")?;
+                bracketed(f, b, b.is_sum())
+            },
+            Probe::Quotient(a, b) =>
+            {
+                bracketed(f, a, a.is_sum())?;
+                write!(f, "/")?;
+                bracketed(f, b, b.is_sum() || b.is_product())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_and_display()
+    {
+        for (text, name, unit) in [("v(2)", "V(2)", "V"), ("V(2, 3)", "V(2,3)", "V"), ("i(R1)*1k", "I(R1)*1000", "A"),
+                                   ("P(D1)", "P(D1)", "W"), ("V(2)/I(R1)", "V(2)/I(R1)", "Ohm"), ("(V(1)-V(2))*I(R1)", "(V(1)-V(2))*I(R1)", "W"),
+                                   ("V(1)-(V(2)-V(3))", "V(1)-(V(2)-V(3))", "V"), ("-V(1)*V(1)", "-V(1)*V(1)", "V^2"),
+                                   ("V(in, out)", "V(in,out)", "V")]
+        {
+            let probe = text.parse::<Probe>().unwrap();
+            assert_eq!(probe.to_string(), name);
+            assert_eq!(probe.unit().to_string(), unit);
+            assert_eq!(name.parse::<Probe>().unwrap(), probe);
+            assert_eq!(unit.parse::<Unit>().unwrap(), probe.unit());
+        }
+
+        assert!("V(1)+I(R1)".parse::<Probe>().is_err());
+        assert!("X(1)".parse::<Probe>().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use super::Scalar;
+use super::parser::{Parser, ParseError, Token};
+
+#[derive(Debug, Clone)]
+pub enum Sweep
+{
+    Linear{start: Scalar, stop: Scalar, points: usize},
+    Decade{start: Scalar, stop: Scalar, points_per_decade: usize},
+    Octave{start: Scalar, stop: Scalar, points_per_octave: usize},
+    List(Vec<Scalar>),
+}
+
+impl Sweep
+{
+    pub fn values(&self) -> Vec<Scalar>
+    {
+        match self
+        {
+            Sweep::Linear { start, stop, points } =>
+            {
+                match *points
+                {
+                    0 => Vec::new(),
+                    1 => vec![*start],
+                    _ => (0..*points)
+                        .map(|i| start + (stop - start) * (i as Scalar) / ((points - 1) as Scalar))
+                        .collect(),
+                }
+            },
+            Sweep::Decade { start, stop, points_per_decade } =>
+            {
+                log_values(*start, *stop, 10.0, *points_per_decade)
+            },
+            Sweep::Octave { start, stop, points_per_octave } =>
+            {
+                log_values(*start, *stop, 2.0, *points_per_octave)
+            },
+            Sweep::List(values) => values.clone(),
+        }
+    }
+
+    /// Parses the SPICE frequency-sweep form
+    /// `DEC|OCT|LIN points start stop`, as used
+    /// by the `.AC` and `.NOISE` cards.
+    pub fn parse_points(parser: &mut Parser) -> Result<Sweep, ParseError>
+    {
+        let location = parser.cur_location();
+        let kind = parser.expect_ident()?.to_uppercase();
+        let points = parse_count(parser)?;
+        let start = parser.expect_value()?;
+        let stop = parser.expect_value()?;
+
+        let sweep = match kind.as_ref()
+        {
+            "LIN" => Sweep::Linear { start, stop, points },
+            "DEC" => Sweep::Decade { start, stop, points_per_decade: points },
+            "OCT" => Sweep::Octave { start, stop, points_per_octave: points },
+            _ => return Err(location.into_error_named(format!("Unknown sweep type \"{}\" - expected DEC, OCT or LIN", kind))),
+        };
+
+        if (kind != "LIN") && ((start <= 0.0) || (stop <= 0.0))
+        {
+            return Err(location.into_error_named("Logarithmic sweeps must have positive start and stop values".to_owned()));
+        }
+
+        Ok(sweep)
+    }
+
+    /// Parses the SPICE range form `LIN start stop increment`,
+    /// `DEC|OCT start stop points` or `LIST value...`, as
+    /// used by the `.STEP` card.
+    pub fn parse_range(parser: &mut Parser) -> Result<Sweep, ParseError>
+    {
+        let location = parser.cur_location();
+        let kind = parser.expect_ident()?.to_uppercase();
+
+        if kind == "LIST"
+        {
+            let mut values = Vec::new();
+            while !parser.is_newline()
+            {
+                values.push(parser.expect_value()?);
+            }
+            if values.is_empty()
+            {
+                return Err(location.into_error_named("Expected at least one value".to_owned()));
+            }
+            return Ok(Sweep::List(values));
+        }
+
+        if kind == "LIN"
+        {
+            return Sweep::parse_increment(parser);
+        }
+
+        let start = parser.expect_value()?;
+        let stop = parser.expect_value()?;
+
+        match kind.as_ref()
+        {
+            "DEC" | "OCT" =>
+            {
+                let points = parse_count(parser)?;
+                if (start <= 0.0) || (stop <= 0.0)
+                {
+                    return Err(location.into_error_named("Logarithmic sweeps must have positive start and stop values".to_owned()));
+                }
+                Ok(match kind.as_ref()
+                {
+                    "DEC" => Sweep::Decade { start, stop, points_per_decade: points },
+                    _ => Sweep::Octave { start, stop, points_per_octave: points },
+                })
+            },
+            _ => Err(location.into_error_named(format!("Unknown sweep type \"{}\" - expected LIST, LIN, DEC or OCT", kind))),
+        }
+    }
+
+    /// Parses the linear form `start stop increment`, as used by `.DC`
+    pub fn parse_increment(parser: &mut Parser) -> Result<Sweep, ParseError>
+    {
+        let start = parser.expect_value()?;
+        let stop = parser.expect_value()?;
+        let location = parser.cur_location();
+        let increment = parser.expect_value()?;
+
+        if (increment == 0.0) || ((stop - start) / increment < 0.0)
+        {
+            return Err(location.into_error_named("Increment must step from start towards stop".to_owned()));
+        }
+
+        let points = ((stop - start) / increment + 1e-9).floor() as usize + 1;
+        Ok(Sweep::Linear { start, stop: start + increment * ((points - 1) as Scalar), points })
+    }
+}
+
+fn parse_count(parser: &mut Parser) -> Result<usize, ParseError>
+{
+    let location = parser.cur_location();
+    match parser.peek().clone()
+    {
+        Token::Integer(count) if count > 0 =>
+        {
+            let _ = parser.expect_value()?;
+            Ok(count)
+        },
+        _ => Err(location.into_error_named("Expected a positive number of points".to_owned())),
+    }
+}
+
+fn log_values(start: Scalar, stop: Scalar, base: Scalar, points_per_interval: usize) -> Vec<Scalar>
+{
+    // Matches SPICE - the sweep starts exactly at
+    // start and then continues in equal ratio steps
+    // until it passes stop
+
+    let ratio = base.powf(1.0 / (points_per_interval as Scalar));
+    let intervals = ((stop / start).ln() / ratio.ln() + 1e-9).floor().max(0.0) as usize;
+
+    (0..=intervals)
+        .map(|i| start * ratio.powi(i as i32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn range(text: &str) -> Result<Vec<Scalar>, ParseError>
+    {
+        Sweep::parse_range(&mut Parser::new(text.to_owned())).map(|sweep| sweep.values())
+    }
+
+    #[test]
+    fn parse_ranges()
+    {
+        let check = |text: &str, expected: &[Scalar]|
+        {
+            let values = range(text).unwrap();
+            assert_eq!(values.len(), expected.len(), "{}", text);
+            assert!(values.iter().zip(expected).all(|(v, e)| (v - e).abs() <= 1e-9 * e.abs()), "{} gave {:?}", text, values);
+        };
+
+        check("LIN 1k 2k 250", &[1e3, 1.25e3, 1.5e3, 1.75e3, 2e3]);
+        check("lin 5 1 -2", &[5.0, 3.0, 1.0]);
+        check("DEC 1 100 2", &[1.0, 10.0_f64.sqrt(), 10.0, 1000.0_f64.sqrt(), 100.0]);
+        check("OCT 100 450 1", &[100.0, 200.0, 400.0]);
+        check("LIST 1 2.2 -3", &[1.0, 2.2, -3.0]);
+
+        for bad in ["LIN 1 2 0", "LIN 1 2 -1", "DEC 0 10 5", "OCT 1 10 0", "DEC 1 10", "LIST", "LOG 1 10 5"]
+        {
+            assert!(range(bad).is_err(), "{}", bad);
+        }
+    }
+}
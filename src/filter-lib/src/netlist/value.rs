@@ -1,28 +1,146 @@
 use  std::fmt::*;
+use super::parser::{Parser, ParseError, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution
+{
+    Uniform,
+    /// Tolerance is the 3-sigma limit
+    Gaussian,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tolerance
+{
+    /// Relative tolerance, e.g. 0.01 for 1%
+    pub relative: f64,
+    pub distribution: Distribution,
+}
 
 #[derive(Debug, Clone)]
 pub struct Value
 {
     val: f64,
+    tolerance: Option<Tolerance>,
+    parameter: Option<String>,
 }
 
 impl Value
 {
     pub fn new(val: f64) -> Self
     {
-        Value { val }
+        Value { val, tolerance: None, parameter: None }
+    }
+
+    pub fn with_tolerance(val: f64, tolerance: Tolerance) -> Self
+    {
+        Value { val, tolerance: Some(tolerance), parameter: None }
     }
 
     pub fn value(&self) -> f64
     {
         self.val
     }
+
+    /// Replaces the value, keeping its tolerance
+    pub fn set_value(&mut self, val: f64)
+    {
+        self.val = val;
+    }
+
+    /// The `.PARAM` this value was given as, e.g. `{rload}`
+    pub fn parameter(&self) -> Option<&str>
+    {
+        self.parameter.as_deref()
+    }
+
+    pub fn tolerance(&self) -> Option<&Tolerance>
+    {
+        self.tolerance.as_ref()
+    }
+
+    /// The nominal value moved by `relative`, e.g. 0.01
+    /// for 1% high, keeping the tolerance and parameter
+    pub fn deviated(&self, relative: f64) -> Value
+    {
+        Value { val: self.val * (1.0 + relative), ..self.clone() }
+    }
+
+    /// Parses `value|{param} [tol=percent%|fraction] [dist=gauss|uniform]`
+    ///
+    /// Parameter values are filled in once the
+    /// whole netlist has been parsed.
+    pub fn parse(parser: &mut Parser) -> std::result::Result<Value, ParseError>
+    {
+        let mut val = 0.0;
+        let mut parameter = None;
+
+        if parser.is_symbol('{')
+        {
+            parser.expect_symbol('{')?;
+            parameter = Some(parser.expect_ident()?);
+            parser.expect_symbol('}')?;
+        }
+        else
+        {
+            val = parser.expect_value()?;
+        }
+
+        let mut tolerance = None;
+        let mut distribution = Distribution::Gaussian;
+
+        while let Token::Ident(ident) = parser.peek()
+        {
+            match ident.to_uppercase().as_ref()
+            {
+                "TOL" =>
+                {
+                    parser.expect_ident()?;
+                    parser.expect_symbol('=')?;
+                    let location = parser.cur_location();
+                    let mut relative = parser.expect_value()?;
+                    if parser.is_symbol('%')
+                    {
+                        parser.expect_symbol('%')?;
+                        relative /= 100.0;
+                    }
+                    if !(0.0..1.0).contains(&relative)
+                    {
+                        return Err(location.into_error_named("Tolerance must be between 0% and 100%".to_owned()));
+                    }
+                    tolerance = Some(relative);
+                },
+                "DIST" =>
+                {
+                    parser.expect_ident()?;
+                    parser.expect_symbol('=')?;
+                    let location = parser.cur_location();
+                    distribution = match parser.expect_ident()?.to_uppercase().as_ref()
+                    {
+                        "GAUSS" | "GAUSSIAN" => Distribution::Gaussian,
+                        "UNIFORM" => Distribution::Uniform,
+                        other => return Err(location.into_error_named(format!("Unknown distribution \"{}\" - expected GAUSS or UNIFORM", other))),
+                    };
+                },
+                _ => break,
+            }
+        }
+
+        let tolerance = tolerance.map(|relative| Tolerance { relative, distribution });
+
+        Ok(Value { val, tolerance, parameter })
+    }
 }
 
 impl Display for Value
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result
     {
-        write!{f, "{}", self.val}
+        write!{f, "{}", self.val}?;
+        if let Some(tolerance) = &self.tolerance
+        {
+            write!{f, " tol={}%", tolerance.relative * 100.0}?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,175 @@
+use crate::netlist::{Device, Netlist, Scalar, SimulationOptions};
+use crate::la::{Complex, Solver, VariableIndex};
+use super::{diode, mna, Axis, Conditions, Metadata, Signal, SignalKind, SimulationResults};
+use super::mna::MnaLayout;
+use super::op::OperatingPoint;
+
+/// Small-signal frequency domain analysis, with every
+/// nonlinear device linearised at the DC operating point.
+pub struct AcSimulation
+{
+    layout: MnaLayout,
+    operating_point: OperatingPoint,
+    stamps: Vec<Stamp>,
+    options: SimulationOptions,
+}
+
+impl AcSimulation
+{
+    /// Returns None if the operating point can't be found
+    pub fn new(netlist: &Netlist) -> Option<Self>
+    {
+        let operating_point = OperatingPoint::solve(netlist)?;
+        let layout = MnaLayout::new(netlist);
+        let conditions = Conditions::new(netlist).ok()?;
+        let vt = conditions.thermal_voltage();
+        let mut stamps = Vec::new();
+
+        for device in netlist.devices()
+        {
+            match device
+            {
+                Device::Voltage { name, plus, minus, ac_magnitude, ac_phase, .. } =>
+                {
+                    let branch = layout.branch(name).unwrap();
+                    let plus = layout.node(plus);
+                    let minus = layout.node(minus);
+                    let stimulus = Complex::from_polar(*ac_magnitude, ac_phase.to_radians());
+                    stamps.push(Stamp::Voltage { branch, plus, minus, stimulus });
+                },
+                Device::Resistor { plus, minus, resistance, tc1, tc2, .. } =>
+                {
+                    let plus = layout.node(plus);
+                    let minus = layout.node(minus);
+                    let conductance = 1.0 / conditions.resistance(resistance.value(), *tc1, *tc2);
+                    stamps.push(Stamp::Admittance { plus, minus, conductance, capacitance: 0.0 });
+                },
+                Device::Capacitor { plus, minus, capacitance, .. } =>
+                {
+                    let plus = layout.node(plus);
+                    let minus = layout.node(minus);
+                    stamps.push(Stamp::Admittance { plus, minus, conductance: 0.0, capacitance: capacitance.value() });
+                },
+                Device::Diode { plus, minus, model, .. } =>
+                {
+                    let vd = operating_point.voltage(plus) - operating_point.voltage(minus);
+                    let (_, conductance) = diode::evaluate(&conditions.diode(model), vt, conditions.gmin(), vd);
+                    let plus = layout.node(plus);
+                    let minus = layout.node(minus);
+                    stamps.push(Stamp::Admittance { plus, minus, conductance, capacitance: 0.0 });
+                },
+                Device::Vcvs { name, plus, minus, control_plus, control_minus, gain } =>
+                {
+                    let branch = layout.branch(name).unwrap();
+                    let plus = layout.node(plus);
+                    let minus = layout.node(minus);
+                    let control_plus = layout.node(control_plus);
+                    let control_minus = layout.node(control_minus);
+                    let gain = gain.value();
+                    stamps.push(Stamp::Vcvs { branch, plus, minus, control_plus, control_minus, gain });
+                },
+            }
+        }
+
+        Some(AcSimulation { layout, operating_point, stamps, options: netlist.options().clone() })
+    }
+
+    pub fn operating_point(&self) -> &OperatingPoint
+    {
+        &self.operating_point
+    }
+
+    /// Solves the circuit at each frequency, driven by
+    /// every source's AC magnitude and phase. Returns
+    /// None if the system is singular at any frequency.
+    pub fn simulate(&self, frequencies: &[Scalar]) -> Option<SimulationResults<Complex<Scalar>>>
+    {
+        let mut results = vec![Vec::with_capacity(frequencies.len()); self.layout.dim()];
+
+        for frequency in frequencies.iter()
+        {
+            let solution = self.fill(*frequency, true).solve()?;
+
+            for (var_results, var_solution) in results.iter_mut().zip(solution)
+            {
+                var_results.push(var_solution);
+            }
+        }
+
+        let signals = self.layout.system().variables().iter().zip(self.layout.units()).zip(results)
+            .map(|((name, unit), values)| Signal { name: name.clone(), unit, kind: SignalKind::of(unit), values })
+            .collect();
+        let metadata = Metadata { method: None, step: None, options: self.options.clone() };
+
+        SimulationResults::new(Axis::Frequency, frequencies.to_vec(), signals, metadata).ok()
+    }
+
+    pub(crate) fn layout(&self) -> &MnaLayout
+    {
+        &self.layout
+    }
+
+    /// Fills the complex system at `frequency`, optionally
+    /// leaving out the sources' stimulus
+    pub(crate) fn fill(&self, frequency: Scalar, stimulus: bool) -> Solver<Complex<Scalar>>
+    {
+        let omega = 2.0 * std::f64::consts::PI * frequency;
+        let mut solver = self.layout.system().new_complex_solver();
+
+        for stamp in self.stamps.iter()
+        {
+            stamp.fill(&mut solver, omega, stimulus);
+        }
+
+        solver
+    }
+}
+
+enum Stamp
+{
+    Voltage{branch: VariableIndex, plus: Option<VariableIndex>, minus: Option<VariableIndex>, stimulus: Complex<Scalar>},
+    Admittance{plus: Option<VariableIndex>, minus: Option<VariableIndex>, conductance: Scalar, capacitance: Scalar},
+    Vcvs
+    {
+        branch: VariableIndex,
+        plus: Option<VariableIndex>,
+        minus: Option<VariableIndex>,
+        control_plus: Option<VariableIndex>,
+        control_minus: Option<VariableIndex>,
+        gain: Scalar,
+    },
+}
+
+impl Stamp
+{
+    fn fill(&self, solver: &mut Solver<Complex<Scalar>>, omega: Scalar, stimulus: bool)
+    {
+        let one = Complex::new(1.0, 0.0);
+
+        match self
+        {
+            Stamp::Voltage { branch, plus, minus, stimulus: value } =>
+            {
+                // Current flows out of the plus terminal
+                mna::stamp_branch_current(solver, *minus, *plus, *branch);
+                mna::stamp_branch_voltage(solver, *branch, *plus, *minus, one);
+                if stimulus
+                {
+                    *mna::branch_constant(solver, *branch) = *value;
+                }
+            },
+            Stamp::Admittance { plus, minus, conductance, capacitance } =>
+            {
+                // Y = G + jwC
+                mna::stamp_admittance(solver, *plus, *minus, Complex::new(*conductance, omega * capacitance));
+            },
+            Stamp::Vcvs { branch, plus, minus, control_plus, control_minus, gain } =>
+            {
+                // (V+ - V-) - G * (Vc+ - Vc-) = 0
+                mna::stamp_branch_current(solver, *plus, *minus, *branch);
+                mna::stamp_branch_voltage(solver, *branch, *plus, *minus, one);
+                mna::stamp_branch_voltage(solver, *branch, *control_plus, *control_minus, Complex::new(-gain, 0.0));
+            },
+        }
+    }
+}
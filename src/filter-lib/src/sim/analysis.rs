@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use crate::netlist::{Analysis, Netlist, Scalar};
+use crate::la::Complex;
+use super::ac::AcSimulation;
+use super::noise::{NoiseResults, NoiseSimulation};
+use super::op::OperatingPoint;
+use super::{Conditions, SimulationError, SimulationResults};
+use super::transient::TransientSimulation;
+
+pub enum AnalysisResults
+{
+    Op(BTreeMap<String, Scalar>),
+    Dc{sweep: Vec<Scalar>, values: BTreeMap<String, Vec<Scalar>>},
+    Ac(SimulationResults<Complex<Scalar>>),
+    Transient(SimulationResults),
+    Noise(NoiseResults),
+}
+
+/// Runs one of the netlist's analysis cards
+pub fn run(netlist: &Netlist, analysis: &Analysis) -> Result<AnalysisResults, SimulationError>
+{
+    let unsolved = |analysis: String| SimulationError::NoSolution { analysis };
+
+    // The analyses below that can't say why they failed
+    // would otherwise hide an invalid device value
+    Conditions::new(netlist)?;
+
+    match analysis
+    {
+        Analysis::Op =>
+        {
+            let point = OperatingPoint::solve(netlist).ok_or_else(|| unsolved("operating point".to_owned()))?;
+            Ok(AnalysisResults::Op(point.results()))
+        },
+        Analysis::Dc(dc) =>
+        {
+            let sweep = dc.sweep.values();
+            let mut values = BTreeMap::<String, Vec<Scalar>>::new();
+
+            for value in sweep.iter()
+            {
+                let point = OperatingPoint::solve(&netlist.with_device_value(&dc.source, *value))
+                    .ok_or_else(|| unsolved(format!("DC sweep at {}={}", dc.source, value)))?;
+                for (name, result) in point.results()
+                {
+                    values.entry(name).or_default().push(result);
+                }
+            }
+
+            Ok(AnalysisResults::Dc { sweep, values })
+        },
+        Analysis::Ac(ac) =>
+        {
+            let frequencies = ac.sweep.values();
+            let simulation = AcSimulation::new(netlist).ok_or_else(|| unsolved("AC operating point".to_owned()))?;
+            Ok(AnalysisResults::Ac(simulation.simulate(&frequencies).ok_or_else(|| unsolved("AC sweep".to_owned()))?))
+        },
+        Analysis::Transient(tran) =>
+        {
+            let results = TransientSimulation::with_uic(netlist, tran.uic)?.simulate(tran.step, tran.steps())?;
+            Ok(AnalysisResults::Transient(results))
+        },
+        Analysis::Noise(noise) =>
+        {
+            let simulation = NoiseSimulation::new(netlist, noise).ok_or_else(|| unsolved("noise operating point".to_owned()))?;
+            Ok(AnalysisResults::Noise(simulation.simulate().ok_or_else(|| unsolved("noise sweep".to_owned()))?))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn dc_sweep_transfer()
+    {
+        let netlist = "V1 1 0 0\nR1 1 2 1k\nR2 2 0 3k\nE1 3 0 2 0 2\n.DC V1 -2 2 0.5".parse::<Netlist>().unwrap();
+
+        let Ok(AnalysisResults::Dc { sweep, values }) = run(&netlist, &netlist.analyses()[0]) else { panic!("DC sweep failed") };
+        assert_eq!(sweep.len(), 9);
+        for (i, vin) in sweep.iter().enumerate()
+        {
+            assert!((values["V_2"][i] - 0.75 * vin).abs() < 1e-12);
+            assert!((values["V_3"][i] - 1.5 * vin).abs() < 1e-12);
+            assert!((values["I_V1"][i] - vin / 4e3).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn transient_ends_at_stop()
+    {
+        let netlist = "V1 1 0 1\nR1 1 2 1k\nC1 2 0 1u\n.TRAN 10u 1m".parse::<Netlist>().unwrap();
+
+        let Ok(AnalysisResults::Transient(results)) = run(&netlist, &netlist.analyses()[0]) else { panic!("transient failed") };
+        assert_eq!(results.points().len(), 101);
+        assert_eq!(results.points()[0], 0.0);
+        assert!((results.points()[100] - 1e-3).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::netlist::{Device, DiodeModel, Netlist, Scalar};
+use super::{thermal_voltage, SimulationError, ZERO_CELSIUS};
+
+/// Operating temperature, and the device
+/// values it gives from their nominal values
+#[derive(Debug, Clone, Copy)]
+pub struct Conditions
+{
+    /// Kelvin
+    temperature: Scalar,
+    /// Kelvin
+    nominal_temperature: Scalar,
+    /// Across every junction (S)
+    gmin: Scalar,
+}
+
+impl Conditions
+{
+    /// Fails if a resistor's temperature coefficients
+    /// take its resistance to zero or below
+    pub fn new(netlist: &Netlist) -> Result<Self, SimulationError>
+    {
+        let conditions = Conditions
+        {
+            temperature: netlist.temperature() + ZERO_CELSIUS,
+            nominal_temperature: netlist.nominal_temperature() + ZERO_CELSIUS,
+            gmin: netlist.options().gmin,
+        };
+
+        for device in netlist.devices()
+        {
+            if let Device::Resistor { name, resistance, tc1, tc2, .. } = device
+            {
+                let value = conditions.resistance(resistance.value(), *tc1, *tc2);
+                if value.is_nan() || (value <= 0.0)
+                {
+                    let reason = format!("resistance must be positive at {}C", netlist.temperature());
+                    return Err(SimulationError::InvalidValue { name: name.clone(), value, reason });
+                }
+            }
+        }
+
+        Ok(conditions)
+    }
+
+    /// Kelvin
+    pub fn temperature(&self) -> Scalar
+    {
+        self.temperature
+    }
+
+    pub fn gmin(&self) -> Scalar
+    {
+        self.gmin
+    }
+
+    pub fn thermal_voltage(&self) -> Scalar
+    {
+        thermal_voltage(self.temperature)
+    }
+
+    /// R(T) = R.(1 + TC1.dT + TC2.dT^2)
+    pub fn resistance(&self, resistance: Scalar, tc1: Scalar, tc2: Scalar) -> Scalar
+    {
+        let dt = self.temperature - self.nominal_temperature;
+        resistance * (1.0 + tc1 * dt + tc2 * dt * dt)
+    }
+
+    /// Scales the saturation current with temperature:
+    /// IS(T) = IS.(T/Tnom)^(XTI/N).exp((T/Tnom - 1).EG/(N.Vt))
+    pub fn diode(&self, model: &DiodeModel) -> DiodeModel
+    {
+        let ratio = self.temperature / self.nominal_temperature;
+        let n_vt = model.n * self.thermal_voltage();
+
+        DiodeModel
+        {
+            is: model.is * ratio.powf(model.xti / model.n) * ((ratio - 1.0) * model.eg / n_vt).exp(),
+            ..model.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Analysis;
+    use crate::sim::analysis::AnalysisResults;
+    use crate::sim::step::StepSimulation;
+    use crate::sim::{BOLTZMANN, ELECTRON_CHARGE};
+
+    #[test]
+    fn temperature_scaling()
+    {
+        let netlist = "V1 1 0 1\nR1 1 2 1k TC1=4m TC2=10u\nR2 2 0 1k\nD1 2 0\n.TEMP 127".parse::<Netlist>().unwrap();
+        let hot = Conditions::new(&netlist).unwrap();
+        let nominal = Conditions::new(&netlist.with_temperature(27.0).unwrap()).unwrap();
+
+        assert_eq!(nominal.resistance(1e3, 4e-3, 10e-6), 1e3);
+        assert!((hot.resistance(1e3, 4e-3, 10e-6) - 1e3 * (1.0 + 0.4 + 0.1)).abs() < 1e-9);
+
+        // Silicon's IS rises by about five decades over 100C
+        let model = DiodeModel::default();
+        let (t, tnom) = (127.0 + ZERO_CELSIUS, 27.0 + ZERO_CELSIUS);
+        let vt = BOLTZMANN * t / ELECTRON_CHARGE;
+        let expected = model.is * (t / tnom).powf(model.xti / model.n) * ((t / tnom - 1.0) * model.eg / (model.n * vt)).exp();
+        assert!((hot.diode(&model).is - expected).abs() < expected * 1e-12);
+        assert!((nominal.diode(&model).is - model.is).abs() < model.is * 1e-12);
+        assert!((1e3..1e5).contains(&(expected / model.is)));
+
+        // .STEP TEMP moves the divider as R1 heats up
+        let divider = "V1 1 0 1\nR1 1 2 1k TC1=4m\nR2 2 0 1k\n.STEP TEMP LIST 27 127\n.OP".parse::<Netlist>().unwrap();
+        let runs = StepSimulation::new(&divider).unwrap().simulate(&Analysis::Op);
+        let output = |i: usize| match &runs[i].results { Ok(AnalysisResults::Op(values)) => values["V_2"], _ => panic!() };
+        assert!((output(0) - 0.5).abs() < 1e-12);
+        assert!((output(1) - 1.0 / 2.4).abs() < 1e-12);
+
+        // TC1 taking R1 through zero
+        let negative = "V1 1 0 1\nR1 1 0 1k TC1=-10m\n.TEMP 127\n.OP".parse::<Netlist>().unwrap();
+        assert!(matches!(Conditions::new(&negative), Err(SimulationError::InvalidValue { name, .. }) if name == "R1"));
+        assert!(crate::sim::analysis::run(&negative, &Analysis::Op).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::{Display, Formatter, Result};
+use crate::netlist::Scalar;
+
+/// Largest conductance (S) gmin stepping starts from,
+/// going down a decade at a time
+pub const GMIN_START: Scalar = 1e-2;
+/// Smallest gmin step before it's removed altogether
+pub const GMIN_END: Scalar = 1e-12;
+/// Source stepping goes up in these fractions of the sources
+pub const SOURCE_STEPS: usize = 10;
+
+/// How a nonlinear solve was made to converge. When plain
+/// Newton-Raphson fails, the others are tried in order -
+/// each is a homotopy, starting from an easy circuit and
+/// moving in steps to the real one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvergenceMethod
+{
+    /// Plain Newton-Raphson, no help needed
+    Newton,
+    /// A conductance from every node to ground,
+    /// reduced a decade at a time until it's gone
+    GminStepping,
+    /// Sources ramped up from zero
+    SourceStepping,
+    /// Capacitors from every node to ground, with a time
+    /// step that grows until they're open circuit
+    PseudoTransient,
+}
+
+impl ConvergenceMethod
+{
+    /// Conductances for gmin stepping, ending with none
+    pub fn gmin_steps() -> impl Iterator<Item = Scalar>
+    {
+        std::iter::successors(Some(GMIN_START), |g| Some(g / 10.0))
+            .take_while(|g| *g >= GMIN_END * 0.5)
+            .chain(std::iter::once(0.0))
+    }
+
+    /// Fractions of the sources for source stepping, ending with all of them
+    pub fn source_steps() -> impl Iterator<Item = Scalar>
+    {
+        (1..=SOURCE_STEPS).map(|i| (i as Scalar) / (SOURCE_STEPS as Scalar))
+    }
+}
+
+impl Display for ConvergenceMethod
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result
+    {
+        match self
+        {
+            ConvergenceMethod::Newton => write!(f, "Newton-Raphson"),
+            ConvergenceMethod::GminStepping => write!(f, "gmin stepping"),
+            ConvergenceMethod::SourceStepping => write!(f, "source stepping"),
+            ConvergenceMethod::PseudoTransient => write!(f, "pseudo-transient"),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::netlist::{DiodeModel, Scalar};
+
+/// Evaluates the Shockley diode equation
+/// Id = Is * (exp(Vd/n.Vt) - 1)
+/// returning the current and dI/dV at `voltage`.
+/// `gmin` is a small conductance placed across the
+/// junction so reverse biased diodes never leave a
+/// node floating.
+pub fn evaluate(model: &DiodeModel, vt: Scalar, gmin: Scalar, voltage: Scalar) -> (Scalar, Scalar)
+{
+    let n_vt = model.n * vt;
+    let exp = (voltage / n_vt).exp();
+
+    let current = model.is * (exp - 1.0) + gmin * voltage;
+    let conductance = model.is * exp / n_vt + gmin;
+
+    (current, conductance)
+}
+
+/// Voltage above which the junction current
+/// grows faster than Newton can follow
+pub fn critical_voltage(model: &DiodeModel, vt: Scalar) -> Scalar
+{
+    let n_vt = model.n * vt;
+    n_vt * (n_vt / (std::f64::consts::SQRT_2 * model.is)).ln()
+}
+
+/// SPICE pn-junction limiting - stops a Newton step from
+/// jumping far up the exponential, which would overflow
+/// or make the next iteration wildly wrong.
+pub fn limit(new_voltage: Scalar, old_voltage: Scalar, model: &DiodeModel, vt: Scalar) -> Scalar
+{
+    let n_vt = model.n * vt;
+    let vcrit = critical_voltage(model, vt);
+
+    if (new_voltage > vcrit) && ((new_voltage - old_voltage).abs() > (n_vt + n_vt))
+    {
+        if old_voltage > 0.0
+        {
+            let arg = 1.0 + (new_voltage - old_voltage) / n_vt;
+            if arg > 0.0
+            {
+                old_voltage + n_vt * arg.ln()
+            }
+            else
+            {
+                vcrit
+            }
+        }
+        else
+        {
+            n_vt * (new_voltage / n_vt).ln()
+        }
+    }
+    else
+    {
+        new_voltage
+    }
+}
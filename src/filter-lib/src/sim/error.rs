@@ -0,0 +1,117 @@
+use std::fmt::{Display, Formatter, Result};
+use crate::netlist::Scalar;
+
+/// Why a simulation couldn't continue
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulationError
+{
+    /// The equations have no unique solution - e.g. a floating
+    /// node, or a loop of voltage sources. Lists the nodes and
+    /// devices involved.
+    Singular{time: Scalar, nodes: Vec<String>, devices: Vec<String>},
+    /// Newton-Raphson didn't settle within the iteration limit.
+    /// Residuals are the unknowns still changing on the last
+    /// iteration, and by how much.
+    NoConvergence{time: Scalar, iterations: usize, residuals: Vec<(String, Scalar)>},
+    /// The operating point to start a transient analysis
+    /// from couldn't be found
+    NoOperatingPoint,
+    /// An operating point, DC, AC or noise analysis that
+    /// couldn't be solved - singular, or didn't converge
+    NoSolution{analysis: String},
+    /// A device value the simulation can't use, e.g. a
+    /// resistance taken below zero by its TC1/TC2
+    InvalidValue{name: String, value: Scalar, reason: String},
+    /// A timestep that can't be simulated
+    InvalidTimestep{step: Scalar, reason: String},
+    /// A source or result named that isn't in the circuit
+    Unknown{name: String},
+    /// A saved state that can't be read
+    InvalidSnapshot{line: usize, reason: String},
+    /// A value became NaN or infinite. Time is None for
+    /// device values found before the simulation started.
+    NotFinite{time: Option<Scalar>, names: Vec<String>},
+    /// Results that don't fit together, e.g. a signal
+    /// without a value at every point
+    InvalidResults{reason: String},
+    /// Input and output buffers that aren't the same
+    /// number of whole frames, as sample counts
+    InvalidFrames{input: usize, output: usize},
+}
+
+impl Display for SimulationError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result
+    {
+        match self
+        {
+            SimulationError::Singular { time, nodes, devices } =>
+            {
+                write!(f, "singular matrix at time={}", time)?;
+                if !nodes.is_empty()
+                {
+                    write!(f, " - nodes {}", nodes.join(", "))?;
+                }
+                if !devices.is_empty()
+                {
+                    write!(f, " - devices {}", devices.join(", "))?;
+                }
+                Ok(())
+            },
+            SimulationError::NoConvergence { time, iterations, residuals } =>
+            {
+                write!(f, "no convergence at time={} after {} iterations", time, iterations)?;
+                for (name, residual) in residuals.iter()
+                {
+                    write!(f, " - {} changed by {:e}", name, residual)?;
+                }
+                Ok(())
+            },
+            SimulationError::NoOperatingPoint =>
+            {
+                write!(f, "no initial operating point - UIC starts from the initial conditions instead")
+            },
+            SimulationError::NoSolution { analysis } =>
+            {
+                write!(f, "no solution for the {} - singular or no convergence", analysis)
+            },
+            SimulationError::InvalidValue { name, value, reason } =>
+            {
+                write!(f, "invalid value {} for {} - {}", value, name, reason)
+            },
+            SimulationError::InvalidTimestep { step, reason } =>
+            {
+                write!(f, "invalid timestep {} - {}", step, reason)
+            },
+            SimulationError::Unknown { name } =>
+            {
+                write!(f, "unknown source or result \"{}\"", name)
+            },
+            SimulationError::InvalidSnapshot { line, reason } =>
+            {
+                write!(f, "invalid snapshot, line {} - {}", line, reason)
+            },
+            SimulationError::NotFinite { time, names } =>
+            {
+                write!(f, "non-finite value")?;
+                if let Some(time) = time
+                {
+                    write!(f, " at time={}", time)?;
+                }
+                write!(f, " in {}", names.join(", "))
+            },
+            SimulationError::InvalidResults { reason } =>
+            {
+                write!(f, "invalid results - {}", reason)
+            },
+            SimulationError::InvalidFrames { input, output } =>
+            {
+                write!(f, "{} input and {} output samples aren't the same number of whole frames", input, output)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SimulationError
+{
+}
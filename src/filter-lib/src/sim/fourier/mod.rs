@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use crate::la::Complex;
+use crate::netlist::{Analysis, FourierAnalysis, Netlist, NodeName, Probe, Scalar};
+use super::analysis::{self, AnalysisResults};
+use super::{SimulationError, SimulationResults};
+
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone)]
+pub struct Harmonic
+{
+    pub number: usize,
+    pub frequency: Scalar,
+    /// Peak amplitude
+    pub magnitude: Scalar,
+    /// Degrees, relative to a cosine
+    pub phase: Scalar,
+    /// Magnitude relative to the fundamental
+    pub normalized_magnitude: Scalar,
+    /// Phase relative to the fundamental
+    pub normalized_phase: Scalar,
+}
+
+#[derive(Debug, Clone)]
+pub struct FourierResults
+{
+    pub dc: Scalar,
+    /// Harmonic 1 (the fundamental) onwards
+    pub harmonics: Vec<Harmonic>,
+    /// Total harmonic distortion, as a ratio of the fundamental
+    pub thd: Scalar,
+    /// Everything but DC and the fundamental, as a ratio of the fundamental
+    pub thd_plus_noise: Scalar,
+    /// Signal to noise and distortion ratio (dB)
+    pub sinad: Scalar,
+}
+
+/// Runs the netlist's first `.TRAN` and evaluates each
+/// of its `.FOUR` cards on it, in order
+pub fn run(netlist: &Netlist) -> Result<Vec<BTreeMap<String, FourierResults>>, SimulationError>
+{
+    // Parsing makes sure there's a .TRAN if there's a .FOUR
+    let transient = netlist.analyses().iter().find(|a| matches!(a, Analysis::Transient(_)));
+
+    match transient
+    {
+        Some(transient) if !netlist.fourier().is_empty() => match analysis::run(netlist, transient)?
+        {
+            AnalysisResults::Transient(results) => Ok(analyse_all(netlist, &results)),
+            _ => Ok(Vec::new()),
+        },
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Evaluates each of the netlist's `.FOUR` cards on a
+/// transient run, in order
+pub fn analyse_all(netlist: &Netlist, values: &SimulationResults) -> Vec<BTreeMap<String, FourierResults>>
+{
+    netlist.fourier().iter().map(|four| analyse(four, values)).collect()
+}
+
+/// Evaluates a `.FOUR` card on a transient run,
+/// keyed by output name, e.g. "V(2)" or "V(2,3)".
+/// Outputs are found as probes or from the node
+/// voltages, and left out if the run is too short.
+pub fn analyse(analysis: &FourierAnalysis, values: &SimulationResults) -> BTreeMap<String, FourierResults>
+{
+    let times = values.points();
+    let mut results = BTreeMap::new();
+
+    let voltage = |node: &NodeName| if *node == NodeName::gnd()
+    {
+        Some(vec![0.0; values.len()])
+    }
+    else
+    {
+        values.get(&format!("V_{}", node.name()))
+            .or_else(|| values.get(&format!("V({})", node)))
+            .cloned()
+    };
+
+    for (output, reference) in analysis.outputs.iter()
+    {
+        let name = Probe::Voltage { node: output.clone(), reference: reference.clone() }.to_string();
+
+        let waveform = match values.get(&name)
+        {
+            Some(recorded) => recorded.clone(),
+            None => match (voltage(output), voltage(reference))
+            {
+                (Some(out), Some(re)) => out.iter().zip(re.iter()).map(|(o, r)| o - r).collect(),
+                _ => continue,
+            },
+        };
+
+        if let Some(result) = fourier(times, &waveform, analysis.fundamental, analysis.harmonics, analysis.periods)
+        {
+            results.insert(name, result);
+        }
+    }
+
+    results
+}
+
+/// Fourier analysis of the last `periods` periods of
+/// `fundamental` in a waveform. Returns None if the
+/// waveform doesn't cover that many periods, or there
+/// are no harmonics or periods to analyse.
+pub fn fourier(times: &[Scalar], values: &[Scalar], fundamental: Scalar, harmonics: usize, periods: usize) -> Option<FourierResults>
+{
+    if (harmonics == 0) || (periods == 0) || !(fundamental.is_finite() && (fundamental > 0.0))
+    {
+        return None;
+    }
+
+    let end = *times.last()?;
+    let span = (periods as Scalar) / fundamental;
+    let start = end - span;
+
+    if times[0] > start + 1e-9 * span
+    {
+        return None;
+    }
+
+    // Resample a whole number of periods onto a uniform
+    // grid, so each harmonic lands exactly on a DFT bin
+
+    let first = times.partition_point(|t| *t < start);
+    let points = (times.len() - first).max(8 * (harmonics + 1));
+    let samples = (0..points)
+        .map(|i| interpolate(times, values, start + span * (i as Scalar) / (points as Scalar)))
+        .collect::<Vec<_>>();
+
+    let count = points as Scalar;
+    let dc = samples.iter().sum::<Scalar>() / count;
+
+    let coefficient = |number: usize| -> Complex<Scalar>
+    {
+        let cycles = (number * periods) as Scalar;
+        samples.iter().enumerate()
+            .map(|(i, v)| Complex::from_polar(*v, -2.0 * PI * cycles * (i as Scalar) / count))
+            .sum::<Complex<Scalar>>() * (2.0 / count)
+    };
+
+    let coefficients = (1..=harmonics).map(coefficient).collect::<Vec<_>>();
+    let fundamental_magnitude = coefficients[0].norm();
+    let fundamental_phase = coefficients[0].arg().to_degrees();
+
+    let harmonics = coefficients.iter().enumerate()
+        .map(|(i, c)| Harmonic
+        {
+            number: i + 1,
+            frequency: fundamental * ((i + 1) as Scalar),
+            magnitude: c.norm(),
+            phase: c.arg().to_degrees(),
+            normalized_magnitude: c.norm() / fundamental_magnitude,
+            normalized_phase: c.arg().to_degrees() - fundamental_phase,
+        })
+        .collect::<Vec<_>>();
+
+    let distortion = harmonics.iter().skip(1)
+        .map(|h| h.magnitude * h.magnitude)
+        .sum::<Scalar>().sqrt();
+    let thd = distortion / fundamental_magnitude;
+
+    // Residual once DC and the fundamental are removed
+
+    let residual_power = samples.iter().enumerate()
+        .map(|(i, v)|
+        {
+            let angle = 2.0 * PI * (periods as Scalar) * (i as Scalar) / count;
+            let fitted = dc + (coefficients[0] * Complex::from_polar(1.0, angle)).re;
+            (v - fitted) * (v - fitted)
+        })
+        .sum::<Scalar>() / count;
+
+    let fundamental_rms = fundamental_magnitude / std::f64::consts::SQRT_2;
+    let thd_plus_noise = residual_power.sqrt() / fundamental_rms;
+    let sinad = -20.0 * thd_plus_noise.log10();
+
+    Some(FourierResults { dc, harmonics, thd, thd_plus_noise, sinad })
+}
+
+fn interpolate(times: &[Scalar], values: &[Scalar], time: Scalar) -> Scalar
+{
+    let index = times.partition_point(|t| *t <= time);
+
+    if index == 0
+    {
+        return values[0];
+    }
+    if index >= times.len()
+    {
+        return values[times.len() - 1];
+    }
+
+    let (t0, t1) = (times[index - 1], times[index]);
+    let (v0, v1) = (values[index - 1], values[index]);
+    v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn known_harmonics()
+    {
+        // 1kHz fundamental with 10% second and 5% third harmonic
+        let times = (0..=4800).map(|i| (i as Scalar) / 480_000.0).collect::<Vec<_>>();
+        let values = times.iter()
+            .map(|t| 0.5
+                + 2.0 * (2.0 * PI * 1000.0 * t).cos()
+                + 0.2 * (2.0 * PI * 2000.0 * t).cos()
+                + 0.1 * (2.0 * PI * 3000.0 * t + 1.0).cos())
+            .collect::<Vec<_>>();
+
+        let results = fourier(&times, &values, 1000.0, 5, 2).unwrap();
+
+        assert!((results.dc - 0.5).abs() < 1e-3);
+        assert!((results.harmonics[0].magnitude - 2.0).abs() < 1e-3);
+        assert!((results.harmonics[1].normalized_magnitude - 0.1).abs() < 1e-3);
+        assert!((results.harmonics[2].phase - 1.0f64.to_degrees()).abs() < 0.5);
+        assert!((results.thd - (0.1f64 * 0.1 + 0.05 * 0.05).sqrt()).abs() < 1e-3);
+        assert!((results.thd_plus_noise - results.thd).abs() < 1e-3);
+
+        assert!(fourier(&times, &values, 1000.0, 0, 2).is_none());
+        assert!(fourier(&times, &values, 1000.0, 5, 0).is_none());
+        assert!(fourier(&times, &values, 0.0, 5, 2).is_none());
+    }
+
+    #[test]
+    fn netlist_sine()
+    {
+        // A 2V peak 1kHz sine - sin(x) is sin(x.t/2pi) - halved,
+        // recorded both as node voltages and as probes
+        let netlist = "V1 1 0 2*sin(39478.417604)\nR1 1 2 1k\nR2 2 0 1k\n.TRAN 2u 5m\n.FOUR 1k 9 V(1) V(2) V(1,2)".parse::<Netlist>().unwrap();
+
+        let results = run(&netlist).unwrap();
+        assert_eq!(results.len(), 1);
+        let check = |results: &BTreeMap<String, FourierResults>|
+        {
+            assert_eq!(results.keys().collect::<Vec<_>>(), ["V(1)", "V(1,2)", "V(2)"]);
+            assert!((results["V(1)"].harmonics[0].magnitude - 2.0).abs() < 1e-3);
+            assert!((results["V(2)"].harmonics[0].magnitude - 1.0).abs() < 1e-3);
+            assert!((results["V(1,2)"].harmonics[0].magnitude - 1.0).abs() < 1e-3);
+            assert!(results.values().all(|r| r.thd < 1e-3));
+        };
+        check(&results[0]);
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.set_probes(&["V(1)".parse::<Probe>().unwrap(), "V(2)".parse().unwrap()]).unwrap();
+        check(&analyse_all(&netlist, &simulation.simulate(2e-6, 2501).unwrap())[0]);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use crate::la::Complex;
+use crate::netlist::{AcPart, Analysis, Crossing, Device, Edge, Measure, MeasureAnalysis, MeasurePoint, MeasureSignal, Measurement, Netlist, NodeName, Probe, Scalar, Statistic};
+use super::analysis::{self, AnalysisResults};
+use super::{SimulationError, SimulationResults};
+
+/// Runs each analysis the netlist's `.MEAS` cards refer to
+/// and evaluates them - failed measurements are left out as
+/// for `analyse`, but a failed simulation is an error, as is
+/// more than one analysis for the same `.MEAS` cards
+pub fn run(netlist: &Netlist) -> Result<BTreeMap<String, Scalar>, SimulationError>
+{
+    let mut measured = BTreeMap::new();
+    let mut analysed = Vec::new();
+
+    for analysis in netlist.analyses()
+    {
+        let measures = match analysis
+        {
+            Analysis::Transient(_) => MeasureAnalysis::Transient,
+            Analysis::Ac(_) => MeasureAnalysis::Ac,
+            _ => continue,
+        };
+        if netlist.measures().iter().any(|measure| measure.analysis == measures)
+        {
+            if analysed.contains(&measures)
+            {
+                let card = match measures
+                {
+                    MeasureAnalysis::Transient => "TRAN",
+                    MeasureAnalysis::Ac => "AC",
+                };
+                return Err(SimulationError::InvalidResults { reason: format!(".MEAS {} cards with more than one .{}", card, card) });
+            }
+            analysed.push(measures);
+            measured.extend(analyse(netlist, &analysis::run(netlist, analysis)?));
+        }
+    }
+
+    Ok(measured)
+}
+
+/// Evaluates the netlist's `.MEAS` cards for the analysis
+/// `results` came from, keyed by name. Measurements that
+/// fail - e.g. a WHEN that never happens, or a signal that
+/// wasn't recorded - are left out.
+pub fn analyse(netlist: &Netlist, results: &AnalysisResults) -> BTreeMap<String, Scalar>
+{
+    let analysis = match results
+    {
+        AnalysisResults::Transient(_) => MeasureAnalysis::Transient,
+        AnalysisResults::Ac(_) => MeasureAnalysis::Ac,
+        _ => return BTreeMap::new(),
+    };
+
+    let results = Results { netlist, results };
+
+    netlist.measures().iter()
+        .filter(|measure| measure.analysis == analysis)
+        .filter_map(|measure| Some((measure.name.clone(), results.measure(measure)?)))
+        .collect()
+}
+
+struct Results<'a>
+{
+    netlist: &'a Netlist,
+    results: &'a AnalysisResults,
+}
+
+impl<'a> Results<'a>
+{
+    /// Times or frequencies
+    fn axis(&self) -> &[Scalar]
+    {
+        match self.results
+        {
+            AnalysisResults::Transient(results) => results.points(),
+            AnalysisResults::Ac(results) => results.points(),
+            _ => &[],
+        }
+    }
+
+    fn measure(&self, measure: &Measure) -> Option<Scalar>
+    {
+        let axis = self.axis();
+
+        match &measure.measurement
+        {
+            Measurement::Find { signal, point } =>
+            {
+                interpolate(axis, &self.signal(signal)?, self.point(point)?)
+            },
+            Measurement::When(crossing) => self.crossing(crossing),
+            Measurement::Derivative { signal, point } =>
+            {
+                slope(axis, &self.signal(signal)?, self.point(point)?)
+            },
+            Measurement::TriggerTarget { trigger, target } =>
+            {
+                Some(self.point(target)? - self.point(trigger)?)
+            },
+            Measurement::Statistic { statistic, signal, from, to } =>
+            {
+                statistics(axis, &self.signal(signal)?, *statistic, *from, *to)
+            },
+            Measurement::Bandwidth { node, reference } =>
+            {
+                let gain = decibels(&self.voltage(node, reference)?);
+                let (peak, level) = gain.iter().enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map(|(i, g)| (i, g - 3.0))?;
+
+                // Outwards from the peak to where the gain is 3dB
+                // down - from 0Hz if it never is below the peak
+                let upper = crossing(&axis[peak..], &gain[peak..], level, Edge::Fall, Some(1), 0.0)?;
+                let lower = crossing(&axis[..=peak], &gain[..=peak], level, Edge::Rise, None, 0.0).unwrap_or(0.0);
+                Some(upper - lower)
+            },
+            Measurement::PeakGain { node, reference } =>
+            {
+                decibels(&self.voltage(node, reference)?).into_iter().max_by(|a, b| a.total_cmp(b))
+            },
+            Measurement::PhaseMargin { node, reference } =>
+            {
+                let voltage = self.voltage(node, reference)?;
+                let unity = crossing(axis, &decibels(&voltage), 0.0, Edge::Fall, Some(1), 0.0)?;
+                Some(180.0 + interpolate(axis, &phase(&voltage), unity)?)
+            },
+        }
+    }
+
+    fn point(&self, point: &MeasurePoint) -> Option<Scalar>
+    {
+        match point
+        {
+            MeasurePoint::At(at) => Some(*at),
+            MeasurePoint::When(when) => self.crossing(when),
+        }
+    }
+
+    fn crossing(&self, when: &Crossing) -> Option<Scalar>
+    {
+        crossing(self.axis(), &self.signal(&when.signal)?, when.value, when.edge, when.number, when.delay)
+    }
+
+    fn signal(&self, signal: &MeasureSignal) -> Option<Vec<Scalar>>
+    {
+        match (signal, self.results)
+        {
+            (MeasureSignal::Probe(probe), AnalysisResults::Transient(results)) =>
+            {
+                // Recorded as a probe, or calculated
+                // from the voltages and currents
+                if let Some(recorded) = results.get(&probe.to_string())
+                {
+                    return Some(recorded.clone());
+                }
+
+                let terms = probe.terms().into_iter()
+                    .map(|term| self.term(term, results))
+                    .collect::<Option<Vec<_>>>()?;
+
+                Some((0..results.len())
+                    .map(|i|
+                    {
+                        let mut terms = terms.iter();
+                        probe.evaluate(&mut || terms.next().unwrap()[i])
+                    })
+                    .collect())
+            },
+            (MeasureSignal::Ac { part, node, reference }, AnalysisResults::Ac(_)) =>
+            {
+                let voltage = self.voltage(node, reference)?;
+                Some(match part
+                {
+                    AcPart::Magnitude => voltage.iter().map(|v| v.norm()).collect(),
+                    AcPart::Decibels => decibels(&voltage),
+                    AcPart::Phase => phase(&voltage),
+                    AcPart::Real => voltage.iter().map(|v| v.re).collect(),
+                    AcPart::Imaginary => voltage.iter().map(|v| v.im).collect(),
+                })
+            },
+            _ => None,
+        }
+    }
+
+    /// A probe's V, I or P term at each time
+    fn term(&self, term: &Probe, results: &SimulationResults) -> Option<Vec<Scalar>>
+    {
+        let voltage = |node: &NodeName| if *node == NodeName::gnd()
+        {
+            Some(vec![0.0; results.len()])
+        }
+        else
+        {
+            results.get(&format!("V_{}", node.name())).cloned()
+        };
+        let difference = |plus: &NodeName, minus: &NodeName| -> Option<Vec<Scalar>>
+        {
+            Some(voltage(plus)?.iter().zip(voltage(minus)?.iter()).map(|(p, m)| p - m).collect())
+        };
+
+        match term
+        {
+            Probe::Voltage { node, reference } => difference(node, reference),
+            Probe::Current(device) => results.get(&format!("I_{}", device)).cloned(),
+            Probe::Power(device) =>
+            {
+                // Voltage source currents are out of the plus terminal
+                let (plus, minus, sign) = match self.netlist.device(device)?
+                {
+                    Device::Voltage { plus, minus, .. } => (plus, minus, -1.0),
+                    Device::Resistor { plus, minus, .. } | Device::Capacitor { plus, minus, .. }
+                        | Device::Diode { plus, minus, .. } | Device::Vcvs { plus, minus, .. } => (plus, minus, 1.0),
+                };
+                let current = results.get(&format!("I_{}", device))?;
+                Some(difference(plus, minus)?.iter().zip(current.iter()).map(|(v, i)| sign * v * i).collect())
+            },
+            _ => None,
+        }
+    }
+
+    fn voltage(&self, node: &NodeName, reference: &NodeName) -> Option<Vec<Complex<Scalar>>>
+    {
+        let AnalysisResults::Ac(results) = self.results else { return None };
+        let voltage = |node: &NodeName| if *node == NodeName::gnd()
+        {
+            Some(vec![Complex::new(0.0, 0.0); results.len()])
+        }
+        else
+        {
+            results.get(&format!("V_{}", node.name())).cloned()
+        };
+
+        Some(voltage(node)?.iter().zip(voltage(reference)?.iter()).map(|(n, r)| n - r).collect())
+    }
+}
+
+fn decibels(values: &[Complex<Scalar>]) -> Vec<Scalar>
+{
+    values.iter().map(|v| 20.0 * v.norm().log10()).collect()
+}
+
+/// Degrees, without jumps of 360
+fn phase(values: &[Complex<Scalar>]) -> Vec<Scalar>
+{
+    let mut offset = 0.0;
+    let mut previous: Option<Scalar> = None;
+
+    values.iter()
+        .map(|v|
+        {
+            let phase = v.arg().to_degrees();
+            if let Some(previous) = previous
+            {
+                offset -= 360.0 * ((phase - previous) / 360.0).round();
+            }
+            previous = Some(phase);
+            phase + offset
+        })
+        .collect()
+}
+
+/// Linear interpolation of `y` at `at`, if it's within `x`
+fn interpolate(x: &[Scalar], y: &[Scalar], at: Scalar) -> Option<Scalar>
+{
+    let i = x.partition_point(|x| *x < at);
+    if i >= x.len()
+    {
+        return None;
+    }
+    if x[i] == at
+    {
+        return Some(y[i]);
+    }
+    if i == 0
+    {
+        return None;
+    }
+    Some(y[i - 1] + (y[i] - y[i - 1]) * (at - x[i - 1]) / (x[i] - x[i - 1]))
+}
+
+/// Slope of the segment of `y` containing `at`
+fn slope(x: &[Scalar], y: &[Scalar], at: Scalar) -> Option<Scalar>
+{
+    if (x.len() < 2) || (at < x[0])
+    {
+        return None;
+    }
+    let i = x.partition_point(|x| *x < at).max(1);
+    if i >= x.len()
+    {
+        return None;
+    }
+    Some((y[i] - y[i - 1]) / (x[i] - x[i - 1]))
+}
+
+/// Where `y` crosses `value` on the given edge,
+/// counting from 1 - None for the last
+fn crossing(x: &[Scalar], y: &[Scalar], value: Scalar, edge: Edge, number: Option<usize>, delay: Scalar) -> Option<Scalar>
+{
+    let mut crossings = x.windows(2).zip(y.windows(2))
+        .filter_map(|(x, y)|
+        {
+            let rising = (y[0] < value) && (y[1] >= value);
+            let falling = (y[0] > value) && (y[1] <= value);
+            let counts = match edge
+            {
+                Edge::Rise => rising,
+                Edge::Fall => falling,
+                Edge::Cross => rising || falling,
+            };
+            if !counts
+            {
+                return None;
+            }
+            let at = x[0] + (x[1] - x[0]) * (value - y[0]) / (y[1] - y[0]);
+            (at >= delay).then_some(at)
+        });
+
+    match number
+    {
+        Some(number) => crossings.nth(number - 1),
+        None => crossings.next_back(),
+    }
+}
+
+fn statistics(x: &[Scalar], y: &[Scalar], statistic: Statistic, from: Option<Scalar>, to: Option<Scalar>) -> Option<Scalar>
+{
+    let (first, last) = (*x.first()?, *x.last()?);
+    let from = from.unwrap_or(first).max(first);
+    let to = to.unwrap_or(last).min(last);
+    if to <= from
+    {
+        return None;
+    }
+
+    // The window's samples, with its ends interpolated
+    let mut points = vec![(from, interpolate(x, y, from)?)];
+    points.extend(x.iter().copied().zip(y.iter().copied()).filter(|(x, _)| (*x > from) && (*x < to)));
+    points.push((to, interpolate(x, y, to)?));
+
+    let integral = |f: &dyn Fn(Scalar) -> Scalar| points.windows(2)
+        .map(|p| 0.5 * (f(p[0].1) + f(p[1].1)) * (p[1].0 - p[0].0))
+        .sum::<Scalar>();
+    let min = points.iter().map(|p| p.1).min_by(|a, b| a.total_cmp(b))?;
+    let max = points.iter().map(|p| p.1).max_by(|a, b| a.total_cmp(b))?;
+
+    Some(match statistic
+    {
+        Statistic::Average => integral(&|y| y) / (to - from),
+        Statistic::Rms => (integral(&|y| y * y) / (to - from)).sqrt(),
+        Statistic::Min => min,
+        Statistic::Max => max,
+        Statistic::PeakToPeak => max - min,
+        Statistic::Integral => integral(&|y| y),
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn rc_measurements()
+    {
+        let netlist = concat!(
+            "V1 1 0 1 AC 1\n",
+            "R1 1 2 1k\n",
+            "C1 2 0 1u IC=0\n",
+            "E1 3 0 2 0 100\n",
+            ".TRAN 1u 10m UIC\n",
+            ".AC DEC 100 1 1meg\n",
+            ".MEAS TRAN rise TRIG V(2) VAL=0.1 RISE=1 TARG V(2) VAL=0.9 RISE=1\n",
+            ".MEAS TRAN tau WHEN V(2)=0.632\n",
+            ".MEAS TRAN end FIND V(2) AT=5m\n",
+            ".MEAS TRAN slope DERIV V(2) AT=0\n",
+            ".MEAS TRAN charge INTEG I(C1)\n",
+            ".MEAS TRAN loss INTEG P(R1)\n",
+            ".MEAS TRAN swing PP V(2) FROM=1m TO=2m\n",
+            ".MEAS AC bw BW V(2)\n",
+            ".MEAS AC peak PEAK V(3)\n",
+            ".MEAS AC pm PM V(3)\n",
+            ".MEAS AC f3db WHEN VDB(2)=-3\n",
+            ".MEAS TRAN never WHEN V(2)=2\n").parse::<Netlist>().unwrap();
+
+        let measured = run(&netlist).unwrap();
+
+        let rc = 1e-3;
+        let f0 = 1.0 / (2.0 * std::f64::consts::PI * rc);
+        let expect = |name: &str, expected: Scalar, tolerance: Scalar| assert!((measured[name] - expected).abs() <= tolerance * expected.abs(), "{} = {}", name, measured[name]);
+
+        expect("rise", rc * 9.0_f64.ln(), 1e-2);
+        expect("tau", rc, 1e-2);
+        expect("end", 1.0 - (-5.0_f64).exp(), 1e-3);
+        expect("slope", 1.0 / rc, 1e-2);
+        expect("charge", 1e-6, 1e-2);
+        expect("loss", 0.5e-6, 1e-2);
+        expect("swing", (-1.0_f64).exp() - (-2.0_f64).exp(), 1e-2);
+        expect("bw", f0, 1e-2);
+        expect("f3db", f0, 1e-2);
+        expect("peak", 40.0, 1e-3);
+        expect("pm", 180.0 - 9999.0_f64.sqrt().atan().to_degrees(), 1e-2);
+        assert!(!measured.contains_key("never"));
+
+        // A failed simulation says why, rather than just failing
+        let sources = "V1 1 0 1\nV2 1 0 2\nR1 1 2 1k\nC1 2 0 1u\n.TRAN 1u 1m UIC\n.MEAS TRAN x MAX V(2)".parse::<Netlist>().unwrap();
+        assert!(matches!(run(&sources), Err(SimulationError::Singular { devices, .. }) if devices == ["V1", "V2"]));
+
+        // Measured once, not overwritten by a second run
+        let twice = "V1 1 0 1\nR1 1 2 1k\nC1 2 0 1u\n.TRAN 1u 1m UIC\n.TRAN 1u 2m UIC\n.MEAS TRAN x MAX V(2)".parse::<Netlist>().unwrap();
+        assert!(matches!(run(&twice), Err(SimulationError::InvalidResults { .. })));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use nalgebra::ComplexField;
+use crate::netlist::{Device, Netlist, NodeName, Unit};
+use crate::la::{Builder, EquationIndex, Matrix, System, VariableIndex};
+
+/// Modified nodal analysis layout - one unknown (and KCL
+/// equation) per non-ground node, plus a branch current
+/// unknown for every voltage-defined device.
+///
+/// Unknowns share the transient naming: "V_<node>" and
+/// "I_<device>", where device currents flow from
+/// `nodes()[1]` through the device into `nodes()[0]`.
+pub struct MnaLayout
+{
+    system: System,
+    nodes: HashMap<NodeName, VariableIndex>,
+    branches: HashMap<String, VariableIndex>,
+}
+
+impl MnaLayout
+{
+    pub fn new(netlist: &Netlist) -> Self
+    {
+        let gnd = NodeName::gnd();
+        let mut builder = Builder::new();
+        let mut nodes = HashMap::new();
+        let mut branches = HashMap::new();
+
+        for device in netlist.devices()
+        {
+            for node in device.nodes()
+            {
+                if (node != gnd) && !nodes.contains_key(&node)
+                {
+                    let _ = builder.new_equation();
+                    let var = builder.find_var(&format!("V_{}", node.name()));
+                    nodes.insert(node, var);
+                }
+            }
+        }
+
+        for device in netlist.devices()
+        {
+            if MnaLayout::has_branch(device)
+            {
+                let _ = builder.new_equation();
+                let var = builder.find_var(&format!("I_{}", device.name()));
+                branches.insert(device.name().to_owned(), var);
+            }
+        }
+
+        let system = builder.build();
+
+        MnaLayout { system, nodes, branches }
+    }
+
+    pub fn has_branch(device: &Device) -> bool
+    {
+        matches!(device, Device::Voltage { .. } | Device::Vcvs { .. })
+    }
+
+    pub fn system(&self) -> &System
+    {
+        &self.system
+    }
+
+    pub fn dim(&self) -> usize
+    {
+        self.system.dim()
+    }
+
+    /// The unknown for a node's voltage, or None for ground
+    pub fn node(&self, node: &NodeName) -> Option<VariableIndex>
+    {
+        self.nodes.get(node).copied()
+    }
+
+    pub fn branch(&self, device: &str) -> Option<VariableIndex>
+    {
+        self.branches.get(device).copied()
+    }
+
+    /// Volts or amps for each unknown, in order
+    pub fn units(&self) -> Vec<Unit>
+    {
+        let mut units = vec![Unit::VOLTS; self.dim()];
+        for var in self.branches.values()
+        {
+            units[var.into_index()] = Unit::AMPS;
+        }
+        units
+    }
+
+    pub fn voltage<T: Copy + Default>(&self, solution: &[T], node: &NodeName) -> T
+    {
+        match self.node(node)
+        {
+            Some(var) => solution[var.into_index()],
+            None => T::default(),
+        }
+    }
+}
+
+fn eq(var: VariableIndex) -> EquationIndex
+{
+    // Every unknown owns the equation with the same index
+    EquationIndex::from_index(var.into_index())
+}
+
+/// Admittance `y` between `plus` and `minus`
+pub fn stamp_admittance<T: ComplexField + Copy, M: Matrix<T>>(solver: &mut M, plus: Option<VariableIndex>, minus: Option<VariableIndex>, y: T)
+{
+    if let Some(p) = plus
+    {
+        *solver.coef(eq(p), p) += y;
+    }
+    if let Some(m) = minus
+    {
+        *solver.coef(eq(m), m) += y;
+    }
+    if let (Some(p), Some(m)) = (plus, minus)
+    {
+        *solver.coef(eq(p), m) -= y;
+        *solver.coef(eq(m), p) -= y;
+    }
+}
+
+/// Independent current `current` flowing out of `from`,
+/// through the device, and into `to`
+pub fn stamp_current<T: ComplexField + Copy, M: Matrix<T>>(solver: &mut M, from: Option<VariableIndex>, to: Option<VariableIndex>, current: T)
+{
+    if let Some(f) = from
+    {
+        *solver.constant(eq(f)) -= current;
+    }
+    if let Some(t) = to
+    {
+        *solver.constant(eq(t)) += current;
+    }
+}
+
+/// As `stamp_current`, into constants kept apart from the coefficients
+pub fn add_current<T: ComplexField + Copy>(constants: &mut [T], from: Option<VariableIndex>, to: Option<VariableIndex>, current: T)
+{
+    if let Some(f) = from
+    {
+        constants[f.into_index()] -= current;
+    }
+    if let Some(t) = to
+    {
+        constants[t.into_index()] += current;
+    }
+}
+
+/// Branch current unknown `branch` flowing out of `from`,
+/// through the device, and into `to`
+pub fn stamp_branch_current<T: ComplexField + Copy, M: Matrix<T>>(solver: &mut M, from: Option<VariableIndex>, to: Option<VariableIndex>, branch: VariableIndex)
+{
+    if let Some(f) = from
+    {
+        *solver.coef(eq(f), branch) += T::one();
+    }
+    if let Some(t) = to
+    {
+        *solver.coef(eq(t), branch) -= T::one();
+    }
+}
+
+/// Branch equation row: `factor` * (V(plus) - V(minus)) + ...
+pub fn stamp_branch_voltage<T: ComplexField + Copy, M: Matrix<T>>(solver: &mut M, branch: VariableIndex, plus: Option<VariableIndex>, minus: Option<VariableIndex>, factor: T)
+{
+    if let Some(p) = plus
+    {
+        *solver.coef(eq(branch), p) += factor;
+    }
+    if let Some(m) = minus
+    {
+        *solver.coef(eq(branch), m) -= factor;
+    }
+}
+
+pub fn branch_constant<T: ComplexField + Copy, M: Matrix<T>>(solver: &mut M, branch: VariableIndex) -> &mut T
+{
+    solver.constant(eq(branch))
+}
@@ -1 +1,33 @@
-pub mod transient;
\ No newline at end of file
+mod conditions;
+mod convergence;
+mod diode;
+mod error;
+mod mna;
+mod results;
+
+pub mod ac;
+pub mod analysis;
+pub mod fourier;
+pub mod measure;
+pub mod montecarlo;
+pub mod noise;
+pub mod op;
+pub mod power;
+pub mod step;
+pub mod transient;
+
+use crate::netlist::Scalar;
+
+pub const BOLTZMANN: Scalar = 1.380649e-23;
+pub const ELECTRON_CHARGE: Scalar = 1.602176634e-19;
+pub const ZERO_CELSIUS: Scalar = 273.15;
+
+pub use conditions::Conditions;
+pub use convergence::ConvergenceMethod;
+pub use error::SimulationError;
+pub use results::{Axis, Metadata, Signal, SignalKind, SimulationResults};
+
+pub fn thermal_voltage(temperature: Scalar) -> Scalar
+{
+    BOLTZMANN * temperature / ELECTRON_CHARGE
+}
@@ -0,0 +1,307 @@
+mod rng;
+
+use std::collections::BTreeMap;
+use crate::netlist::{Distribution, Netlist, Scalar, Tolerance};
+
+pub use rng::Rng;
+
+/// Re-runs a circuit with its toleranced component
+/// values (e.g. `R1 1 2 10k tol=1%`) varied, collecting
+/// whatever measurements the caller makes on each build.
+pub struct MonteCarlo
+{
+    netlist: Netlist,
+    tolerances: Vec<(String, Tolerance)>,
+}
+
+impl MonteCarlo
+{
+    pub fn new(netlist: &Netlist) -> Self
+    {
+        let tolerances = netlist.devices().iter()
+            .filter_map(|d| d.value()
+                .and_then(|v| v.tolerance())
+                .map(|t| (d.name().to_owned(), t.clone())))
+            .collect();
+
+        MonteCarlo { netlist: netlist.clone(), tolerances }
+    }
+
+    /// Builds `runs` circuits with values drawn from each
+    /// tolerance's distribution. `measure` is given each
+    /// circuit and returns named measurements - it may leave
+    /// out any it can't make (e.g. if the simulation failed).
+    pub fn run<F>(&self, runs: usize, seed: u64, mut measure: F) -> MonteCarloResults
+        where F: FnMut(&Netlist) -> BTreeMap<String, Scalar>
+    {
+        let mut rng = Rng::new(seed);
+        let mut statistics = BTreeMap::<String, Statistics>::new();
+
+        for _ in 0..runs
+        {
+            let deviations = self.tolerances.iter()
+                .map(|(name, tolerance)|
+                {
+                    let deviation = match tolerance.distribution
+                    {
+                        Distribution::Uniform => tolerance.relative * (2.0 * rng.uniform() - 1.0),
+                        Distribution::Gaussian => tolerance.relative / 3.0 * rng.gaussian(),
+                    };
+                    (name.clone(), deviation)
+                })
+                .collect();
+
+            for (name, value) in measure(&self.netlist.with_deviations(&deviations))
+            {
+                statistics.entry(name).or_default().samples.push(value);
+            }
+        }
+
+        MonteCarloResults { runs, statistics }
+    }
+
+    /// Finds the extreme corners of each measurement.
+    ///
+    /// Each toleranced value is first pushed to its upper
+    /// limit alone to find which way it moves each measurement,
+    /// then every value is set to the limit that drives the
+    /// measurement up (or down). This takes 1 + N + 2M runs
+    /// rather than 2^N, and is exact whenever measurements
+    /// are monotonic in each value over its tolerance.
+    pub fn worst_case<F>(&self, mut measure: F) -> BTreeMap<String, WorstCase>
+        where F: FnMut(&Netlist) -> BTreeMap<String, Scalar>
+    {
+        let nominal = measure(&self.netlist);
+
+        let sensitivities = self.tolerances.iter()
+            .map(|(name, tolerance)|
+            {
+                let deviations = BTreeMap::from([(name.clone(), tolerance.relative)]);
+                measure(&self.netlist.with_deviations(&deviations))
+            })
+            .collect::<Vec<_>>();
+
+        let mut results = BTreeMap::new();
+
+        for (measurement, nominal_value) in nominal.iter()
+        {
+            let corner = |direction: Scalar| -> BTreeMap<String, Scalar>
+            {
+                self.tolerances.iter().zip(sensitivities.iter())
+                    .map(|((name, tolerance), sensitivity)|
+                    {
+                        let delta = sensitivity.get(measurement).map(|v| v - nominal_value).unwrap_or(0.0);
+                        let sign = if delta < 0.0 { -direction } else { direction };
+                        (name.clone(), sign * tolerance.relative)
+                    })
+                    .collect()
+            };
+
+            let max_corner = corner(1.0);
+            let min_corner = corner(-1.0);
+
+            let max = measure(&self.netlist.with_deviations(&max_corner)).get(measurement).copied();
+            let min = measure(&self.netlist.with_deviations(&min_corner)).get(measurement).copied();
+
+            if let (Some(max), Some(min)) = (max, min)
+            {
+                results.insert(measurement.clone(), WorstCase
+                {
+                    nominal: *nominal_value,
+                    min: min.min(*nominal_value),
+                    max: max.max(*nominal_value),
+                    min_corner,
+                    max_corner,
+                });
+            }
+        }
+
+        results
+    }
+}
+
+pub struct MonteCarloResults
+{
+    runs: usize,
+    statistics: BTreeMap<String, Statistics>,
+}
+
+impl MonteCarloResults
+{
+    pub fn runs(&self) -> usize
+    {
+        self.runs
+    }
+
+    pub fn statistics(&self) -> &BTreeMap<String, Statistics>
+    {
+        &self.statistics
+    }
+
+    pub fn get(&self, measurement: &str) -> Option<&Statistics>
+    {
+        self.statistics.get(measurement)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Statistics
+{
+    samples: Vec<Scalar>,
+}
+
+impl Statistics
+{
+    pub fn samples(&self) -> &Vec<Scalar>
+    {
+        &self.samples
+    }
+
+    /// None for this and the other statistics
+    /// if there are no samples
+    pub fn mean(&self) -> Option<Scalar>
+    {
+        if self.samples.is_empty()
+        {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Scalar>() / (self.samples.len() as Scalar))
+    }
+
+    /// Sample standard deviation - zero for one sample
+    pub fn sigma(&self) -> Option<Scalar>
+    {
+        let mean = self.mean()?;
+        if self.samples.len() < 2
+        {
+            return Some(0.0);
+        }
+
+        let sum_sq = self.samples.iter().map(|s| (s - mean) * (s - mean)).sum::<Scalar>();
+        Some((sum_sq / ((self.samples.len() - 1) as Scalar)).sqrt())
+    }
+
+    pub fn min(&self) -> Option<Scalar>
+    {
+        self.samples.iter().copied().reduce(Scalar::min)
+    }
+
+    pub fn max(&self) -> Option<Scalar>
+    {
+        self.samples.iter().copied().reduce(Scalar::max)
+    }
+
+    /// Splits min..max into `bins` equal bins -
+    /// None if there are no bins or no samples
+    pub fn histogram(&self, bins: usize) -> Option<Histogram>
+    {
+        if bins == 0
+        {
+            return None;
+        }
+
+        let start = self.min()?;
+        let range = self.max()? - start;
+        let bin_width = if range > 0.0 { range / (bins as Scalar) } else { 1.0 };
+        let mut counts = vec![0; bins];
+
+        for sample in self.samples.iter()
+        {
+            let bin = (((sample - start) / bin_width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+
+        Some(Histogram { start, bin_width, counts })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram
+{
+    pub start: Scalar,
+    pub bin_width: Scalar,
+    pub counts: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorstCase
+{
+    pub nominal: Scalar,
+    pub min: Scalar,
+    pub max: Scalar,
+    /// Relative deviation of each toleranced device giving `min`
+    pub min_corner: BTreeMap<String, Scalar>,
+    /// Relative deviation of each toleranced device giving `max`
+    pub max_corner: BTreeMap<String, Scalar>,
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::{Analysis, Device};
+    use crate::sim::analysis::{self, AnalysisResults};
+
+    fn cutoff(netlist: &Netlist) -> BTreeMap<String, Scalar>
+    {
+        let r = netlist.device("R1").and_then(Device::value).unwrap().value();
+        let c = netlist.device("C1").and_then(Device::value).unwrap().value();
+        BTreeMap::from([("fc".to_owned(), 1.0 / (2.0 * std::f64::consts::PI * r * c))])
+    }
+
+    #[test]
+    fn rc_cutoff_spread()
+    {
+        let netlist = "V1 1 0 0\nR1 1 2 10k tol=1%\nC1 2 0 10n tol=10% dist=uniform".parse::<Netlist>().unwrap();
+        let nominal = cutoff(&netlist)["fc"];
+        let mc = MonteCarlo::new(&netlist);
+
+        let first = mc.run(500, 42, cutoff);
+        let second = mc.run(500, 42, cutoff);
+        assert_eq!(first.get("fc").unwrap().samples(), second.get("fc").unwrap().samples());
+
+        let stats = first.get("fc").unwrap();
+        assert!((stats.mean().unwrap() - nominal).abs() < nominal * 0.02);
+        // R1 is gaussian so may just pass its 3-sigma limit
+        assert!(stats.max().unwrap() < nominal / (0.98 * 0.9));
+        assert!(stats.min().unwrap() > nominal / (1.02 * 1.1));
+        assert_eq!(stats.histogram(10).unwrap().counts.iter().sum::<usize>(), 500);
+        assert!(stats.histogram(0).is_none());
+
+        let empty = Statistics::default();
+        assert_eq!((empty.mean(), empty.sigma(), empty.min(), empty.max()), (None, None, None, None));
+        assert!(empty.histogram(10).is_none());
+
+        let worst = &mc.worst_case(cutoff)["fc"];
+        assert!((worst.max - nominal / (0.99 * 0.9)).abs() < nominal * 1e-9);
+        assert!((worst.min - nominal / (1.01 * 1.1)).abs() < nominal * 1e-9);
+        assert_eq!(worst.max_corner["R1"], -0.01);
+    }
+
+    #[test]
+    fn simulated_divider_spread()
+    {
+        // Measured by solving each build, not from a formula
+        let netlist = "V1 1 0 10\nR1 1 2 1k tol=5% dist=uniform\nR2 2 0 1k tol=5% dist=uniform\n.OP".parse::<Netlist>().unwrap();
+        let output = |netlist: &Netlist| match analysis::run(netlist, &Analysis::Op)
+        {
+            Ok(AnalysisResults::Op(values)) => BTreeMap::from([("out".to_owned(), values["V_2"])]),
+            _ => BTreeMap::new(),
+        };
+
+        let mc = MonteCarlo::new(&netlist);
+        let results = mc.run(200, 7, output);
+        let stats = results.get("out").unwrap();
+        assert_eq!(stats.samples().len(), 200);
+
+        // Extremes are 10 * 0.95 / (1.05 + 0.95) and the reverse
+        let (low, high) = (10.0 * 0.95 / 2.0, 10.0 * 1.05 / 2.0);
+        assert!((stats.mean().unwrap() - 5.0).abs() < 0.05);
+        assert!((stats.min().unwrap() > low) && (stats.max().unwrap() < high));
+        assert!(stats.sigma().unwrap() > 0.01);
+
+        let worst = &mc.worst_case(output)["out"];
+        assert!((worst.min - low).abs() < 1e-9);
+        assert!((worst.max - high).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::netlist::Scalar;
+
+/// Small deterministic xoshiro256** generator - the same
+/// seed always gives the same sequence on every platform,
+/// so Monte Carlo runs can be reproduced exactly.
+#[derive(Debug, Clone)]
+pub struct Rng
+{
+    state: [u64; 4],
+}
+
+impl Rng
+{
+    pub fn new(seed: u64) -> Self
+    {
+        // Expand the seed with SplitMix64 so that
+        // nearby seeds give unrelated sequences
+
+        let mut x = seed;
+        let mut next = ||
+        {
+            x = x.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+
+        Rng { state: [next(), next(), next(), next()] }
+    }
+
+    pub fn next_u64(&mut self) -> u64
+    {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+
+    /// Uniform in [0, 1)
+    pub fn uniform(&mut self) -> Scalar
+    {
+        (self.next_u64() >> 11) as Scalar / (1u64 << 53) as Scalar
+    }
+
+    /// Standard normal (mean 0, sigma 1) by Box-Muller
+    pub fn gaussian(&mut self) -> Scalar
+    {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+use crate::netlist::{Device, Netlist, NodeName, NoiseAnalysis, Scalar};
+use crate::la::{Complex, EquationIndex, VariableIndex};
+use super::{diode, Conditions, BOLTZMANN, ELECTRON_CHARGE};
+use super::ac::AcSimulation;
+
+/// `.NOISE` analysis - every resistor gets a thermal noise
+/// current source and every diode shot and flicker noise.
+/// Each is propagated to the output through the adjoint of
+/// the AC system, so one extra solve per frequency gives
+/// the gain from every source at once.
+pub struct NoiseSimulation
+{
+    ac: AcSimulation,
+    output: NodeName,
+    reference: NodeName,
+    input: String,
+    frequencies: Vec<Scalar>,
+    sources: Vec<NoiseSource>,
+}
+
+impl NoiseSimulation
+{
+    /// Returns None if the operating point can't be found
+    pub fn new(netlist: &Netlist, analysis: &NoiseAnalysis) -> Option<Self>
+    {
+        let ac = AcSimulation::new(netlist)?;
+        let conditions = Conditions::new(netlist).ok()?;
+        let vt = conditions.thermal_voltage();
+        let mut sources = Vec::new();
+
+        for device in netlist.devices()
+        {
+            match device
+            {
+                Device::Resistor { name, plus, minus, resistance, tc1, tc2 } =>
+                {
+                    // Thermal: i^2 = 4kT/R
+                    let plus = ac.layout().node(plus);
+                    let minus = ac.layout().node(minus);
+                    let resistance = conditions.resistance(resistance.value(), *tc1, *tc2);
+                    let white = 4.0 * BOLTZMANN * conditions.temperature() / resistance;
+                    sources.push(NoiseSource { device: name.clone(), plus, minus, white, flicker: 0.0 });
+                },
+                Device::Diode { name, plus, minus, model } =>
+                {
+                    // Shot: i^2 = 2qId
+                    // Flicker: i^2 = KF.Id^AF / f
+                    let vd = ac.operating_point().voltage(plus) - ac.operating_point().voltage(minus);
+                    let (id, _) = diode::evaluate(&conditions.diode(model), vt, conditions.gmin(), vd);
+                    let plus = ac.layout().node(plus);
+                    let minus = ac.layout().node(minus);
+                    let white = 2.0 * ELECTRON_CHARGE * id.abs();
+                    let flicker = model.kf * id.abs().powf(model.af);
+                    sources.push(NoiseSource { device: name.clone(), plus, minus, white, flicker });
+                },
+                _ => (),
+            }
+        }
+
+        Some(NoiseSimulation
+        {
+            ac,
+            output: analysis.output.clone(),
+            reference: analysis.reference.clone(),
+            input: analysis.input.clone(),
+            frequencies: analysis.sweep.values(),
+            sources,
+        })
+    }
+
+    /// Returns None if the system is singular at any frequency
+    pub fn simulate(&self) -> Option<NoiseResults>
+    {
+        let layout = self.ac.layout();
+        let output = layout.node(&self.output);
+        let reference = layout.node(&self.reference);
+        let input = layout.branch(&self.input)?;
+
+        let mut gain = Vec::with_capacity(self.frequencies.len());
+        let mut output_psd = Vec::with_capacity(self.frequencies.len());
+        let mut contributions = BTreeMap::<String, Vec<Scalar>>::new();
+
+        for frequency in self.frequencies.iter()
+        {
+            // Adjoint system - select V(output) - V(reference)
+            // so the solution is d(output)/d(constant) for
+            // every equation
+
+            let mut solver = self.ac.fill(*frequency, false);
+            if let Some(output) = output
+            {
+                *solver.constant(equation(output)) += Complex::new(1.0, 0.0);
+            }
+            if let Some(reference) = reference
+            {
+                *solver.constant(equation(reference)) -= Complex::new(1.0, 0.0);
+            }
+            let adjoint = solver.solve_transposed()?;
+
+            // Gain from the input source is the sensitivity
+            // to its branch equation's constant
+
+            gain.push(adjoint[input.into_index()]);
+
+            let mut total = 0.0;
+            let mut device_totals = BTreeMap::new();
+
+            for source in self.sources.iter()
+            {
+                // A current i from plus to minus sets the
+                // constants -i at plus and +i at minus
+                let sensitivity = |var: Option<VariableIndex>| var.map(|v| adjoint[v.into_index()]).unwrap_or_default();
+                let transfer = sensitivity(source.minus) - sensitivity(source.plus);
+
+                let psd = transfer.norm_sqr() * source.psd(*frequency);
+                total += psd;
+                *device_totals.entry(source.device.clone()).or_insert(0.0) += psd;
+            }
+
+            output_psd.push(total);
+            for (device, psd) in device_totals
+            {
+                contributions.entry(device).or_default().push(psd);
+            }
+        }
+
+        Some(NoiseResults
+        {
+            frequencies: self.frequencies.clone(),
+            gain,
+            output_psd,
+            contributions,
+        })
+    }
+}
+
+fn equation(var: VariableIndex) -> EquationIndex
+{
+    EquationIndex::from_index(var.into_index())
+}
+
+struct NoiseSource
+{
+    device: String,
+    plus: Option<VariableIndex>,
+    minus: Option<VariableIndex>,
+    /// Frequency independent current PSD (A^2/Hz)
+    white: Scalar,
+    /// Current PSD at 1Hz of the 1/f component (A^2/Hz)
+    flicker: Scalar,
+}
+
+impl NoiseSource
+{
+    fn psd(&self, frequency: Scalar) -> Scalar
+    {
+        self.white + self.flicker / frequency
+    }
+}
+
+pub struct NoiseResults
+{
+    frequencies: Vec<Scalar>,
+    gain: Vec<Complex<Scalar>>,
+    output_psd: Vec<Scalar>,
+    contributions: BTreeMap<String, Vec<Scalar>>,
+}
+
+impl NoiseResults
+{
+    pub fn frequencies(&self) -> &Vec<Scalar>
+    {
+        &self.frequencies
+    }
+
+    /// Small-signal gain from the input source to the output
+    pub fn gain(&self) -> &Vec<Complex<Scalar>>
+    {
+        &self.gain
+    }
+
+    /// Output noise spectral density (V/sqrt(Hz))
+    pub fn output_density(&self) -> Vec<Scalar>
+    {
+        self.output_psd.iter().map(|psd| psd.sqrt()).collect()
+    }
+
+    /// Output noise referred back to the input source (V/sqrt(Hz))
+    pub fn input_density(&self) -> Vec<Scalar>
+    {
+        self.input_psd().iter().map(|psd| psd.sqrt()).collect()
+    }
+
+    /// Each device's contribution to the output noise
+    /// power spectral density (V^2/Hz)
+    pub fn contributions(&self) -> &BTreeMap<String, Vec<Scalar>>
+    {
+        &self.contributions
+    }
+
+    /// Total RMS output noise (V) between `start` and `stop` Hz
+    pub fn integrated_output(&self, start: Scalar, stop: Scalar) -> Scalar
+    {
+        integrate(&self.frequencies, &self.output_psd, start, stop).sqrt()
+    }
+
+    /// Total RMS input-referred noise (V) between `start` and `stop` Hz
+    pub fn integrated_input(&self, start: Scalar, stop: Scalar) -> Scalar
+    {
+        integrate(&self.frequencies, &self.input_psd(), start, stop).sqrt()
+    }
+
+    /// RMS output noise (V) from a single device between `start` and `stop` Hz
+    pub fn integrated_contribution(&self, device: &str, start: Scalar, stop: Scalar) -> Option<Scalar>
+    {
+        self.contributions.get(device)
+            .map(|psd| integrate(&self.frequencies, psd, start, stop).sqrt())
+    }
+
+    fn input_psd(&self) -> Vec<Scalar>
+    {
+        self.output_psd.iter().zip(self.gain.iter())
+            .map(|(psd, gain)| psd / gain.norm_sqr())
+            .collect()
+    }
+}
+
+/// Trapezoidal integral of `values` over `frequencies`,
+/// clipped to the band `start..stop`
+fn integrate(frequencies: &[Scalar], values: &[Scalar], start: Scalar, stop: Scalar) -> Scalar
+{
+    let mut total = 0.0;
+
+    for i in 1..frequencies.len()
+    {
+        let (f0, f1) = (frequencies[i - 1], frequencies[i]);
+        let (v0, v1) = (values[i - 1], values[i]);
+
+        let lo = f0.max(start);
+        let hi = f1.min(stop);
+        if hi <= lo
+        {
+            continue;
+        }
+
+        let at = |f: Scalar| v0 + (v1 - v0) * (f - f0) / (f1 - f0);
+        total += 0.5 * (at(lo) + at(hi)) * (hi - lo);
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Analysis;
+    use crate::sim::op::OperatingPoint;
+
+    #[test]
+    fn resistor_divider_thermal_noise()
+    {
+        let netlist = "V1 1 0 0\nR1 1 2 1k\nR2 2 0 1k\n.NOISE V(2) V1 LIN 10 1 10001".parse::<Netlist>().unwrap();
+        let Analysis::Noise(analysis) = &netlist.analyses()[0] else { panic!() };
+
+        let results = NoiseSimulation::new(&netlist, analysis).unwrap().simulate().unwrap();
+
+        // Output sees both resistors in parallel: 4kT(R1||R2)
+        let expected = (4.0 * BOLTZMANN * Conditions::new(&netlist).unwrap().temperature() * 500.0).sqrt();
+        for density in results.output_density()
+        {
+            assert!((density - expected).abs() < expected * 1e-9);
+        }
+
+        // Divider halves the input, so input noise is double
+        for density in results.input_density()
+        {
+            assert!((density - 2.0 * expected).abs() < expected * 1e-9);
+        }
+
+        let integrated = results.integrated_output(1.0, 10001.0);
+        assert!((integrated - expected * 100.0).abs() < expected * 1e-6);
+
+        let r1 = results.integrated_contribution("R1", 1.0, 10001.0).unwrap();
+        let r2 = results.integrated_contribution("R2", 1.0, 10001.0).unwrap();
+        assert!((r1 - r2).abs() < r1 * 1e-9);
+    }
+
+    #[test]
+    fn diode_shot_and_flicker_noise()
+    {
+        let netlist = "V1 1 0 5\nR1 1 2 1k\nD1 2 0 DN\n.MODEL DN D(KF=1e-14 AF=1.5)\n.NOISE V(2) V1 DEC 1 1 1meg".parse::<Netlist>().unwrap();
+        let Analysis::Noise(analysis) = &netlist.analyses()[0] else { panic!() };
+
+        let results = NoiseSimulation::new(&netlist, analysis).unwrap().simulate().unwrap();
+        let id = OperatingPoint::solve(&netlist).unwrap().branch_current("V1").unwrap().abs();
+
+        // R1 and D1 see the same impedance, so R1's
+        // contribution gives its square
+        let temperature = Conditions::new(&netlist).unwrap().temperature();
+        let r1 = &results.contributions()["R1"];
+        let d1 = &results.contributions()["D1"];
+        for ((frequency, r1), d1) in results.frequencies().iter().zip(r1.iter()).zip(d1.iter())
+        {
+            let impedance_squared = r1 / (4.0 * BOLTZMANN * temperature / 1e3);
+            let expected = impedance_squared * (2.0 * ELECTRON_CHARGE * id + 1e-14 * id.powf(1.5) / frequency);
+            assert!((d1 - expected).abs() < expected * 1e-3);
+        }
+
+        // Flicker dominates at 1Hz, shot noise at 1MHz
+        let shot = 2.0 * ELECTRON_CHARGE * id;
+        assert!(1e-14 * id.powf(1.5) > 10.0 * shot);
+        assert!(1e-14 * id.powf(1.5) / 1e6 < 0.1 * shot);
+    }
+}
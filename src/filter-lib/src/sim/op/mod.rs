@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use crate::netlist::{Device, Netlist, NodeName, Scalar};
+use crate::la::{EquationIndex, VariableIndex};
+use super::{diode, mna, Conditions, ConvergenceMethod};
+use super::mna::MnaLayout;
+
+/// Pseudo-transient continuation - conductance (S) of the
+/// capacitors for the first pseudo step, and the smallest
+/// before they're taken out
+const PSEUDO_START: Scalar = 1.0;
+const PSEUDO_END: Scalar = 1e-12;
+const PSEUDO_MAX_STEPS: usize = 200;
+
+/// DC operating point - capacitors open,
+/// sources at their t=0 value, diodes solved
+/// with Newton-Raphson iteration.
+pub struct OperatingPoint
+{
+    layout: MnaLayout,
+    solution: Vec<Scalar>,
+    method: ConvergenceMethod,
+}
+
+impl OperatingPoint
+{
+    /// Returns None if the circuit is singular or
+    /// Newton-Raphson fails to converge
+    pub fn solve(netlist: &Netlist) -> Option<Self>
+    {
+        Self::solve_holding(netlist, &BTreeMap::new())
+    }
+
+    /// Operating point with the `held` node voltages fixed -
+    /// as `.IC` does for the start of a transient analysis.
+    ///
+    /// If Newton-Raphson doesn't converge, gmin stepping,
+    /// source stepping and then pseudo-transient continuation
+    /// are tried - `method` says which worked.
+    pub fn solve_holding(netlist: &Netlist, held: &BTreeMap<String, Scalar>) -> Option<Self>
+    {
+        let layout = MnaLayout::new(netlist);
+        let conditions = Conditions::new(netlist).ok()?;
+        let circuit = Circuit { netlist, layout: &layout, conditions: &conditions, held };
+        let start = circuit.start();
+
+        let (iterate, method) = circuit.newton(&start, &Aid::NONE).map(|i| (i, ConvergenceMethod::Newton))
+            .or_else(|| circuit.gmin_stepping(&start).map(|i| (i, ConvergenceMethod::GminStepping)))
+            .or_else(|| circuit.source_stepping(&start).map(|i| (i, ConvergenceMethod::SourceStepping)))
+            .or_else(|| circuit.pseudo_transient(&start).map(|i| (i, ConvergenceMethod::PseudoTransient)))?;
+
+        Some(OperatingPoint { layout, solution: iterate.solution, method })
+    }
+
+    /// How the solve converged
+    pub fn method(&self) -> ConvergenceMethod
+    {
+        self.method
+    }
+
+    /// Every unknown, in the MNA layout's order
+    pub fn solution(&self) -> &Vec<Scalar>
+    {
+        &self.solution
+    }
+
+    pub fn voltage(&self, node: &NodeName) -> Scalar
+    {
+        self.layout.voltage(&self.solution, node)
+    }
+
+    /// Current through a voltage-defined device, if it has a branch
+    pub fn branch_current(&self, device: &str) -> Option<Scalar>
+    {
+        self.layout.branch(device).map(|var| self.solution[var.into_index()])
+    }
+
+    pub fn results(&self) -> BTreeMap<String, Scalar>
+    {
+        self.layout.system().variables().iter().cloned()
+            .zip(self.solution.iter().copied())
+            .collect()
+    }
+}
+
+/// Unknowns and diode junction voltages
+/// during Newton-Raphson iteration
+#[derive(Clone)]
+struct Iterate
+{
+    solution: Vec<Scalar>,
+    junctions: Vec<Scalar>,
+}
+
+/// Changes to the circuit while a convergence aid is
+/// stepping - a conductance from every node to ground,
+/// pulling it towards `target` (or ground), and a
+/// scale on the sources
+struct Aid<'a>
+{
+    conductance: Scalar,
+    target: Option<&'a [Scalar]>,
+    source_scale: Scalar,
+}
+
+impl Aid<'_>
+{
+    const NONE: Aid<'static> = Aid { conductance: 0.0, target: None, source_scale: 1.0 };
+}
+
+struct Circuit<'a>
+{
+    netlist: &'a Netlist,
+    layout: &'a MnaLayout,
+    conditions: &'a Conditions,
+    held: &'a BTreeMap<String, Scalar>,
+}
+
+impl Circuit<'_>
+{
+    /// Starts from the .NODESET and held voltages
+    fn start(&self) -> Iterate
+    {
+        let layout = self.layout;
+        let vt = self.conditions.thermal_voltage();
+
+        let mut solution = vec![0.0; layout.dim()];
+        for (node, voltage) in self.netlist.nodesets().iter().chain(self.held.iter())
+        {
+            if let Some(var) = layout.node(&NodeName::new(node.clone()))
+            {
+                solution[var.into_index()] = *voltage;
+            }
+        }
+
+        // Junction voltage each diode is linearised around
+        let junctions = self.netlist.devices().iter()
+            .filter_map(|d| match d
+            {
+                Device::Diode { plus, minus, model, .. } =>
+                {
+                    let vd = layout.voltage(&solution, plus) - layout.voltage(&solution, minus);
+                    Some(diode::limit(vd, 0.0, &self.conditions.diode(model), vt))
+                },
+                _ => None,
+            })
+            .collect();
+
+        Iterate { solution, junctions }
+    }
+
+    /// Newton-Raphson from `start` with the circuit changed by `aid`
+    fn newton(&self, start: &Iterate, aid: &Aid) -> Option<Iterate>
+    {
+        let layout = self.layout;
+        let conditions = self.conditions;
+        let vt = conditions.thermal_voltage();
+        let options = self.netlist.options();
+        let Iterate { mut solution, mut junctions } = start.clone();
+
+        for _ in 0..options.itl1
+        {
+            let mut solver = layout.system().new_solver();
+            let mut junction_index = 0;
+
+            for device in self.netlist.devices()
+            {
+                match device
+                {
+                    Device::Voltage { name, plus, minus, voltage, .. } =>
+                    {
+                        let branch = layout.branch(name).unwrap();
+                        let (plus, minus) = (layout.node(plus), layout.node(minus));
+                        mna::stamp_branch_current(&mut solver, minus, plus, branch);
+                        mna::stamp_branch_voltage(&mut solver, branch, plus, minus, 1.0);
+                        *mna::branch_constant(&mut solver, branch) = voltage.calc(0.0) * aid.source_scale;
+                    },
+                    Device::Resistor { plus, minus, resistance, tc1, tc2, .. } =>
+                    {
+                        let resistance = conditions.resistance(resistance.value(), *tc1, *tc2);
+                        mna::stamp_admittance(&mut solver, layout.node(plus), layout.node(minus), 1.0 / resistance);
+                    },
+                    Device::Capacitor { .. } =>
+                    {
+                        // Open circuit
+                    },
+                    Device::Diode { plus, minus, model, .. } =>
+                    {
+                        // Linearise around the junction voltage:
+                        // I = Id + gd.(V - Vd)
+                        let vd = junctions[junction_index];
+                        junction_index += 1;
+
+                        let (id, gd) = diode::evaluate(&conditions.diode(model), vt, conditions.gmin(), vd);
+                        let (plus, minus) = (layout.node(plus), layout.node(minus));
+                        mna::stamp_admittance(&mut solver, plus, minus, gd);
+                        mna::stamp_current(&mut solver, plus, minus, id - gd * vd);
+                    },
+                    Device::Vcvs { name, plus, minus, control_plus, control_minus, gain } =>
+                    {
+                        let branch = layout.branch(name).unwrap();
+                        let (plus, minus) = (layout.node(plus), layout.node(minus));
+                        mna::stamp_branch_current(&mut solver, plus, minus, branch);
+                        mna::stamp_branch_voltage(&mut solver, branch, plus, minus, 1.0);
+                        mna::stamp_branch_voltage(&mut solver, branch, layout.node(control_plus), layout.node(control_minus), -gain.value());
+                    },
+                }
+            }
+
+            if aid.conductance > 0.0
+            {
+                for (i, var) in layout.system().variables().iter().enumerate()
+                {
+                    if var.starts_with("V_")
+                    {
+                        let (eq, var) = (EquationIndex::from_index(i), VariableIndex::from_index(i));
+                        *solver.coef(eq, var) += aid.conductance;
+                        *solver.constant(eq) += aid.conductance * aid.target.map(|t| t[i]).unwrap_or(0.0);
+                    }
+                }
+            }
+
+            // Replace each held node's equation with V = held
+
+            for (node, voltage) in self.held.iter()
+            {
+                if let Some(var) = layout.node(&NodeName::new(node.clone()))
+                {
+                    let eq = EquationIndex::from_index(var.into_index());
+                    for other in 0..layout.dim()
+                    {
+                        *solver.coef(eq, VariableIndex::from_index(other)) = 0.0;
+                    }
+                    *solver.coef(eq, var) = 1.0;
+                    *solver.constant(eq) = *voltage;
+                }
+            }
+
+            let new_solution = solver.solve()?;
+
+            if new_solution.iter().any(|v| !v.is_finite())
+            {
+                return None;
+            }
+
+            let mut converged = new_solution.iter().zip(solution.iter())
+                .all(|(new, old)| (new - old).abs() <= options.reltol * new.abs().max(old.abs()) + options.vntol);
+
+            // Move each junction to its new voltage - limited so
+            // the exponential can't run away before the next iteration
+
+            let mut junction_index = 0;
+            for device in self.netlist.devices()
+            {
+                if let Device::Diode { plus, minus, model, .. } = device
+                {
+                    let new_vd = layout.voltage(&new_solution, plus) - layout.voltage(&new_solution, minus);
+                    let limited = diode::limit(new_vd, junctions[junction_index], &conditions.diode(model), vt);
+
+                    if limited != new_vd
+                    {
+                        converged = false;
+                    }
+
+                    junctions[junction_index] = limited;
+                    junction_index += 1;
+                }
+            }
+
+            solution = new_solution;
+
+            if converged
+            {
+                return Some(Iterate { solution, junctions });
+            }
+        }
+
+        None
+    }
+
+    fn gmin_stepping(&self, start: &Iterate) -> Option<Iterate>
+    {
+        ConvergenceMethod::gmin_steps()
+            .try_fold(start.clone(), |iterate, conductance| self.newton(&iterate, &Aid { conductance, ..Aid::NONE }))
+    }
+
+    fn source_stepping(&self, start: &Iterate) -> Option<Iterate>
+    {
+        // Everything is zero with no sources
+        let zero = Iterate { solution: vec![0.0; start.solution.len()], junctions: vec![0.0; start.junctions.len()] };
+
+        ConvergenceMethod::source_steps()
+            .try_fold(zero, |iterate, source_scale| self.newton(&iterate, &Aid { source_scale, ..Aid::NONE }))
+    }
+
+    /// Backward Euler through the capacitors, growing the step
+    /// while it converges and shrinking it when it doesn't,
+    /// until the capacitors make no difference
+    fn pseudo_transient(&self, start: &Iterate) -> Option<Iterate>
+    {
+        let mut iterate = start.clone();
+        let mut conductance = PSEUDO_START;
+
+        for _ in 0..PSEUDO_MAX_STEPS
+        {
+            let aid = Aid { conductance, target: Some(&iterate.solution), ..Aid::NONE };
+            match self.newton(&iterate, &aid)
+            {
+                Some(next) =>
+                {
+                    iterate = next;
+                    conductance /= 4.0;
+                    if conductance < PSEUDO_END
+                    {
+                        return self.newton(&iterate, &Aid::NONE);
+                    }
+                },
+                None =>
+                {
+                    conductance *= 16.0;
+                    if conductance > 1.0 / PSEUDO_END
+                    {
+                        return None;
+                    }
+                },
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn convergence_aids_agree()
+    {
+        let netlist = "V1 1 0 1\nE1 2 0 1 3 1e6\nD1 2 4\nD2 4 3\nR1 3 0 1k\nD3 3 0".parse::<Netlist>().unwrap();
+        let expected = OperatingPoint::solve(&netlist).unwrap();
+        assert_eq!(expected.method(), ConvergenceMethod::Newton);
+
+        let layout = MnaLayout::new(&netlist);
+        let conditions = Conditions::new(&netlist).unwrap();
+        let held = BTreeMap::new();
+        let circuit = Circuit { netlist: &netlist, layout: &layout, conditions: &conditions, held: &held };
+        let start = circuit.start();
+
+        for iterate in [circuit.gmin_stepping(&start), circuit.source_stepping(&start), circuit.pseudo_transient(&start)]
+        {
+            for (actual, expected) in iterate.unwrap().solution.iter().zip(expected.solution().iter())
+            {
+                assert!((actual - expected).abs() <= 1e-3 * expected.abs() + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn aids_take_over_from_newton()
+    {
+        // Stacked diodes hard on from 20V need more
+        // Newton iterations than ITL1 allows
+        let solve = |itl1: usize|
+        {
+            let netlist = format!("V1 1 0 20\nR1 1 2 10\nD1 2 3\nD2 3 4\nD3 4 0\n.OPTIONS ITL1={}", itl1).parse::<Netlist>().unwrap();
+            let op = OperatingPoint::solve(&netlist).unwrap();
+            (op.method(), op.voltage(&NodeName::new("2".to_owned())))
+        };
+
+        let (method, expected) = solve(200);
+        assert_eq!(method, ConvergenceMethod::Newton);
+        for (itl1, aid) in [(7, ConvergenceMethod::SourceStepping), (3, ConvergenceMethod::PseudoTransient)]
+        {
+            let (method, voltage) = solve(itl1);
+            assert_eq!(method, aid);
+            assert!((voltage - expected).abs() < 1e-5);
+        }
+    }
+}
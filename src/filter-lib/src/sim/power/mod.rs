@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use crate::netlist::{Device, Netlist, NodeName, Scalar};
+use super::{SimulationError, SimulationResults};
+
+/// Power into one device over a transient run - positive
+/// while it absorbs power, negative while it delivers it
+#[derive(Debug, Clone)]
+pub struct DevicePower
+{
+    /// At each time (W)
+    pub power: Vec<Scalar>,
+    /// Over the run (W)
+    pub average: Scalar,
+    pub rms: Scalar,
+    /// Absorbed over the run (J)
+    pub energy: Scalar,
+}
+
+/// Where the energy went over a transient run. The
+/// sources' energy must be dissipated or stored, so
+/// anything left over is numerical error.
+#[derive(Debug, Clone)]
+pub struct EnergyBalance
+{
+    /// By voltage sources and VCVSs (J)
+    pub delivered: Scalar,
+    /// In resistors and diodes (J)
+    pub dissipated: Scalar,
+    /// Change in the capacitors' 1/2.C.V^2 (J)
+    pub stored: Scalar,
+}
+
+#[derive(Debug, Clone)]
+pub struct PowerResults
+{
+    pub devices: BTreeMap<String, DevicePower>,
+    pub balance: EnergyBalance,
+}
+
+impl EnergyBalance
+{
+    /// Energy unaccounted for (J)
+    pub fn error(&self) -> Scalar
+    {
+        self.delivered - self.dissipated - self.stored
+    }
+
+    /// Error as a fraction of the largest of the energies
+    pub fn relative_error(&self) -> Scalar
+    {
+        let scale = self.delivered.abs().max(self.dissipated.abs()).max(self.stored.abs());
+        if scale == 0.0 { 0.0 } else { self.error().abs() / scale }
+    }
+
+    /// Whether the relative error is within `tolerance` -
+    /// if not, the timestep is probably too long
+    pub fn is_balanced(&self, tolerance: Scalar) -> bool
+    {
+        self.relative_error() <= tolerance
+    }
+}
+
+/// Power in every device from transient results with
+/// every node voltage and device current recorded, as
+/// `TransientSimulation` does without probes
+pub fn analyse(netlist: &Netlist, results: &SimulationResults) -> Result<PowerResults, SimulationError>
+{
+    let times = results.points();
+    let signal = |name: String| results.get(&name).ok_or(SimulationError::Unknown { name });
+    let voltage = |node: &NodeName| -> Result<Option<&Vec<Scalar>>, SimulationError>
+    {
+        if *node == NodeName::gnd() { Ok(None) } else { signal(format!("V_{}", node.name())).map(Some) }
+    };
+    let at = |values: Option<&Vec<Scalar>>, i: usize| values.map(|v| v[i]).unwrap_or(0.0);
+
+    let mut devices = BTreeMap::new();
+    let mut balance = EnergyBalance { delivered: 0.0, dissipated: 0.0, stored: 0.0 };
+
+    for device in netlist.devices()
+    {
+        let (plus, minus) = match device
+        {
+            Device::Voltage { plus, minus, .. } | Device::Resistor { plus, minus, .. } | Device::Capacitor { plus, minus, .. }
+                | Device::Diode { plus, minus, .. } | Device::Vcvs { plus, minus, .. } => (voltage(plus)?, voltage(minus)?),
+        };
+        let current = signal(format!("I_{}", device.name()))?;
+
+        // Voltage source currents are out of the plus
+        // terminal, everything else's into it
+        let sign = if let Device::Voltage { .. } = device { -1.0 } else { 1.0 };
+        let power = (0..times.len())
+            .map(|i| sign * (at(plus, i) - at(minus, i)) * current[i])
+            .collect::<Vec<_>>();
+
+        let result = summarise(times, power);
+
+        match device
+        {
+            Device::Voltage { .. } | Device::Vcvs { .. } => balance.delivered -= result.energy,
+            Device::Resistor { .. } | Device::Diode { .. } => balance.dissipated += result.energy,
+            Device::Capacitor { capacitance, .. } =>
+            {
+                if let Some(last) = times.len().checked_sub(1)
+                {
+                    let v = |i| at(plus, i) - at(minus, i);
+                    balance.stored += 0.5 * capacitance.value() * (v(last) * v(last) - v(0) * v(0));
+                }
+            },
+        }
+
+        devices.insert(device.name().to_owned(), result);
+    }
+
+    Ok(PowerResults { devices, balance })
+}
+
+/// Average, RMS and energy of a power waveform,
+/// integrated with the trapezoidal rule
+fn summarise(times: &[Scalar], power: Vec<Scalar>) -> DevicePower
+{
+    let mut energy = 0.0;
+    let mut squared = 0.0;
+    for (t, p) in times.windows(2).zip(power.windows(2))
+    {
+        let dt = t[1] - t[0];
+        energy += 0.5 * (p[0] + p[1]) * dt;
+        squared += 0.5 * (p[0] * p[0] + p[1] * p[1]) * dt;
+    }
+
+    let duration = times.last().zip(times.first()).map(|(last, first)| last - first).unwrap_or(0.0);
+    let (average, rms) = if duration > 0.0
+    {
+        (energy / duration, (squared / duration).sqrt())
+    }
+    else
+    {
+        let first = power.first().copied().unwrap_or(0.0);
+        (first, first.abs())
+    };
+
+    DevicePower { power, average, rms, energy }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn rc_energy_balances()
+    {
+        // Charging 1uF to 1V through 1k stores 0.5uJ and, after
+        // many time constants, dissipates as much again
+        let netlist = "V1 1 0 PULSE(0 1 0 1n 1n 1)\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+        let values = TransientSimulation::new(&netlist).unwrap().simulate(1e-6, 20001).unwrap();
+
+        let results = analyse(&netlist, &values).unwrap();
+        assert!((results.devices["R1"].energy - 0.5e-6).abs() < 1e-8);
+        assert!((results.balance.stored - 0.5e-6).abs() < 1e-9);
+        assert!((results.devices["V1"].energy + 1e-6).abs() < 1e-8);
+        assert!(results.devices["V1"].average < 0.0);
+        assert!(results.balance.is_balanced(1e-3));
+    }
+}
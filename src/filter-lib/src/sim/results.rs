@@ -0,0 +1,333 @@
+use std::ops::{Add, Index, Mul, Sub};
+use crate::netlist::{IntegrationMethod, Scalar, SimulationOptions, Unit};
+use super::SimulationError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis
+{
+    /// Seconds
+    Time,
+    /// Hz
+    Frequency,
+    /// A DC sweep's source value
+    Sweep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalKind
+{
+    Voltage,
+    Current,
+    Power,
+    /// Anything else a probe expression works out to,
+    /// e.g. a gain
+    Other,
+}
+
+impl SignalKind
+{
+    pub fn of(unit: Unit) -> SignalKind
+    {
+        match unit
+        {
+            Unit::VOLTS => SignalKind::Voltage,
+            Unit::AMPS => SignalKind::Current,
+            Unit::WATTS => SignalKind::Power,
+            _ => SignalKind::Other,
+        }
+    }
+}
+
+/// One recorded value at every point on the axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal<T = Scalar>
+{
+    /// e.g. "V_2", "I_R1" or a probe such as "V(2,3)"
+    pub name: String,
+    pub unit: Unit,
+    pub kind: SignalKind,
+    pub values: Vec<T>,
+}
+
+impl<T> Signal<T>
+{
+    /// The same signal with other values
+    pub fn with_values<U>(&self, values: Vec<U>) -> Signal<U>
+    {
+        Signal { name: self.name.clone(), unit: self.unit, kind: self.kind, values }
+    }
+}
+
+/// How the results were produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata
+{
+    /// None for frequency domain analyses
+    pub method: Option<IntegrationMethod>,
+    /// Between points - None if they aren't evenly spaced
+    pub step: Option<Scalar>,
+    pub options: SimulationOptions,
+}
+
+/// Signals against time, or against frequency with
+/// complex values for an AC sweep. Index by name for
+/// a signal's values, e.g. `results["V_2"]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationResults<T = Scalar>
+{
+    axis: Axis,
+    points: Vec<Scalar>,
+    signals: Vec<Signal<T>>,
+    metadata: Metadata,
+}
+
+impl<T: Clone> SimulationResults<T>
+{
+    /// Every signal must have a value for each point,
+    /// and the points must be increasing
+    pub fn new(axis: Axis, points: Vec<Scalar>, signals: Vec<Signal<T>>, metadata: Metadata) -> Result<Self, SimulationError>
+    {
+        if let Some(signal) = signals.iter().find(|signal| signal.values.len() != points.len())
+        {
+            let reason = format!("{} has {} values but there are {} points", signal.name, signal.values.len(), points.len());
+            return Err(SimulationError::InvalidResults { reason });
+        }
+        Ok(SimulationResults { axis, points, signals, metadata })
+    }
+
+    pub fn axis(&self) -> Axis
+    {
+        self.axis
+    }
+
+    /// Times or frequencies
+    pub fn points(&self) -> &[Scalar]
+    {
+        &self.points
+    }
+
+    pub fn signals(&self) -> &[Signal<T>]
+    {
+        &self.signals
+    }
+
+    pub fn into_signals(self) -> Vec<Signal<T>>
+    {
+        self.signals
+    }
+
+    pub fn metadata(&self) -> &Metadata
+    {
+        &self.metadata
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.points.is_empty()
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&Signal<T>>
+    {
+        self.signals.iter().find(|signal| signal.name == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Vec<T>>
+    {
+        self.signal(name).map(|signal| &signal.values)
+    }
+
+    /// Each point, with every signal's value there in order
+    pub fn iter(&self) -> impl Iterator<Item = (Scalar, Vec<T>)> + '_
+    {
+        self.points.iter().enumerate()
+            .map(|(i, point)| (*point, self.signals.iter().map(|signal| signal.values[i].clone()).collect()))
+    }
+
+    /// Just the points from `from` to `to` inclusive
+    pub fn window(&self, from: Scalar, to: Scalar) -> Self
+    {
+        let start = self.points.partition_point(|p| *p < from);
+        let end = self.points.partition_point(|p| *p <= to).max(start);
+
+        SimulationResults
+        {
+            axis: self.axis,
+            points: self.points[start..end].to_vec(),
+            signals: self.signals.iter()
+                .map(|signal| signal.with_values(signal.values[start..end].to_vec()))
+                .collect(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Appends the results of carrying on the same simulation,
+    /// which must have the same signals and start after these end
+    pub fn concat(&mut self, other: &SimulationResults<T>) -> Result<(), SimulationError>
+    {
+        let mismatch = (0..self.signals.len().max(other.signals.len()))
+            .find(|i| self.signals.get(*i).map(|s| &s.name) != other.signals.get(*i).map(|s| &s.name));
+        if let Some(i) = mismatch
+        {
+            let signal = self.signals.get(i).or_else(|| other.signals.get(i)).unwrap();
+            return Err(SimulationError::Unknown { name: signal.name.clone() });
+        }
+
+        match (self.points.last(), other.points.first())
+        {
+            (Some(end), Some(start)) =>
+            {
+                let gap = start - end;
+                if gap <= 0.0
+                {
+                    return Err(SimulationError::InvalidTimestep { step: gap, reason: "concatenated results must start after the others end".to_owned() });
+                }
+
+                // Still evenly spaced only if the join is the same step
+                let even = self.metadata.step.is_some_and(|step| (gap - step).abs() <= 1e-9 * step)
+                    && ((other.len() == 1) || (other.metadata.step == self.metadata.step));
+                if !even
+                {
+                    self.metadata.step = None;
+                }
+            },
+            (None, _) => self.metadata.step = other.metadata.step,
+            _ => (),
+        }
+
+        self.points.extend_from_slice(&other.points);
+        for (signal, other) in self.signals.iter_mut().zip(other.signals.iter())
+        {
+            signal.values.extend_from_slice(&other.values);
+        }
+        Ok(())
+    }
+}
+
+impl<T> SimulationResults<T>
+    where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Scalar, Output = T>
+{
+    /// A signal's value at any time (or frequency) between
+    /// the first and last points, linearly interpolated
+    pub fn interpolate(&self, name: &str, at: Scalar) -> Option<T>
+    {
+        let values = self.get(name)?;
+        interpolate(&self.points, values, at)
+    }
+
+    /// Evenly spaced every `step` from the first point
+    /// to the last, linearly interpolated
+    pub fn resample(&self, step: Scalar) -> Result<Self, SimulationError>
+    {
+        if !step.is_finite() || (step <= 0.0)
+        {
+            return Err(SimulationError::InvalidTimestep { step, reason: "must be positive and finite".to_owned() });
+        }
+
+        let points = match (self.points.first(), self.points.last())
+        {
+            (Some(first), Some(last)) =>
+            {
+                let count = ((last - first) / step + 1e-9).floor() as usize + 1;
+                (0..count).map(|i| first + (i as Scalar) * step).collect()
+            },
+            _ => Vec::new(),
+        };
+
+        let signals = self.signals.iter()
+            .map(|signal| signal.with_values(points.iter()
+                .map(|p| interpolate(&self.points, &signal.values, *p).unwrap_or(*signal.values.last().unwrap()))
+                .collect()))
+            .collect();
+
+        Ok(SimulationResults { axis: self.axis, points, signals, metadata: Metadata { step: Some(step), ..self.metadata.clone() } })
+    }
+}
+
+impl<T: Clone> Index<&str> for SimulationResults<T>
+{
+    type Output = Vec<T>;
+
+    fn index(&self, name: &str) -> &Vec<T>
+    {
+        self.get(name).unwrap_or_else(|| panic!("no signal \"{}\"", name))
+    }
+}
+
+fn interpolate<T>(points: &[Scalar], values: &[T], at: Scalar) -> Option<T>
+    where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Scalar, Output = T>
+{
+    let i = points.partition_point(|p| *p < at);
+    if i >= points.len()
+    {
+        return None;
+    }
+    if points[i] == at
+    {
+        return Some(values[i]);
+    }
+    if i == 0
+    {
+        return None;
+    }
+    let fraction = (at - points[i - 1]) / (points[i] - points[i - 1]);
+    Some(values[i - 1] + (values[i] - values[i - 1]) * fraction)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Netlist;
+    use crate::sim::ac::AcSimulation;
+    use crate::sim::transient::TransientSimulation;
+
+    #[test]
+    fn successive_runs_concatenate()
+    {
+        let netlist = "V1 1 0 sin(1000*t)\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+
+        let whole = TransientSimulation::new(&netlist).unwrap().simulate(1e-5, 200).unwrap();
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        let mut joined = simulation.simulate(1e-5, 120).unwrap();
+        let rest = simulation.simulate(1e-5, 80).unwrap();
+        assert!((rest.points()[0] - 120e-5).abs() < 1e-12);
+        joined.concat(&rest).unwrap();
+        assert_eq!(joined.metadata().step, Some(1e-5));
+        assert_eq!(joined.len(), whole.len());
+        for (a, b) in joined["V_2"].iter().zip(whole["V_2"].iter())
+        {
+            assert!((a - b).abs() < 1e-12);
+        }
+        assert!(joined.concat(&rest).is_err());
+
+        let signal = joined.signal("I_R1").unwrap();
+        assert_eq!((signal.unit, signal.kind), (Unit::AMPS, SignalKind::Current));
+
+        let short = vec![signal.with_values(vec![0.0])];
+        let error = SimulationResults::new(Axis::Time, vec![0.0, 1e-5], short, joined.metadata().clone());
+        assert!(matches!(error, Err(SimulationError::InvalidResults { .. })));
+
+        let ac = AcSimulation::new(&netlist).unwrap().simulate(&[100.0]).unwrap();
+        let units = ["V_1", "V_2", "I_V1"].map(|name| ac.signal(name).unwrap().unit);
+        assert_eq!(units, [Unit::VOLTS, Unit::VOLTS, Unit::AMPS]);
+
+        let window = joined.window(49.5e-5, 60.5e-5);
+        assert_eq!(window.len(), 11);
+        assert_eq!(window["V_2"][0], joined["V_2"][50]);
+
+        let halfway = joined.interpolate("V_2", 50.5e-5).unwrap();
+        assert!((halfway - 0.5 * (joined["V_2"][50] + joined["V_2"][51])).abs() < 1e-12);
+        assert!(joined.interpolate("V_2", 1.0).is_none());
+
+        let resampled = joined.resample(2e-5).unwrap();
+        assert_eq!(resampled.len(), 100);
+        assert!((resampled["V_2"][10] - joined["V_2"][20]).abs() < 1e-12);
+        assert_eq!(resampled.iter().nth(10).unwrap().0, resampled.points()[10]);
+    }
+}
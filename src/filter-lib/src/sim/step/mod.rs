@@ -0,0 +1,113 @@
+use crate::netlist::{Analysis, Netlist, OptionError, Scalar, StepTarget};
+use super::analysis::{self, AnalysisResults};
+use super::SimulationError;
+
+/// Runs an analysis once for every combination of
+/// the netlist's `.STEP` values - nested in the order
+/// the `.STEP` cards appear.
+pub struct StepSimulation
+{
+    variants: Vec<(Vec<(String, Scalar)>, Netlist)>,
+}
+
+impl StepSimulation
+{
+    /// Fails if a stepped temperature is out of range
+    pub fn new(netlist: &Netlist) -> Result<Self, OptionError>
+    {
+        let mut variants = vec![(Vec::new(), netlist.clone())];
+
+        for step in netlist.steps()
+        {
+            let values = step.sweep.values();
+            let mut next = Vec::with_capacity(variants.len() * values.len());
+
+            for (label, variant) in variants.iter()
+            {
+                for value in values.iter()
+                {
+                    let (name, stepped) = match &step.target
+                    {
+                        StepTarget::Parameter(name) => (name.clone(), variant.with_parameter(name, *value)),
+                        StepTarget::Device(name) => (name.clone(), variant.with_device_value(name, *value)),
+                        StepTarget::Temperature => ("TEMP".to_owned(), variant.with_temperature(*value)?),
+                    };
+
+                    let mut label = label.clone();
+                    label.push((name, *value));
+                    next.push((label, stepped));
+                }
+            }
+
+            variants = next;
+        }
+
+        Ok(StepSimulation { variants })
+    }
+
+    pub fn netlists(&self) -> impl Iterator<Item = &Netlist>
+    {
+        self.variants.iter().map(|(_, netlist)| netlist)
+    }
+
+    pub fn simulate(&self, analysis: &Analysis) -> Vec<StepResult>
+    {
+        self.variants.iter()
+            .map(|(values, netlist)| StepResult
+            {
+                values: values.clone(),
+                results: analysis::run(netlist, analysis),
+            })
+            .collect()
+    }
+}
+
+pub struct StepResult
+{
+    /// Each stepped parameter or device, and its value for this run
+    pub values: Vec<(String, Scalar)>,
+    /// Why this run's circuit couldn't be solved, if it couldn't
+    pub results: Result<AnalysisResults, SimulationError>,
+}
+
+impl StepResult
+{
+    /// e.g. "R1=1000 gain=2"
+    pub fn label(&self) -> String
+    {
+        self.values.iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn stepped_divider()
+    {
+        let netlist = concat!(
+            "V1 1 0 10\n",
+            "R1 1 2 {rtop}\n",
+            "R2 2 0 1k\n",
+            ".PARAM rtop=1k\n",
+            ".STEP PARAM rtop LIST 1k 3k 9k\n",
+            ".STEP R2 LIN 1k 2k 1k\n",
+            ".OP\n").parse::<Netlist>().unwrap();
+
+        let runs = StepSimulation::new(&netlist).unwrap().simulate(&netlist.analyses()[0]);
+        assert_eq!(runs.len(), 6);
+        assert_eq!(runs[1].label(), "rtop=1000 R2=2000");
+
+        for run in runs.iter()
+        {
+            let (top, bottom) = (run.values[0].1, run.values[1].1);
+            let Ok(AnalysisResults::Op(values)) = &run.results else { panic!("{} didn't solve", run.label()) };
+            assert!((values["V_2"] - 10.0 * bottom / (top + bottom)).abs() < 1e-9, "{}", run.label());
+        }
+    }
+}
@@ -1,305 +1,1708 @@
 use std::collections::BTreeMap;
-use crate::netlist::{Device, Exp, Netlist, NodeName, Scalar};
-use crate::la::{Builder, EquationIndex, Solver, System, VariableIndex};
+use crate::netlist::{Analysis, Device, DiodeModel, Exp, IntegrationMethod, Netlist, NodeName, Probe, Scalar, SimulationOptions, Unit};
+use crate::dsp::Decimator;
+use crate::la::{EquationIndex, LuFactors, Matrix, Solver, SolverBackend, SparseSolver, VariableIndex};
+use super::{diode, mna, Axis, Conditions, ConvergenceMethod, Metadata, Signal, SignalKind, SimulationError, SimulationResults};
+use super::mna::MnaLayout;
+use super::op::OperatingPoint;
 
-const DIODE_MIN_CONDUCTANCE: Scalar = 1.0 / 100_000_000.0;
-const DIODE_IS: Scalar = 1e-12;
-const DIODE_1_ON_N_VT: Scalar = 1.0 / 1.5 / 0.025852;
+mod processor;
+mod state;
+pub use processor::TransientProcessor;
+pub use state::TransientState;
 
+/// Local truncation error allowed in each capacitor's
+/// voltage per step, relative to the voltage
+const LTE_RELTOL: Scalar = 1e-3;
+/// Local truncation error always allowed (V)
+const LTE_ABSTOL: Scalar = 1e-6;
+
+/// Convergence aids recorded, so a long run that keeps
+/// needing them doesn't allocate
+const MAX_AIDS: usize = 64;
+
+/// Transient analysis with modified nodal analysis - the
+/// unknowns are the non-ground node voltages and the branch
+/// currents of voltage sources. Every other device's current
+/// is worked out from the solution, so results still have
+/// "V_<node>" for every node and "I_<device>" for every device -
+/// or, once probes are set, just the probes.
 pub struct TransientSimulation
 {
-    system: System,
-    equations: Vec<Equation>,
-    time: Scalar
+    layout: MnaLayout,
+    elements: Vec<Element>,
+    /// Unknowns, then the currents worked out from them
+    names: Vec<String>,
+    /// Next output time
+    time: Scalar,
+    /// Time of the last accepted step
+    solved: Option<Scalar>,
+    /// Unknowns and device currents of the last accepted step
+    values: Vec<Scalar>,
+    /// Step the adaptive mode will try first
+    next_step: Option<Scalar>,
+    options: SimulationOptions,
+    /// Newton-Raphson absolute tolerance of each unknown
+    tolerances: Vec<Scalar>,
+    /// Kept between solves when the sparse backend is selected
+    sparse: Option<SparseSolver>,
+    /// Last dense factorisation
+    dense: Option<LuFactors>,
+    /// Coefficients that can change between solves, as they
+    /// were when the current factorisation was made
+    factored: Option<Vec<Scalar>>,
+    /// Times the stepping system has been factored
+    factorisations: usize,
+    /// No nonlinear devices - every solve is exact
+    linear: bool,
+    /// Each device and the unknowns of its terminals and
+    /// branch, to say which are involved in a singular matrix
+    terminals: Vec<(String, Vec<VariableIndex>)>,
+    /// Where to find each device's voltage and current
+    ports: BTreeMap<String, Port>,
+    /// What's recorded instead of every value, with
+    /// where to find each of the probe's terms
+    probes: Vec<(Probe, Vec<Term>)>,
+    /// Results up to the point a simulation failed
+    partial: Option<SimulationResults>,
+    /// Convergence aids in use - a conductance from every
+    /// node to ground, and a scale on the voltage sources
+    gmin: Scalar,
+    source_scale: Scalar,
+    /// Where plain Newton-Raphson failed, and what worked
+    aids: Vec<(Scalar, ConvergenceMethod)>,
+    // Reused between steps so solving doesn't allocate
+    solution: Vec<Scalar>,
+    previous: Vec<Scalar>,
+    constants: Vec<Scalar>,
+    coefficients: Vec<Scalar>,
+    junctions: Vec<Scalar>,
 }
 
 impl TransientSimulation
 {
-    pub fn new(netlist: &Netlist) -> Self
+    /// As `with_uic`, with `UIC` if the netlist's
+    /// first `.TRAN` has it
+    pub fn new(netlist: &Netlist) -> Result<Self, SimulationError>
     {
-        let gnd = NodeName::gnd();
-        let mut builder = Builder::new();
-        let mut equations = Vec::new();
-
-        // First - reference V_GND as 0 Volts
-
-        {
-            let _ = builder.new_equation();
-            let gnd = builder.find_var(&format!("V_{}", gnd.name()));
-            equations.push(Equation::GndRef { gnd });
-        }
-
-        // The sum of currents into every node
-        // must be zero. Skip the GND node
-        // as it's a redundant equation
-
-        for node in netlist.nodes()
-        {
-            if node != gnd
+        let uic = netlist.analyses().iter()
+            .find_map(|a| match a
             {
-                let mut currents = Vec::new();
-                for device in netlist.devices()
-                {
-                    if let Some(factor) = device.flow_into_node(&node)
-                    {
-                        let var = builder.find_var(&format!("I_{}", device.name()));
-                        currents.push((var, factor));
-                    }
-                }
-                equations.push(Equation::NodeCurrents { currents });
-            }
-        }
+                Analysis::Transient(tran) => Some(tran.uic),
+                _ => None,
+            })
+            .unwrap_or(false);
+        Self::with_uic(netlist, uic)
+    }
 
-        // Finally - every device generates an equation
+    /// Starts from the operating point, with any `.IC` node
+    /// voltages held, unless `uic` - then capacitors start at
+    /// their `IC=` voltage, or that from the `.IC` node
+    /// voltages (zero if not given).
+    ///
+    /// Fails if a device's value isn't finite or positive -
+    /// e.g. a zero ohm resistor - or there's no operating point.
+    pub fn with_uic(netlist: &Netlist, uic: bool) -> Result<Self, SimulationError>
+    {
+        let layout = MnaLayout::new(netlist);
+        let conditions = Conditions::new(netlist)?;
+        let options = netlist.options().clone();
+        let method = options.method;
+        let mut elements = Vec::new();
+        let mut invalid = Vec::new();
 
         for device in netlist.devices()
         {
+            let name = device.name().to_owned();
+
             match device
             {
                 Device::Voltage { plus, minus, voltage, .. } =>
                 {
-                    let plus = builder.find_var(&format!("V_{}", plus.name()));
-                    let minus = builder.find_var(&format!("V_{}", minus.name()));
+                    let branch = layout.branch(&name).unwrap();
+                    let (plus, minus) = (layout.node(plus), layout.node(minus));
                     let voltage = voltage.clone();
-                    equations.push(Equation::Voltage { voltage, plus, minus });
+                    elements.push(Element::Voltage { branch, plus, minus, voltage });
                 },
-                Device::Resistor { name, plus, minus, resistance } =>
+                Device::Resistor { plus, minus, resistance, tc1, tc2, .. } =>
                 {
-                    let current = builder.find_var(&format!("I_{}", name));
-                    let plus = builder.find_var(&format!("V_{}", plus.name()));
-                    let minus = builder.find_var(&format!("V_{}", minus.name()));
-                    let conductance = 1.0 / resistance.value();
-                    equations.push(Equation::Conductance { current, plus, minus, conductance });
+                    let (plus, minus) = (layout.node(plus), layout.node(minus));
+                    let conductance = 1.0 / conditions.resistance(resistance.value(), *tc1, *tc2);
+                    elements.push(Element::Conductance { name, plus, minus, conductance });
                 },
-                Device::Capacitor { name, plus, minus, capacitance } =>
+                Device::Capacitor { plus, minus, capacitance, .. } =>
                 {
-                    let current = builder.find_var(&format!("I_{}", name));
-                    let plus = builder.find_var(&format!("V_{}", plus.name()));
-                    let minus = builder.find_var(&format!("V_{}", minus.name()));
+                    let (plus, minus) = (layout.node(plus), layout.node(minus));
                     let capacitance = capacitance.value();
-                    let voltage = 0.0;
-                    equations.push(Equation::Capacitor { current, plus, minus, capacitance, voltage });
+                    let history = CapacitorHistory::default();
+                    elements.push(Element::Capacitor { name, plus, minus, capacitance, method, history });
                 },
-                Device::Diode { name, plus, minus} =>
+                Device::Diode { plus, minus, model, .. } =>
                 {
-                    let current_var = builder.find_var(&format!("I_{}", name));
-                    let plus_voltage_var = builder.find_var(&format!("V_{}", plus.name()));
-                    let minus_voltage_var = builder.find_var(&format!("V_{}", minus.name()));
-                    let conductance = DIODE_MIN_CONDUCTANCE;
-                    let offset_voltage = 0.0;
-                    equations.push(Equation::Diode { conductance, offset_voltage, plus_voltage_var, minus_voltage_var, current_var });
+                    let (plus, minus) = (layout.node(plus), layout.node(minus));
+                    let model = conditions.diode(model);
+                    let vt = conditions.thermal_voltage();
+                    let gmin = conditions.gmin();
+                    let junction_voltage = 0.0;
+                    elements.push(Element::Diode { name, plus, minus, model, vt, gmin, junction_voltage });
                 },
                 Device::Vcvs { plus, minus, control_plus, control_minus, gain, .. } =>
                 {
-                    let plus_voltage_var = builder.find_var(&format!("V_{}", plus.name()));
-                    let minus_voltage_var = builder.find_var(&format!("V_{}", minus.name()));
-                    let control_plus_voltage_var = builder.find_var(&format!("V_{}", control_plus.name()));
-                    let control_minus_voltage_var = builder.find_var(&format!("V_{}", control_minus.name()));
+                    let branch = layout.branch(&name).unwrap();
+                    let (plus, minus) = (layout.node(plus), layout.node(minus));
+                    let (control_plus, control_minus) = (layout.node(control_plus), layout.node(control_minus));
                     let gain = gain.value();
-
-                    equations.push(Equation::Vcvs{ plus_voltage_var, minus_voltage_var, control_plus_voltage_var, control_minus_voltage_var, gain});
+                    elements.push(Element::Vcvs { branch, plus, minus, control_plus, control_minus, gain });
                 },
             }
+
+            if !elements.last().unwrap().is_finite()
+            {
+                invalid.push(device.name().to_owned());
+            }
+        }
+
+        if !invalid.is_empty()
+        {
+            return Err(SimulationError::NotFinite { time: None, names: invalid });
         }
 
-        let system = builder.build();
+        let terminals = netlist.devices().iter()
+            .map(|device|
+            {
+                let mut vars = device.nodes().iter().filter_map(|node| layout.node(node)).collect::<Vec<_>>();
+                vars.extend(layout.branch(device.name()));
+                (device.name().to_owned(), vars)
+            })
+            .collect();
+
+        let mut names = layout.system().variables().clone();
+        names.extend(elements.iter().filter_map(|e| e.name()).map(|name| format!("I_{}", name)));
+
+        let ports = netlist.devices().iter()
+            .map(|device|
+            {
+                let (plus, minus, sign) = match device
+                {
+                    Device::Voltage { plus, minus, .. } => (plus, minus, -1.0),
+                    Device::Resistor { plus, minus, .. } | Device::Capacitor { plus, minus, .. }
+                        | Device::Diode { plus, minus, .. } | Device::Vcvs { plus, minus, .. } => (plus, minus, 1.0),
+                };
+                let current = names.iter().position(|n| *n == format!("I_{}", device.name())).unwrap();
+                (device.name().to_owned(), Port { plus: layout.node(plus), minus: layout.node(minus), current, sign })
+            })
+            .collect();
+
+        let tolerances = layout.system().variables().iter()
+            .map(|var| if var.starts_with("I_") { options.abstol } else { options.vntol })
+            .collect();
+
+        let linear = !elements.iter().any(|e| matches!(e, Element::Diode { .. }));
         let time = 0.0;
 
-        TransientSimulation{ system, equations, time }
+        let mut simulation = TransientSimulation
+        {
+            layout, elements, names, time, solved: None, values: Vec::new(), next_step: None, options, tolerances,
+            sparse: None, dense: None, factored: None, factorisations: 0, linear, terminals, ports, probes: Vec::new(), partial: None,
+            gmin: 0.0, source_scale: 1.0, aids: Vec::with_capacity(MAX_AIDS),
+            solution: Vec::new(), previous: Vec::new(), constants: Vec::new(), coefficients: Vec::new(), junctions: Vec::new(),
+        };
+        simulation.set_backend(simulation.options.backend);
+        simulation.initialise(netlist, uic)?;
+
+        Ok(simulation)
     }
 
-    pub fn simulate(&mut self, delta_t: Scalar, steps: usize) -> BTreeMap<String, Vec<Scalar>>
+    /// Sets the capacitor voltages and diode junctions to start from
+    fn initialise(&mut self, netlist: &Netlist, uic: bool) -> Result<(), SimulationError>
     {
-        let mut results = vec![vec![0.0;steps];self.system.dim()];
+        let layout = &self.layout;
 
-        for step in 0..steps
+        let initial = if uic
         {
-            let time = (step as Scalar) * delta_t + self.time;
-
-            let mut solver = self.system.new_solver();
-            for (i, eq) in self.equations.iter().enumerate()
+            let mut initial = vec![0.0; layout.dim()];
+            for (node, value) in netlist.initial_conditions()
             {
-                eq.fill(&mut solver, EquationIndex::from_index(i), time);
+                if let Some(var) = layout.node(&NodeName::new(node.clone()))
+                {
+                    initial[var.into_index()] = *value;
+                }
             }
-
-            match solver.solve()
+            initial
+        }
+        else
+        {
+            match OperatingPoint::solve_holding(netlist, netlist.initial_conditions())
             {
-                Some(solution) =>
+                Some(op) =>
                 {
-                    for eq in self.equations.iter_mut()
-                    {
-                        eq.update(&solution, delta_t);
-                    }
-
-                    for (var_index, var_solution) in solution.iter().enumerate()
+                    if op.method() != ConvergenceMethod::Newton
                     {
-                        results[var_index][step] = *var_solution;
+                        self.record_aid(0.0, op.method());
                     }
+                    op.solution().clone()
                 },
                 None =>
                 {
-                    println!("Solution failed at time={}", time);
-                    let mut solver = self.system.new_solver();
-                    for (i, eq) in self.equations.iter().enumerate()
+                    // Say what's wrong if it's singular even with
+                    // the capacitors in - otherwise it's only the
+                    // operating point that can't be found
+                    return Err(match self.singular(0.0, 1.0)
                     {
-                        eq.fill(&mut solver, EquationIndex::from_index(i), time);
-                    }
-                    self.system.print(&solver);
-                    panic!();
+                        SimulationError::Singular { nodes, devices, .. } if nodes.is_empty() && devices.is_empty() => SimulationError::NoOperatingPoint,
+                        error => error,
+                    });
+                },
+            }
+        };
+
+        for (device, element) in netlist.devices().iter().zip(self.elements.iter_mut())
+        {
+            match (device, element)
+            {
+                (Device::Capacitor { ic, .. }, Element::Capacitor { plus, minus, history, .. }) =>
+                {
+                    let voltage = match ic
+                    {
+                        Some(ic) if uic => *ic,
+                        _ => voltage(&initial, *plus) - voltage(&initial, *minus),
+                    };
+                    *history = CapacitorHistory::new(voltage);
+                },
+                (_, Element::Diode { plus, minus, model, vt, junction_voltage, .. }) =>
+                {
+                    // Limited so the first linearisation can't overflow
+                    let voltage = voltage(&initial, *plus) - voltage(&initial, *minus);
+                    *junction_voltage = diode::limit(voltage, 0.0, model, *vt);
                 },
+                _ => (),
             }
         }
-        self.time += (steps as Scalar) * delta_t;
 
-        results.into_iter().enumerate()
-            .map(|(var_index, var_results)| (self.system.variables()[var_index].clone(), var_results))
-            .collect()
+        Ok(())
     }
-}
 
-pub enum Equation
-{
-    GndRef{gnd: VariableIndex},
-    NodeCurrents{currents: Vec<(VariableIndex, Scalar)>},
-    Voltage{voltage: Exp, plus: VariableIndex, minus: VariableIndex},
-    Conductance{conductance: Scalar, plus: VariableIndex, minus: VariableIndex, current: VariableIndex},
-    Capacitor{capacitance: Scalar, plus: VariableIndex, minus: VariableIndex, current: VariableIndex, voltage: Scalar},
-    Diode{conductance: Scalar, offset_voltage: Scalar, plus_voltage_var: VariableIndex, minus_voltage_var: VariableIndex, current_var: VariableIndex},
-    Vcvs
+    pub fn set_backend(&mut self, backend: SolverBackend)
     {
-        gain: Scalar,
-        plus_voltage_var: VariableIndex,
-        minus_voltage_var: VariableIndex,
-        control_plus_voltage_var: VariableIndex,
-        control_minus_voltage_var: VariableIndex,
-    },
-}
+        self.sparse = match backend
+        {
+            SolverBackend::Dense => None,
+            SolverBackend::Sparse => Some(self.layout.system().new_sparse_solver()),
+        };
+        self.dense = None;
+        self.factored = None;
+    }
 
-impl Equation
-{
-    pub fn fill(&self, solver: &mut Solver, eq: EquationIndex, time: Scalar)
+    /// From now on only record these probes, named as they
+    /// display - e.g. "V(2,3)" - rather than every value
+    pub fn set_probes(&mut self, probes: &[Probe]) -> Result<(), SimulationError>
     {
-        match self
+        self.probes = probes.iter()
+            .map(|probe|
+            {
+                let terms = probe.terms().into_iter()
+                    .map(|term| self.term(term).ok_or_else(|| SimulationError::Unknown { name: term.to_string() }))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((probe.clone(), terms))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Name and unit of everything recorded, in order
+    pub fn signals(&self) -> Vec<(String, Unit)>
+    {
+        if self.probes.is_empty()
+        {
+            self.names.iter()
+                .map(|name| (name.clone(), if name.starts_with("I_") { Unit::AMPS } else { Unit::VOLTS }))
+                .collect()
+        }
+        else
+        {
+            self.probes.iter()
+                .map(|(probe, _)| (probe.to_string(), probe.unit()))
+                .collect()
+        }
+    }
+
+    fn term(&self, probe: &Probe) -> Option<Term>
+    {
+        let node = |node: &NodeName| if *node == NodeName::gnd()
+        {
+            Some(None)
+        }
+        else
+        {
+            self.layout.node(node).map(Some)
+        };
+
+        match probe
+        {
+            Probe::Voltage { node: plus, reference } => Some(Term::Voltage(node(plus)?, node(reference)?)),
+            Probe::Current(device) => self.ports.get(device).map(|port| Term::Current(port.current)),
+            Probe::Power(device) => self.ports.get(device).map(|port| Term::Power(*port)),
+            _ => None,
+        }
+    }
+
+    /// Passes each recorded value of the
+    /// last accepted step to `push`, in order
+    fn record(&self, mut push: impl FnMut(usize, Scalar))
+    {
+        if self.probes.is_empty()
+        {
+            for (i, value) in self.values.iter().enumerate()
+            {
+                push(i, *value);
+            }
+            return;
+        }
+
+        for (i, (probe, terms)) in self.probes.iter().enumerate()
+        {
+            let mut terms = terms.iter();
+            push(i, probe.evaluate(&mut || terms.next().unwrap().value(&self.values)));
+        }
+    }
+
+    fn recorded(&self) -> Vec<Scalar>
+    {
+        let mut result = Vec::new();
+        self.record(|_, value| result.push(value));
+        result
+    }
+
+    /// Fixed steps of `delta_t`, carrying on from the end of
+    /// the last call. On failure the steps solved so far are
+    /// kept in `partial_results`.
+    pub fn simulate(&mut self, delta_t: Scalar, steps: usize) -> Result<SimulationResults, SimulationError>
+    {
+        self.partial = None;
+        check_step(delta_t, "must be positive and finite")?;
+
+        let mut times = Vec::with_capacity(steps);
+        let mut results = vec![Vec::with_capacity(steps); self.signals().len()];
+
+        for step in 0..steps
+        {
+            let time = (step as Scalar) * delta_t + self.time;
+
+            match self.solve(time, delta_t)
+            {
+                Ok(()) =>
+                {
+                    self.accept(time, delta_t);
+                    times.push(time);
+                    self.record(|i, value| results[i].push(value));
+                },
+                Err(error) =>
+                {
+                    self.time = time;
+                    self.partial = self.results(times, results, Some(delta_t)).ok();
+                    return Err(error);
+                },
+            }
+        }
+        self.time += (steps as Scalar) * delta_t;
+
+        self.results(times, results, Some(delta_t))
+    }
+
+    /// Same output as `simulate`, but internally each step is
+    /// split into `factor` and the results pass through an
+    /// anti-aliasing filter, so nonlinear devices alias less.
+    /// The filter doesn't delay the results.
+    pub fn simulate_oversampled(&mut self, delta_t: Scalar, steps: usize, factor: usize) -> Result<SimulationResults, SimulationError>
+    {
+        let Some(mut decimator) = Decimator::new(factor) else
+        {
+            return Err(SimulationError::InvalidTimestep { step: delta_t, reason: "oversampling factor must be at least one".to_owned() });
+        };
+        let Some(oversampled_steps) = steps.checked_mul(factor) else
+        {
+            return Err(SimulationError::InvalidTimestep { step: delta_t, reason: "too many oversampled steps".to_owned() });
+        };
+
+        // Each signal primes the filter, so one does them all
+        let mut decimate = |results: SimulationResults|
         {
-            Equation::GndRef { gnd } =>
+            let times = results.points().iter().step_by(factor).copied().collect();
+            let metadata = Metadata { step: Some(delta_t), ..results.metadata().clone() };
+            let signals = results.into_signals().iter()
+                .map(|signal| signal.with_values(decimator.decimate(&signal.values)))
+                .collect();
+            SimulationResults::new(Axis::Time, times, signals, metadata)
+        };
+
+        match self.simulate(delta_t / (factor as Scalar), oversampled_steps)
+        {
+            Ok(results) => decimate(results),
+            Err(error) =>
             {
-                // V_GND = 0
-                *solver.coef(eq, *gnd) = 1.0;
+                self.partial = self.partial.take().and_then(|partial| decimate(partial).ok());
+                Err(error)
             },
-            Equation::NodeCurrents { currents } =>
+        }
+    }
+
+    /// Each time plain Newton-Raphson failed and a convergence
+    /// aid was needed, and which one worked - time zero for
+    /// the operating point. Only the first 64 are kept.
+    pub fn convergence_aids(&self) -> &[(Scalar, ConvergenceMethod)]
+    {
+        &self.aids
+    }
+
+    fn record_aid(&mut self, time: Scalar, method: ConvergenceMethod)
+    {
+        if self.aids.len() < MAX_AIDS
+        {
+            self.aids.push((time, method));
+        }
+    }
+
+    /// Results up to the step that failed, if the
+    /// last simulation returned an error
+    pub fn partial_results(&self) -> Option<&SimulationResults>
+    {
+        self.partial.as_ref()
+    }
+
+    /// Same output as `simulate`, but internally the step
+    /// varies between `min_step` and `max_step` to keep each
+    /// capacitor's local truncation error in bounds, and
+    /// lands exactly on every source breakpoint. Results are
+    /// interpolated back onto the uniform `delta_t` grid.
+    /// `.OPTIONS MAXSTEP` lowers `max_step` if it's smaller.
+    pub fn simulate_adaptive(&mut self, delta_t: Scalar, steps: usize, min_step: Scalar, max_step: Scalar) -> Result<SimulationResults, SimulationError>
+    {
+        self.partial = None;
+        check_step(delta_t, "must be positive and finite")?;
+        check_step(min_step, "minimum step must be positive and finite")?;
+        if max_step.is_nan() || (max_step < min_step)
+        {
+            return Err(SimulationError::InvalidTimestep { step: max_step, reason: format!("maximum step is less than the minimum step {}", min_step) });
+        }
+        let max_step = self.options.max_step.map_or(max_step, |limit| max_step.min(limit).max(min_step));
+
+        let start = self.time;
+        let stop = start + (steps.max(1) - 1) as Scalar * delta_t;
+        let mut times = Vec::new();
+        let mut solutions = Vec::new();
+
+        // First solution - either carried on from a previous
+        // call, or the initial point
+
+        let (mut time, solution) = match self.solved
+        {
+            Some(time) => (time, self.recorded()),
+            None =>
             {
-                // +/- I_1 +/- I_2 +/- ... = 0
-                for current in currents.iter()
+                if let Err(error) = self.solve(start, delta_t)
                 {
-                    *solver.coef(eq, current.0) = current.1;
+                    self.partial = self.results(Vec::new(), vec![Vec::new(); self.signals().len()], Some(delta_t)).ok();
+                    return Err(error);
                 }
+                self.accept(start, delta_t);
+                (start, self.recorded())
             },
-            Equation::Voltage { voltage, plus, minus } =>
+        };
+        times.push(time);
+        solutions.push(solution);
+
+        let mut breakpoints = self.breakpoints(time, stop);
+        breakpoints.push(stop);
+
+        let mut step = self.next_step.unwrap_or(delta_t).clamp(min_step, max_step);
+
+        while time < stop
+        {
+            // Don't step past the next breakpoint, or leave
+            // a sliver of less than min_step before it
+
+            let breakpoint = breakpoints[breakpoints.partition_point(|b| *b <= time)];
+            let mut delta = step;
+            if time + delta + 0.5 * min_step >= breakpoint
             {
-                // V+ - V- = voltage
-                *solver.coef(eq, *plus) = 1.0;
-                *solver.coef(eq, *minus) = -1.0;
-                *solver.constant(eq) = voltage.calc(time);
-            },
-            Equation::Conductance { conductance, plus, minus, current } =>
-            {
-                // V = IR => I = V / R
-                // => I - V/R = 0
-                // => I - (V+ - V-) / R = 0
-                // => I + V-/R - V+/R = 0
-                // => I + V-.C - V+.C = 0
-                *solver.coef(eq, *current) = 1.0;
-                *solver.coef(eq, *minus) = *conductance;
-                *solver.coef(eq, *plus) = -conductance;
-            },
-            Equation::Capacitor { plus, minus, voltage, .. } =>
+                delta = breakpoint - time;
+            }
+
+            match self.solve(time + delta, delta)
             {
-                // Same as a voltage source
-                // Stored voltage is updated when the new current is found
+                Ok(()) => (),
+                Err(_) if delta > min_step =>
+                {
+                    self.restore_junctions();
+                    step = (0.25 * delta).max(min_step);
+                    continue;
+                },
+                Err(error) =>
+                {
+                    // Keep the output points already passed
+                    let solved = (0..steps).take_while(|i| start + (*i as Scalar) * delta_t <= time).count();
+                    self.time = start + (solved as Scalar) * delta_t;
+                    self.partial = self.resample(start, delta_t, solved, &times, &solutions).ok();
+                    return Err(error);
+                },
+            };
 
-                *solver.coef(eq, *plus) = 1.0;
-                *solver.coef(eq, *minus) = -1.0;
-                *solver.constant(eq) = *voltage;
-            },
-            Equation::Diode { conductance, offset_voltage, plus_voltage_var, minus_voltage_var, current_var } =>
-            {
-                // Diode is modelled as an offset voltage, then a resistor
-                // V+ - V- - Voff = I.R
-                // I - V+.C + V-.C = Voff.C
-                *solver.coef(eq, *current_var) = 1.0;
-                *solver.coef(eq, *plus_voltage_var) = -conductance;
-                *solver.coef(eq, *minus_voltage_var) = *conductance;
-                *solver.constant(eq) = offset_voltage * conductance;
-            },
-            Equation::Vcvs { gain, plus_voltage_var, minus_voltage_var, control_plus_voltage_var, control_minus_voltage_var, ..} =>
+            // Largest factor the step could be changed by
+            // and keep the error of every capacitor in bounds
+
+            let factor = self.elements.iter()
+                .filter_map(|eq| eq.step_factor(&self.solution, time + delta))
+                .fold(Scalar::INFINITY, Scalar::min);
+
+            if (factor < 1.0) && (delta > min_step)
             {
-                // (V+ - V-) = G * (Vc+ - Vc-)
-                // => V+ - V- - G*Vc+ + G*Vc- = 0
-                *solver.coef(eq, *plus_voltage_var) = 1.0;
-                *solver.coef(eq, *minus_voltage_var) = -1.0;
-                *solver.coef(eq, *control_plus_voltage_var) = -gain;
-                *solver.coef(eq, *control_minus_voltage_var) = *gain;
+                self.restore_junctions();
+                step = (0.9 * factor * delta).clamp(0.25 * delta, delta).max(min_step);
+                continue;
             }
+
+            time = if delta == breakpoint - time { breakpoint } else { time + delta };
+            times.push(time);
+            self.accept(time, delta);
+            solutions.push(self.recorded());
+
+            step = (0.9 * factor * delta).clamp(0.25 * delta, 2.0 * delta).clamp(min_step, max_step);
         }
+
+        self.next_step = Some(step);
+        self.time = stop + delta_t;
+
+        self.resample(start, delta_t, steps, &times, &solutions)
     }
 
-    pub fn update(&mut self, solution: &Vec<Scalar>, delta_t: Scalar)
+    /// Interpolates accepted steps onto the output grid
+    fn resample(&self, start: Scalar, delta_t: Scalar, steps: usize, times: &[Scalar], solutions: &[Vec<Scalar>]) -> Result<SimulationResults, SimulationError>
     {
-        match self
+        let grid = (0..steps).map(|i| start + (i as Scalar) * delta_t).collect::<Vec<_>>();
+        let mut results = vec![Vec::with_capacity(steps); self.signals().len()];
+        if times.len() == 1
         {
-            Equation::Capacitor { capacitance, voltage, current, .. } =>
+            for (var_results, value) in results.iter_mut().zip(solutions[0].iter())
             {
-                // I = C . dV/dt
-                // => dV = I * dt / C
-                *voltage += solution[current.into_index()] * delta_t / *capacitance;
-            },
-            Equation::Diode { conductance, offset_voltage, plus_voltage_var, minus_voltage_var, .. } =>
+                var_results.extend(std::iter::repeat_n(*value, steps));
+            }
+            return self.results(grid, results, Some(delta_t));
+        }
+
+        for time in grid.iter().copied()
+        {
+            let index = times.partition_point(|t| *t < time).clamp(1, times.len() - 1);
+            let (t0, t1) = (times[index - 1], times[index]);
+            let fraction = ((time - t0) / (t1 - t0)).clamp(0.0, 1.0);
+
+            for (var_results, (v0, v1)) in results.iter_mut().zip(solutions[index - 1].iter().zip(solutions[index].iter()))
             {
-                // First, work out the final voltage across the diode
-                let new_voltage = solution[plus_voltage_var.into_index()] - solution[minus_voltage_var.into_index()];
+                var_results.push(v0 + (v1 - v0) * fraction);
+            }
+        }
 
-                // Work out the new voltage offset and conductance
-                if new_voltage <= 0.0
-                {
-                    // Negative biased:
-                    // Just model as a very large resistor
-                    // to simulate some leakage current
+        self.results(grid, results, Some(delta_t))
+    }
+
+    /// Solves a step, leaving the result in `self.solution`.
+    /// If Newton-Raphson doesn't converge gmin stepping and
+    /// then source stepping are tried - it's already a
+    /// transient, so pseudo-transient would be no different
+    /// to a shorter step.
+    fn solve(&mut self, time: Scalar, delta_t: Scalar) -> Result<(), SimulationError>
+    {
+        self.junctions.clear();
+        self.junctions.extend(self.elements.iter().filter_map(|e| match e
+        {
+            Element::Diode { junction_voltage, .. } => Some(*junction_voltage),
+            _ => None,
+        }));
+
+        let error = match self.newton(time, delta_t)
+        {
+            Err(error @ (SimulationError::NoConvergence { .. } | SimulationError::NotFinite { .. })) => error,
+            result => return result,
+        };
+
+        let gmin_steps = ConvergenceMethod::gmin_steps().map(|gmin| (gmin, 1.0));
+        let source_steps = ConvergenceMethod::source_steps().map(|scale| (0.0, scale));
+
+        if self.step_aid(time, delta_t, gmin_steps)
+        {
+            self.record_aid(time, ConvergenceMethod::GminStepping);
+            return Ok(());
+        }
+        if self.step_aid(time, delta_t, source_steps)
+        {
+            self.record_aid(time, ConvergenceMethod::SourceStepping);
+            return Ok(());
+        }
+        Err(error)
+    }
+
+    /// Solves with each (gmin, source scale) in turn, from
+    /// the diode junctions the step started with. Returns
+    /// true if every one converged.
+    fn step_aid(&mut self, time: Scalar, delta_t: Scalar, steps: impl Iterator<Item = (Scalar, Scalar)>) -> bool
+    {
+        self.restore_junctions();
+
+        let mut converged = true;
+        for (gmin, source_scale) in steps
+        {
+            self.set_aid(gmin, source_scale);
+            if self.newton(time, delta_t).is_err()
+            {
+                converged = false;
+                break;
+            }
+        }
+        self.set_aid(0.0, 1.0);
+        converged
+    }
+
+    /// Moves the diodes back to the junction voltages the
+    /// last solve started from, so a retry starts there too
+    fn restore_junctions(&mut self)
+    {
+        let diodes = self.elements.iter_mut().filter_map(|e| match e
+        {
+            Element::Diode { junction_voltage, .. } => Some(junction_voltage),
+            _ => None,
+        });
+        for (junction_voltage, saved) in diodes.zip(self.junctions.iter())
+        {
+            *junction_voltage = *saved;
+        }
+    }
+
+    fn set_aid(&mut self, gmin: Scalar, source_scale: Scalar)
+    {
+        if (gmin, source_scale) != (self.gmin, self.source_scale)
+        {
+            self.gmin = gmin;
+            self.source_scale = source_scale;
+            self.factored = None;
+        }
+    }
+
+    /// Newton-Raphson iteration - fill, solve and re-linearise
+    /// the diodes until the solution stops changing
+    fn newton(&mut self, time: Scalar, delta_t: Scalar) -> Result<(), SimulationError>
+    {
+        let dim = self.layout.dim();
+        let hold = self.solved.is_none();
+
+        for iteration in 0..self.options.itl4
+        {
+            // Only factor again if a coefficient has changed -
+            // otherwise just the constants need rebuilding
+
+            self.coefficients.clear();
+            self.coefficients.extend(self.elements.iter().filter_map(|e| e.varying_coefficient(delta_t)));
 
-                    *conductance = DIODE_MIN_CONDUCTANCE;
-                    *offset_voltage = 0.0;
+            if hold
+            {
+                self.factored = None;
+                if !self.factor_held()
+                {
+                    return Err(self.singular(time, delta_t));
+                }
+            }
+            else if self.factored.as_deref() != Some(&self.coefficients[..])
+            {
+                let mut factored = self.factored.take().unwrap_or_default();
+                if !self.factor(delta_t)
+                {
+                    return Err(self.singular(time, delta_t));
                 }
-                else
+                factored.clear();
+                factored.extend_from_slice(&self.coefficients);
+                self.factored = Some(factored);
+            }
+
+            std::mem::swap(&mut self.solution, &mut self.previous);
+            self.fill_constants(time, delta_t);
+            self.solution.resize(self.constants.len(), 0.0);
+
+            let solved = match &self.sparse
+            {
+                Some(solver) if !hold => solver.solve_factored_into(&mut self.constants, &mut self.solution),
+                _ =>
                 {
-                    // Positive biased:
-                    // Model the Shockley Diode Equation
-                    // piecewise - as a voltage offset + resistor
+                    self.solution.copy_from_slice(&self.constants);
+                    self.dense.as_ref().map(|lu| lu.solve_in_place(&mut self.solution)).unwrap_or(false)
+                },
+            };
+            if !solved
+            {
+                return Err(self.singular(time, delta_t));
+            }
+
+            if self.solution.iter().any(|v| !v.is_finite())
+            {
+                let names = self.names.iter().zip(self.solution.iter()).take(dim)
+                    .filter(|(_, v)| !v.is_finite())
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                return Err(SimulationError::NotFinite { time: Some(time), names });
+            }
 
-                    // First: Solve the Schokley Diode Equation
-                    // to find the current operating point
-                    // Id = Is * (exp(Vd/n.Vt) - 1)
+            if self.linear
+            {
+                return Ok(());
+            }
 
-                    let id = DIODE_IS * ((new_voltage * DIODE_1_ON_N_VT).exp() - 1.0);
+            let mut converged = (iteration > 0) && self.solution.iter().zip(self.previous.iter()).zip(self.tolerances.iter())
+                .all(|((new, old), tol)| !exceeds_tolerance(*new, *old, self.options.reltol, *tol));
+
+            for element in self.elements.iter_mut()
+            {
+                converged &= element.linearise(&self.solution, &self.options);
+            }
 
-                    // Now - dI/dV will be the resistance
-                    // as the current operating point
-                    // dI/dV = Is * exp(Vd/n.Vt) / n.Vt
+            if converged
+            {
+                return Ok(());
+            }
+        }
 
-                    let didv = (DIODE_IS * DIODE_1_ON_N_VT) * (new_voltage * DIODE_1_ON_N_VT).exp();
+        let residuals = self.names.iter().zip(self.solution.iter().zip(self.previous.iter())).zip(self.tolerances.iter())
+            .filter(|((_, (new, old)), tol)| exceeds_tolerance(**new, **old, self.options.reltol, **tol))
+            .map(|((name, (new, old)), _)| (name.clone(), new - old))
+            .collect();
 
-                    // Now - solve for the new offset voltage
-                    // and new conductanc
-                    //
-                    // C = dI/dV;
-                    //
-                    // dI/dV = I/V => Vr = I / (dI/dV)
-                    // Voff = Vd - Vr
-                    //      = Vd - (I / dI/dV)
+        Err(SimulationError::NoConvergence { time, iterations: self.options.itl4, residuals })
+    }
 
-                    *conductance = didv;
-                    *offset_voltage = new_voltage - (id / didv);
+    /// Returns false if the system is singular
+    fn factor(&mut self, delta_t: Scalar) -> bool
+    {
+        self.factorisations += 1;
+        match &mut self.sparse
+        {
+            Some(solver) =>
+            {
+                solver.clear();
+                for element in self.elements.iter()
+                {
+                    element.stamp(solver, delta_t);
                 }
+                stamp_gmin(solver, &self.names, self.gmin);
+                solver.factor()
             },
-            _ => (),
+            None =>
+            {
+                let mut solver = self.layout.system().new_solver();
+                for element in self.elements.iter()
+                {
+                    element.stamp(&mut solver, delta_t);
+                }
+                stamp_gmin(&mut solver, &self.names, self.gmin);
+                self.dense = solver.factor();
+                self.dense.is_some()
+            },
+        }
+    }
+
+    /// Factors the first step as the operating point would
+    /// stamp it, with each capacitor a voltage source at its
+    /// initial voltage - its current an extra unknown after
+    /// the others, found exactly rather than from a step.
+    /// Returns false if the system is singular.
+    fn factor_held(&mut self) -> bool
+    {
+        let dim = self.layout.dim();
+        let capacitors = self.elements.iter().filter(|e| matches!(e, Element::Capacitor { .. })).count();
+
+        let mut solver = Solver::new(dim + capacitors);
+        let mut branches = (dim..).map(VariableIndex::from_index);
+        for element in self.elements.iter()
+        {
+            match element
+            {
+                Element::Capacitor { plus, minus, .. } =>
+                {
+                    let branch = branches.next().unwrap();
+                    mna::stamp_branch_current(&mut solver, *plus, *minus, branch);
+                    mna::stamp_branch_voltage(&mut solver, branch, *plus, *minus, 1.0);
+                },
+                _ => element.stamp(&mut solver, 0.0),
+            }
         }
+        stamp_gmin(&mut solver, &self.names, self.gmin);
+        self.dense = solver.factor();
+        self.dense.is_some()
     }
-}
\ No newline at end of file
+
+    fn fill_constants(&mut self, time: Scalar, delta_t: Scalar)
+    {
+        let hold = self.solved.is_none();
+        self.constants.clear();
+        self.constants.resize(self.layout.dim(), 0.0);
+        for element in self.elements.iter()
+        {
+            match element
+            {
+                Element::Voltage { branch, voltage, .. } => self.constants[branch.into_index()] += voltage.calc(time) * self.source_scale,
+                Element::Capacitor { history, .. } if hold => self.constants.push(history.voltage() * self.source_scale),
+                _ => element.stamp_constants(&mut self.constants, time, delta_t),
+            }
+        }
+    }
+
+    /// Moves the simulation on to the solved step, returning
+    /// its unknowns and device currents
+    fn accept(&mut self, time: Scalar, delta_t: Scalar) -> &[Scalar]
+    {
+        let dim = self.layout.dim();
+        self.values.clear();
+        self.values.extend_from_slice(&self.solution[..dim]);
+
+        if self.solved.is_none()
+        {
+            // The held capacitors' currents were unknowns
+            let mut held = self.solution[dim..].iter().copied();
+            self.values.extend(self.elements.iter().filter_map(|e| match e
+            {
+                Element::Capacitor { .. } => held.next(),
+                _ => e.current(&self.solution, delta_t),
+            }));
+            self.solution.truncate(dim);
+            self.dense = None;
+        }
+        else
+        {
+            self.values.extend(self.elements.iter().filter_map(|e| e.current(&self.solution, delta_t)));
+        }
+
+        let mut currents = self.values[dim..].iter();
+        for element in self.elements.iter_mut()
+        {
+            let current = if element.name().is_some() { currents.next().copied() } else { None };
+            element.update(&self.solution, time, current.unwrap_or_default());
+        }
+
+        self.solved = Some(time);
+        &self.values
+    }
+
+    /// Works out which nodes and devices make the system singular
+    fn singular(&self, time: Scalar, delta_t: Scalar) -> SimulationError
+    {
+        let mut solver = self.layout.system().new_solver();
+        for element in self.elements.iter()
+        {
+            element.stamp(&mut solver, delta_t);
+        }
+        let vars = solver.singular_variables();
+
+        let mut nodes = vars.iter()
+            .filter_map(|v| self.names[v.into_index()].strip_prefix("V_"))
+            .map(|node| node.to_owned())
+            .collect::<Vec<_>>();
+        nodes.sort();
+
+        let mut devices = self.terminals.iter()
+            .filter(|(_, terminals)| terminals.iter().any(|t| vars.contains(t)))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        devices.sort();
+
+        SimulationError::Singular { time, nodes, devices }
+    }
+
+    fn breakpoints(&self, start: Scalar, stop: Scalar) -> Vec<Scalar>
+    {
+        let mut result = self.elements.iter()
+            .flat_map(|e| match e
+            {
+                Element::Voltage { voltage, .. } => voltage.breakpoints(start, stop),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        result.sort_by(|a, b| a.total_cmp(b));
+        result.dedup();
+        result
+    }
+
+    fn results(&self, times: Vec<Scalar>, values: Vec<Vec<Scalar>>, step: Option<Scalar>) -> Result<SimulationResults, SimulationError>
+    {
+        let signals = self.signals().into_iter().zip(values)
+            .map(|((name, unit), values)| Signal { name, unit, kind: SignalKind::of(unit), values })
+            .collect();
+        let metadata = Metadata { method: Some(self.options.method), step, options: self.options.clone() };
+        SimulationResults::new(Axis::Time, times, signals, metadata)
+    }
+}
+
+/// Adds `gmin` from every node to ground
+fn stamp_gmin<M: Matrix>(solver: &mut M, names: &[String], gmin: Scalar)
+{
+    if gmin == 0.0
+    {
+        return;
+    }
+    for (i, name) in names.iter().enumerate().take(solver.dim())
+    {
+        if name.starts_with("V_")
+        {
+            *solver.coef(EquationIndex::from_index(i), VariableIndex::from_index(i)) += gmin;
+        }
+    }
+}
+
+/// Whether a Newton-Raphson iteration moved an
+/// unknown by more than `reltol` of its value plus `tolerance`
+fn exceeds_tolerance(new: Scalar, old: Scalar, reltol: Scalar, tolerance: Scalar) -> bool
+{
+    (new - old).abs() > reltol * new.abs().max(old.abs()) + tolerance
+}
+
+/// Where a device's voltage and current are in the values
+#[derive(Clone, Copy)]
+struct Port
+{
+    plus: Option<VariableIndex>,
+    minus: Option<VariableIndex>,
+    current: usize,
+    /// -1 if the current is out of the plus terminal
+    sign: Scalar,
+}
+
+/// A probe's V, I or P term
+enum Term
+{
+    Voltage(Option<VariableIndex>, Option<VariableIndex>),
+    Current(usize),
+    Power(Port),
+}
+
+impl Term
+{
+    fn value(&self, values: &[Scalar]) -> Scalar
+    {
+        match self
+        {
+            Term::Voltage(plus, minus) => voltage(values, *plus) - voltage(values, *minus),
+            Term::Current(current) => values[*current],
+            Term::Power(port) => port.sign * (voltage(values, port.plus) - voltage(values, port.minus)) * values[port.current],
+        }
+    }
+}
+
+fn check_step(step: Scalar, reason: &str) -> Result<(), SimulationError>
+{
+    if step.is_finite() && (step > 0.0)
+    {
+        Ok(())
+    }
+    else
+    {
+        Err(SimulationError::InvalidTimestep { step, reason: reason.to_owned() })
+    }
+}
+
+fn voltage(solution: &[Scalar], var: Option<VariableIndex>) -> Scalar
+{
+    var.map(|v| solution[v.into_index()]).unwrap_or(0.0)
+}
+
+/// A device's contribution to the MNA system. Currents
+/// flow from plus, through the device, to minus.
+pub enum Element
+{
+    Voltage{branch: VariableIndex, plus: Option<VariableIndex>, minus: Option<VariableIndex>, voltage: Exp},
+    Conductance{name: String, plus: Option<VariableIndex>, minus: Option<VariableIndex>, conductance: Scalar},
+    Capacitor{name: String, plus: Option<VariableIndex>, minus: Option<VariableIndex>, capacitance: Scalar, method: IntegrationMethod, history: CapacitorHistory},
+    Diode
+    {
+        name: String,
+        plus: Option<VariableIndex>,
+        minus: Option<VariableIndex>,
+        model: DiodeModel,
+        vt: Scalar,
+        gmin: Scalar,
+        /// Voltage the diode is linearised around
+        junction_voltage: Scalar,
+    },
+    Vcvs
+    {
+        branch: VariableIndex,
+        plus: Option<VariableIndex>,
+        minus: Option<VariableIndex>,
+        control_plus: Option<VariableIndex>,
+        control_minus: Option<VariableIndex>,
+        gain: Scalar,
+    },
+}
+
+impl Element
+{
+    /// Name of a device whose current isn't an unknown
+    pub fn name(&self) -> Option<&str>
+    {
+        match self
+        {
+            Element::Conductance { name, .. } | Element::Capacitor { name, .. } | Element::Diode { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// False if a device value is NaN or infinite
+    pub fn is_finite(&self) -> bool
+    {
+        match self
+        {
+            Element::Conductance { conductance, .. } => conductance.is_finite(),
+            Element::Capacitor { capacitance, .. } => capacitance.is_finite(),
+            Element::Vcvs { gain, .. } => gain.is_finite(),
+            _ => true,
+        }
+    }
+
+    pub fn stamp<M: Matrix>(&self, solver: &mut M, delta_t: Scalar)
+    {
+        match self
+        {
+            Element::Voltage { branch, plus, minus, .. } =>
+            {
+                // Branch current leaves the plus terminal,
+                // and V+ - V- = voltage
+                mna::stamp_branch_current(solver, *minus, *plus, *branch);
+                mna::stamp_branch_voltage(solver, *branch, *plus, *minus, 1.0);
+            },
+            Element::Conductance { plus, minus, conductance, .. } =>
+            {
+                mna::stamp_admittance(solver, *plus, *minus, *conductance);
+            },
+            Element::Capacitor { plus, minus, capacitance, method, history, .. } =>
+            {
+                // Companion model - a conductance in parallel
+                // with a current from the history:
+                // I = G.(V+ - V-) + Ih
+                let (conductance, _) = history.companion(*capacitance, *method, delta_t);
+                mna::stamp_admittance(solver, *plus, *minus, conductance);
+            },
+            Element::Diode { plus, minus, model, vt, gmin, junction_voltage, .. } =>
+            {
+                // Tangent to the Shockley equation at the junction voltage:
+                // I = Id + Gd.(V+ - V- - Vd)
+                let (_, gd) = diode::evaluate(model, *vt, *gmin, *junction_voltage);
+                mna::stamp_admittance(solver, *plus, *minus, gd);
+            },
+            Element::Vcvs { branch, plus, minus, control_plus, control_minus, gain } =>
+            {
+                // (V+ - V-) = G * (Vc+ - Vc-)
+                mna::stamp_branch_current(solver, *plus, *minus, *branch);
+                mna::stamp_branch_voltage(solver, *branch, *plus, *minus, 1.0);
+                mna::stamp_branch_voltage(solver, *branch, *control_plus, *control_minus, -gain);
+            },
+        }
+    }
+
+    /// Adds to the right hand side of the system
+    pub fn stamp_constants(&self, constants: &mut [Scalar], time: Scalar, delta_t: Scalar)
+    {
+        match self
+        {
+            Element::Voltage { branch, voltage, .. } =>
+            {
+                constants[branch.into_index()] += voltage.calc(time);
+            },
+            Element::Capacitor { plus, minus, capacitance, method, history, .. } =>
+            {
+                let (_, history_current) = history.companion(*capacitance, *method, delta_t);
+                mna::add_current(constants, *plus, *minus, history_current);
+            },
+            Element::Diode { plus, minus, model, vt, gmin, junction_voltage, .. } =>
+            {
+                let (id, gd) = diode::evaluate(model, *vt, *gmin, *junction_voltage);
+                mna::add_current(constants, *plus, *minus, id - gd * junction_voltage);
+            },
+            _ => (),
+        }
+    }
+
+    /// The coefficient that can change between solves, if any -
+    /// while these stay the same the factorisation can be reused
+    pub fn varying_coefficient(&self, delta_t: Scalar) -> Option<Scalar>
+    {
+        match self
+        {
+            Element::Capacitor { capacitance, method, history, .. } => Some(history.companion(*capacitance, *method, delta_t).0),
+            Element::Diode { model, vt, gmin, junction_voltage, .. } => Some(diode::evaluate(model, *vt, *gmin, *junction_voltage).1),
+            _ => None,
+        }
+    }
+
+    /// Current through a device that isn't an unknown,
+    /// from plus to minus, at a solution
+    pub fn current(&self, solution: &[Scalar], delta_t: Scalar) -> Option<Scalar>
+    {
+        match self
+        {
+            Element::Conductance { plus, minus, conductance, .. } =>
+            {
+                Some(conductance * (voltage(solution, *plus) - voltage(solution, *minus)))
+            },
+            Element::Capacitor { plus, minus, capacitance, method, history, .. } =>
+            {
+                let (conductance, history_current) = history.companion(*capacitance, *method, delta_t);
+                Some(conductance * (voltage(solution, *plus) - voltage(solution, *minus)) + history_current)
+            },
+            Element::Diode { plus, minus, model, vt, gmin, junction_voltage, .. } =>
+            {
+                let (id, gd) = diode::evaluate(model, *vt, *gmin, *junction_voltage);
+                Some(id + gd * (voltage(solution, *plus) - voltage(solution, *minus) - junction_voltage))
+            },
+            _ => None,
+        }
+    }
+
+    /// How much the step that gave `solution` could be
+    /// scaled by and keep the local truncation error within
+    /// tolerance - less than one means it should be retried
+    pub fn step_factor(&self, solution: &[Scalar], time: Scalar) -> Option<Scalar>
+    {
+        match self
+        {
+            Element::Capacitor { plus, minus, method, history, .. } =>
+            {
+                let voltage = voltage(solution, *plus) - voltage(solution, *minus);
+                let (error, order) = history.truncation_error(history.method(*method), time, voltage)?;
+                let tolerance = LTE_RELTOL * voltage.abs().max(history.voltage().abs()) + LTE_ABSTOL;
+
+                Some((tolerance / error).powf(1.0 / (order as Scalar + 1.0)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Moves nonlinear devices to the operating point in a
+    /// Newton-Raphson iteration's solution, returning true
+    /// if they were already there
+    pub fn linearise(&mut self, solution: &[Scalar], options: &SimulationOptions) -> bool
+    {
+        match self
+        {
+            Element::Diode { plus, minus, model, vt, gmin, junction_voltage, .. } =>
+            {
+                let new_voltage = voltage(solution, *plus) - voltage(solution, *minus);
+
+                // The solved current is from the old tangent - it
+                // has converged once it matches the real diode
+                let (id, _) = diode::evaluate(model, *vt, *gmin, new_voltage);
+                let (old_id, old_gd) = diode::evaluate(model, *vt, *gmin, *junction_voltage);
+                let current = old_id + old_gd * (new_voltage - *junction_voltage);
+                let converged = !exceeds_tolerance(id, current, options.reltol, options.abstol);
+
+                // Limit the step up the exponential
+                // so the next iteration can't overflow
+                let limited = diode::limit(new_voltage, *junction_voltage, model, *vt);
+                let moved = exceeds_tolerance(limited, *junction_voltage, options.reltol, options.vntol);
+                *junction_voltage = limited;
+
+                converged && !moved && (limited == new_voltage)
+            },
+            _ => true,
+        }
+    }
+
+    /// Records an accepted step, with the device's current
+    pub fn update(&mut self, solution: &[Scalar], time: Scalar, current: Scalar)
+    {
+        if let Element::Capacitor { plus, minus, history, .. } = self
+        {
+            let voltage = voltage(solution, *plus) - voltage(solution, *minus);
+            history.push(time, voltage, current);
+        }
+    }
+}
+
+/// Capacitor state from the previous timesteps
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CapacitorHistory
+{
+    /// Number of timesteps solved so far
+    steps: usize,
+    /// t(n), t(n-1) and t(n-2)
+    times: [Scalar; 3],
+    /// V(n), V(n-1) and V(n-2)
+    voltages: [Scalar; 3],
+    /// I(n)
+    current: Scalar,
+    /// I(n) - I(n-1)
+    current_change: Scalar,
+    /// Number of consecutive steps where the
+    /// current change has flipped direction
+    alternations: usize,
+}
+
+impl CapacitorHistory
+{
+    fn new(voltage: Scalar) -> Self
+    {
+        CapacitorHistory { voltages: [voltage; 3], ..Default::default() }
+    }
+
+    fn voltage(&self) -> Scalar
+    {
+        self.voltages[0]
+    }
+
+    /// Conductance and history current of the companion model
+    /// for the next step
+    fn companion(&self, capacitance: Scalar, method: IntegrationMethod, delta_t: Scalar) -> (Scalar, Scalar)
+    {
+        let c_on_h = capacitance / delta_t;
+
+        match self.method(method)
+        {
+            IntegrationMethod::BackwardEuler =>
+            {
+                // I = C/h . (V - Vn)
+                (c_on_h, -c_on_h * self.voltage())
+            },
+            IntegrationMethod::Trapezoidal =>
+            {
+                // I = 2C/h . (V - Vn) - In
+                (2.0 * c_on_h, -2.0 * c_on_h * self.voltage() - self.current)
+            },
+            IntegrationMethod::Gear =>
+            {
+                // Variable step, with w = h / h(n-1):
+                // I = C/h . ((1+2w)/(1+w).V - (1+w).Vn + w^2/(1+w).Vn-1)
+                // which is 3/2, -2, 1/2 for fixed steps
+                let w = delta_t / self.step();
+                let (v, vn, vn1) = ((1.0 + 2.0 * w) / (1.0 + w), 1.0 + w, w * w / (1.0 + w));
+                (v * c_on_h, c_on_h * (vn1 * self.previous_voltage() - vn * self.voltage()))
+            },
+        }
+    }
+
+    fn previous_voltage(&self) -> Scalar
+    {
+        self.voltages[1]
+    }
+
+    /// h(n-1)
+    fn step(&self) -> Scalar
+    {
+        self.times[0] - self.times[1]
+    }
+
+    fn push(&mut self, time: Scalar, voltage: Scalar, current: Scalar)
+    {
+        let change = current - self.current;
+        if (self.steps >= 2) && (change * self.current_change < 0.0)
+        {
+            self.alternations += 1;
+        }
+        else
+        {
+            self.alternations = 0;
+        }
+
+        self.steps += 1;
+        self.times = [time, self.times[0], self.times[1]];
+        self.voltages = [voltage, self.voltages[0], self.voltages[1]];
+        self.current = current;
+        self.current_change = change;
+    }
+
+    /// Method to use for the next step. The first step
+    /// has no V(n-1) for Gear, and the trapezoidal rule
+    /// is damped with backward Euler when the current
+    /// zig-zags every step - a trapezoidal artifact, as
+    /// real signals at the step frequency can't be resolved
+    fn method(&self, method: IntegrationMethod) -> IntegrationMethod
+    {
+        match method
+        {
+            _ if self.steps < 2 => IntegrationMethod::BackwardEuler,
+            IntegrationMethod::Trapezoidal if self.alternations >= 2 => IntegrationMethod::BackwardEuler,
+            _ => method,
+        }
+    }
+
+    /// Estimated local truncation error, and the method's
+    /// order, for a step that gives `voltage` at `time`:
+    /// BE: h^2/2.V'', trapezoidal: h^3/12.V''', BDF2: 2/9.h^3.V'''
+    /// with the derivatives from divided differences
+    fn truncation_error(&self, method: IntegrationMethod, time: Scalar, voltage: Scalar) -> Option<(Scalar, usize)>
+    {
+        let order = match method
+        {
+            IntegrationMethod::BackwardEuler => 1,
+            _ => 2,
+        };
+        if self.steps < order + 1
+        {
+            return None;
+        }
+
+        let mut points = vec![(time, voltage)];
+        points.extend(self.times.iter().copied().zip(self.voltages.iter().copied()).take(order + 1));
+
+        // n! times the nth divided difference
+        // approximates the nth derivative
+        let derivative = divided_difference(&points) * if order == 1 { 2.0 } else { 6.0 };
+        let h = time - self.times[0];
+
+        let error = match method
+        {
+            IntegrationMethod::BackwardEuler => h * h / 2.0 * derivative,
+            IntegrationMethod::Trapezoidal => h * h * h / 12.0 * derivative,
+            IntegrationMethod::Gear => 2.0 / 9.0 * h * h * h * derivative,
+        };
+
+        Some((error.abs(), order))
+    }
+}
+
+fn divided_difference(points: &[(Scalar, Scalar)]) -> Scalar
+{
+    let mut values = points.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+
+    for level in 1..points.len()
+    {
+        for i in 0..(points.len() - level)
+        {
+            values[i] = (values[i] - values[i + 1]) / (points[i].0 - points[i + level].0);
+        }
+    }
+
+    values[0]
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::sim::analysis::{self, AnalysisResults};
+
+    #[test]
+    fn rc_charge_accuracy()
+    {
+        // 1ms time constant, sampled at 1 tau
+        let error = |method: &str|
+        {
+            let netlist = format!("V1 1 0 1\nR1 1 2 1k\nC1 2 0 1u\n.TRAN 10u 1m UIC\n.OPTIONS METHOD={}", method).parse::<Netlist>().unwrap();
+            let results = TransientSimulation::new(&netlist).unwrap().simulate(1e-5, 101).unwrap();
+            (results["V_2"][100] - (1.0 - (-1.0 as Scalar).exp())).abs()
+        };
+
+        let euler = error("EULER");
+        let trap = error("TRAP");
+        let gear = error("GEAR");
+
+        assert!(euler < 5e-3);
+        assert!(trap < euler / 10.0);
+        assert!(gear < euler / 10.0);
+    }
+
+    #[test]
+    fn adaptive_matches_fixed()
+    {
+        let netlist = "V1 1 0 PULSE(0 1 1m 1u 1u 2m)\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+
+        let fixed = TransientSimulation::new(&netlist).unwrap().simulate(1e-6, 5001).unwrap();
+        let adaptive = TransientSimulation::new(&netlist).unwrap().simulate_adaptive(1e-5, 501, 1e-9, 1e-5).unwrap();
+
+        for (i, value) in adaptive["V_2"].iter().enumerate()
+        {
+            assert!((value - fixed["V_2"][i * 10]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn factorisation_is_reused()
+    {
+        let netlist = "V1 1 0 PULSE(0 1 1m 1u 1u 2m)\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+
+        // A linear circuit at a fixed step factors for the
+        // backward Euler start, then once for trapezoidal -
+        // even carrying on at the same step
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.simulate(1e-5, 100).unwrap();
+        simulation.simulate(1e-5, 100).unwrap();
+        assert_eq!(simulation.factorisations, 2);
+        simulation.simulate(2e-5, 10).unwrap();
+        assert_eq!(simulation.factorisations, 3);
+
+        // Adaptive steps factor again each time the step
+        // changes, but not while it's held at the maximum
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        simulation.simulate_adaptive(1e-5, 501, 1e-9, 1e-5).unwrap();
+        assert!((3..50).contains(&simulation.factorisations));
+    }
+
+    #[test]
+    fn sparse_matches_dense()
+    {
+        // Gear, as trapezoidal damping can be set off by a
+        // zig-zag in the rounding, which differs between solvers
+        let circuit = "V1 1 0 PULSE(-5 5 0 10u 10u 40u 100u)\nR1 1 2 1k\nC1 2 0 10n\nD1 2 3\nD2 3 0\nD3 0 2\nR2 3 0 10k";
+        let run = |solver: &str|
+        {
+            let netlist = format!("{}\n.OPTIONS METHOD=GEAR SOLVER={}", circuit, solver).parse::<Netlist>().unwrap();
+            let mut simulation = TransientSimulation::new(&netlist).unwrap();
+            let results = simulation.simulate(1e-6, 300).unwrap();
+            (simulation, results)
+        };
+        let (dense, expected) = run("DENSE");
+        let (sparse, actual) = run("SPARSE");
+
+        assert!(dense.sparse.is_none());
+        for name in ["V_2", "V_3", "I_D1", "I_D3"]
+        {
+            for (a, e) in actual[name].iter().zip(expected[name].iter())
+            {
+                assert!((a - e).abs() < 1e-9);
+            }
+        }
+
+        // Every Newton iteration refactors, but the
+        // pivots are only picked the first time
+        assert!(sparse.factorisations > 300);
+        assert_eq!(sparse.sparse.as_ref().unwrap().pivotings(), 1);
+    }
+
+    #[test]
+    fn oversampled_matches_fixed()
+    {
+        let netlist = "V1 1 0 sin(6283*t)\nR1 1 2 1k\nC1 2 0 1u".parse::<Netlist>().unwrap();
+
+        let fixed = TransientSimulation::new(&netlist).unwrap().simulate(1.0 / 48000.0, 960).unwrap();
+        let oversampled = TransientSimulation::new(&netlist).unwrap().simulate_oversampled(1.0 / 48000.0, 960, 4).unwrap();
+
+        assert_eq!(oversampled["V_2"].len(), 960);
+        for (value, expected) in oversampled["V_2"].iter().zip(fixed["V_2"].iter()).skip(50)
+        {
+            assert!((value - expected).abs() < 1e-3);
+        }
+
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+        assert!(matches!(simulation.simulate_oversampled(1.0 / 48000.0, usize::MAX, 4), Err(SimulationError::InvalidTimestep { .. })));
+        assert!(matches!(simulation.simulate_oversampled(1.0 / 48000.0, 960, 0), Err(SimulationError::InvalidTimestep { .. })));
+    }
+
+    #[test]
+    fn convergence_aids_match_newton()
+    {
+        let netlist = "V1 1 0 PULSE(0 5 0 10u 10u 1m)\nR1 1 2 1k\nC1 2 0 10n\nD1 2 0\nE1 3 0 2 0 1e6\nD2 3 4\nR2 4 0 1k".parse::<Netlist>().unwrap();
+        let expected = TransientSimulation::new(&netlist).unwrap().simulate(1e-6, 20).unwrap();
+
+        for aid in [ConvergenceMethod::GminStepping, ConvergenceMethod::SourceStepping]
+        {
+            let mut simulation = TransientSimulation::new(&netlist).unwrap();
+            let node = simulation.names.iter().position(|n| n == "V_2").unwrap();
+            for (step, expected) in expected["V_2"].iter().enumerate()
+            {
+                let time = step as Scalar * 1e-6;
+                simulation.junctions.clear();
+                simulation.junctions.extend(simulation.elements.iter().filter_map(|e| match e
+                {
+                    Element::Diode { junction_voltage, .. } => Some(*junction_voltage),
+                    _ => None,
+                }));
+
+                let converged = match aid
+                {
+                    ConvergenceMethod::GminStepping => simulation.step_aid(time, 1e-6, ConvergenceMethod::gmin_steps().map(|g| (g, 1.0))),
+                    _ => simulation.step_aid(time, 1e-6, ConvergenceMethod::source_steps().map(|s| (0.0, s))),
+                };
+                assert!(converged);
+
+                let values = simulation.accept(time, 1e-6);
+                assert!((values[node] - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn aids_take_over_from_newton()
+    {
+        // The step up to 20V needs more iterations than ITL4
+        let simulate = |itl4: usize|
+        {
+            let netlist = format!("V1 1 0 PULSE(0 20 10u 1n 1n 1m)\nR1 1 2 10\nD1 2 3\nD2 3 4\nD3 4 0\nC1 2 0 1n\n.OPTIONS ITL4={}", itl4)
+                .parse::<Netlist>().unwrap();
+            let mut simulation = TransientSimulation::new(&netlist).unwrap();
+            let results = simulation.simulate(1e-6, 40).unwrap();
+            (results["V_2"][39], simulation.convergence_aids().to_vec())
+        };
+
+        let (expected, aids) = simulate(100);
+        assert!(aids.is_empty());
+        let (voltage, aids) = simulate(7);
+        assert!((voltage - expected).abs() < 1e-5);
+        assert_eq!(aids.len(), 1);
+        assert!(((aids[0].0 - 11e-6).abs() < 1e-12) && (aids[0].1 == ConvergenceMethod::SourceStepping));
+    }
+
+    #[test]
+    fn pulse_breakpoints()
+    {
+        // Only the corners between start and stop
+        let netlist = "V1 1 0 PULSE(0 1 1u 1u 1u 2u 10u)\nR1 1 0 1k".parse::<Netlist>().unwrap();
+        let breakpoints = TransientSimulation::new(&netlist).unwrap().breakpoints(20e-6, 32e-6);
+        let expected = [21e-6, 22e-6, 24e-6, 25e-6, 31e-6, 32e-6];
+        assert_eq!(breakpoints.len(), expected.len());
+        assert!(breakpoints.iter().zip(expected.iter()).all(|(b, e)| (b - e).abs() < 1e-15));
+
+        // A tiny period is capped
+        let netlist = "V1 1 0 PULSE(0 1 0 1p 1p 1p 4p)\nR1 1 0 1k".parse::<Netlist>().unwrap();
+        let breakpoints = TransientSimulation::new(&netlist).unwrap().breakpoints(0.0, 1.0);
+        assert_eq!(breakpoints.len(), 4 * 10000 - 1);
+    }
+
+    #[test]
+    fn diode_clipper()
+    {
+        // Each edge swings the junctions across 10V in one
+        // step, which only converges with the steps limited
+        let netlist = "V1 1 0 PULSE(-5 5 0 1u 1u 49u 100u)\nR1 1 2 1k\nD1 2 0\nD2 0 2".parse::<Netlist>().unwrap();
+        let fixed = TransientSimulation::new(&netlist).unwrap().simulate(1e-5, 101).unwrap();
+        let adaptive = TransientSimulation::new(&netlist).unwrap().simulate_adaptive(1e-5, 101, 1e-9, 1e-5).unwrap();
+
+        // Every sample is on a flat, where the output is
+        // the DC solution of 5V = V + 1k.(Id(V) - Id(-V))
+        let simulation = TransientSimulation::new(&netlist).unwrap();
+        let Some(Element::Diode { model, vt, gmin, .. }) = simulation.elements.iter().find(|e| matches!(e, Element::Diode { .. })) else { panic!() };
+        let current = |v: Scalar| diode::evaluate(model, *vt, *gmin, v).0;
+        let (mut low, mut high) = (0.0, 5.0);
+        for _ in 0..100
+        {
+            let v = 0.5 * (low + high);
+            if v + 1e3 * (current(v) - current(-v)) > 5.0 { high = v; } else { low = v; }
+        }
+        assert!((0.8..0.9).contains(&low));
+
+        for results in [&fixed, &adaptive]
+        {
+            for (input, output) in results["V_1"].iter().zip(results["V_2"].iter())
+            {
+                assert!((output - low * input.signum()).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn floating_node_is_reported()
+    {
+        let netlist = "V1 1 0 1\nR1 1 0 1k\nR2 2 3 1k\n.TRAN 10u 1m UIC".parse::<Netlist>().unwrap();
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+
+        let Err(SimulationError::Singular { nodes, devices, .. }) = simulation.simulate(1e-5, 10) else { panic!() };
+        assert_eq!(nodes, vec!["2", "3"]);
+        assert_eq!(devices, vec!["R2"]);
+        assert!(simulation.partial_results().unwrap()["V_1"].is_empty());
+
+        // Found before the operating point without UIC
+        let netlist = "V1 1 0 1\nR1 1 0 1k\nR2 2 3 1k".parse::<Netlist>().unwrap();
+        let Err(SimulationError::Singular { nodes, .. }) = TransientSimulation::new(&netlist) else { panic!() };
+        assert_eq!(nodes, vec!["2", "3"]);
+    }
+
+    #[test]
+    fn initial_conditions()
+    {
+        let circuit = "V1 1 0 1\nR1 1 2 1k\nC1 2 0 1u IC=0.25\nR2 2 3 1k\nC2 3 0 1u";
+        let start = |commands: &str|
+        {
+            let netlist = format!("{}\n{}", circuit, commands).parse::<Netlist>().unwrap();
+            let results = TransientSimulation::new(&netlist).unwrap().simulate(1e-5, 2).unwrap();
+            (results["V_2"][0], results["V_3"][0])
+        };
+
+        // Operating point, ignoring IC=
+        assert_eq!(start(".TRAN 10u 1m"), (1.0, 1.0));
+        // Operating point with node 3 held
+        let (v2, v3) = start(".IC V(3)=0.5\n.TRAN 10u 1m");
+        assert!(((v2 - 0.75).abs() < 1e-9) && ((v3 - 0.5).abs() < 1e-9));
+        // IC= then .IC, then zero - held exactly
+        let (v2, v3) = start(".IC V(3)=0.5\n.TRAN 10u 1m UIC");
+        assert!(((v2 - 0.25).abs() < 1e-12) && ((v3 - 0.5).abs() < 1e-12));
+        let (_, v3) = start(".TRAN 10u 1m UIC");
+        assert!(v3.abs() < 1e-12);
+
+        // Each .TRAN has its own UIC
+        let netlist = format!("{}\n.TRAN 10u 20u UIC\n.TRAN 10u 20u", circuit).parse::<Netlist>().unwrap();
+        let starts = netlist.analyses().iter()
+            .map(|analysis| match analysis::run(&netlist, analysis).unwrap()
+            {
+                AnalysisResults::Transient(results) => results["V_3"][0],
+                _ => panic!("transient"),
+            })
+            .collect::<Vec<_>>();
+        assert!((starts[0].abs() < 1e-12) && ((starts[1] - 1.0).abs() < 1e-9));
+
+        // A .NODESET near the operating point lets plain Newton
+        // find it within ITL1, where otherwise an aid is needed
+        let diode = "V1 1 0 5\nR1 1 2 1k\nD1 2 0\nC1 2 0 1n\n.OPTIONS ITL1=4";
+        let aids = |commands: &str|
+        {
+            let netlist = format!("{}\n{}", diode, commands).parse::<Netlist>().unwrap();
+            let mut simulation = TransientSimulation::new(&netlist).unwrap();
+            let results = simulation.simulate(1e-6, 2).unwrap();
+            assert!((results["V_2"][0] - 0.85914).abs() < 1e-4);
+            simulation.convergence_aids().to_vec()
+        };
+        assert!(!aids("").is_empty());
+        assert!(aids(".NODESET V(2)=0.8").is_empty());
+    }
+
+    #[test]
+    fn system_size_and_currents()
+    {
+        let netlist = "V1 1 0 1\nR1 1 2 1k\nC1 2 0 1u IC=0.25\nD1 2 0\nE1 3 0 2 0 2\nR2 3 0 1k\n.TRAN 10u 1m UIC".parse::<Netlist>().unwrap();
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+
+        // Only node voltages and voltage-defined branches
+        // are unknowns - the other currents are worked out
+        assert_eq!(simulation.layout.dim(), 5);
+        assert_eq!(simulation.names, ["V_1", "V_2", "V_3", "I_V1", "I_E1", "I_R1", "I_C1", "I_D1", "I_R2"]);
+
+        // The first step holds C1 at its initial voltage,
+        // and its current comes from the held solve
+        let results = simulation.simulate(1e-5, 2).unwrap();
+        let at_start = |name: &str| results[name][0];
+        assert!((at_start("V_2") - 0.25).abs() < 1e-12);
+        assert!((at_start("I_R1") - 0.75e-3).abs() < 1e-15);
+        assert!((at_start("I_V1") - 0.75e-3).abs() < 1e-15);
+        assert!((at_start("I_R1") - at_start("I_C1") - at_start("I_D1")).abs() < 1e-15);
+        assert!((at_start("I_R2") - 0.5e-3).abs() < 1e-15);
+
+        // After that the capacitor's current follows its voltage
+        let current = 1e-6 * (results["V_2"][1] - results["V_2"][0]) / 1e-5;
+        assert!((results["I_C1"][1] - current).abs() < 1e-9);
+    }
+
+    #[test]
+    fn probes()
+    {
+        let netlist = "V1 1 0 PULSE(0 1 10u)\nR1 1 2 1k\nC1 2 0 10n\nD1 2 0".parse::<Netlist>().unwrap();
+        let mut simulation = TransientSimulation::new(&netlist).unwrap();
+
+        let probes = ["V(1,2)", "I(R1)*1k", "P(V1)+P(R1)+P(C1)+P(D1)", "P(R1)"].map(|p| p.parse::<Probe>().unwrap());
+        simulation.set_probes(&probes).unwrap();
+        let results = simulation.simulate(1e-6, 100).unwrap();
+
+        assert_eq!(simulation.signals().iter().map(|(name, unit)| format!("{} {}", name, unit)).collect::<Vec<_>>(),
+                   vec!["V(1,2) V", "I(R1)*1000 A", "P(V1)+P(R1)+P(C1)+P(D1) W", "P(R1) W"]);
+        assert_eq!(results.signals().len(), 4);
+
+        for (voltage, current) in results["V(1,2)"].iter().zip(results["I(R1)*1000"].iter())
+        {
+            assert!((voltage - current).abs() < 1e-9);
+        }
+        // Power balances
+        assert!(results["P(V1)+P(R1)+P(C1)+P(D1)"].iter().all(|p| p.abs() < 1e-9));
+        assert!(results["P(R1)"][50] > 0.0);
+
+        assert!(matches!(simulation.set_probes(&["I(R9)".parse().unwrap()]), Err(SimulationError::Unknown { .. })));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::dsp::{Decimator, Interpolator};
+use crate::netlist::{Exp, Scalar};
+use super::{check_step, Element, SimulationError, TransientSimulation, TransientState};
+
+/// Runs a transient simulation a block of samples at a time,
+/// e.g. as an audio effect. Voltage sources are driven from
+/// the input channels and results are written to the output
+/// channels, both interleaved one frame per sample. State is
+/// kept between blocks, and after the first sample's operating
+/// point processing doesn't allocate - though nonlinear circuits
+/// only refactor in place with `.OPTIONS SOLVER=SPARSE`, the
+/// dense solver allocating each time.
+pub struct TransientProcessor
+{
+    simulation: TransientSimulation,
+    sample_rate: Scalar,
+    /// Simulation steps per sample
+    factor: usize,
+    /// Simulation step
+    delta_t: Scalar,
+    /// Element of the source each input channel drives
+    inputs: Vec<usize>,
+    /// Index into the step's values of each output channel
+    outputs: Vec<usize>,
+    /// Per channel when oversampling
+    interpolators: Vec<Interpolator>,
+    decimators: Vec<Decimator>,
+    /// One frame of simulation steps, channel by channel
+    oversampled_inputs: Vec<Scalar>,
+    oversampled_outputs: Vec<Scalar>,
+}
+
+impl TransientProcessor
+{
+    /// `inputs` are voltage source names, replacing their
+    /// netlist values, and `outputs` are result names such
+    /// as "V_2" or "I_R1"
+    pub fn new(mut simulation: TransientSimulation, sample_rate: Scalar, inputs: &[&str], outputs: &[&str]) -> Result<Self, SimulationError>
+    {
+        let delta_t = 1.0 / sample_rate;
+        check_step(delta_t, "sample rate must be positive and finite")?;
+
+        let inputs = inputs.iter()
+            .map(|name|
+            {
+                let branch = simulation.layout.branch(name);
+                simulation.elements.iter()
+                    .position(|e| matches!(e, Element::Voltage { branch: b, .. } if Some(*b) == branch))
+                    .ok_or_else(|| SimulationError::Unknown { name: name.to_string() })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = outputs.iter()
+            .map(|name| simulation.names.iter()
+                .position(|n| n == name)
+                .ok_or_else(|| SimulationError::Unknown { name: name.to_string() }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for index in inputs.iter()
+        {
+            if let Element::Voltage { voltage, .. } = &mut simulation.elements[*index]
+            {
+                *voltage = Exp::Value(0.0);
+            }
+        }
+
+        let (oversampled_inputs, oversampled_outputs) = (vec![0.0; inputs.len()], vec![0.0; outputs.len()]);
+
+        Ok(TransientProcessor
+        {
+            simulation, sample_rate, factor: 1, delta_t, inputs, outputs,
+            interpolators: Vec::new(), decimators: Vec::new(), oversampled_inputs, oversampled_outputs,
+        })
+    }
+
+    /// Simulates `factor` steps per sample. Inputs are
+    /// interpolated up to the simulation's rate and outputs
+    /// decimated back down with anti-aliasing filters, which
+    /// delay the output by `latency` samples.
+    pub fn set_oversampling(&mut self, factor: usize) -> Result<(), SimulationError>
+    {
+        if factor == 0
+        {
+            return Err(SimulationError::InvalidTimestep { step: self.delta_t, reason: "oversampling factor must be at least one".to_owned() });
+        }
+
+        self.factor = factor;
+        self.delta_t = 1.0 / (self.sample_rate * factor as Scalar);
+        self.oversampled_inputs = vec![0.0; self.inputs.len() * factor];
+        self.oversampled_outputs = vec![0.0; self.outputs.len() * factor];
+
+        if factor == 1
+        {
+            self.interpolators.clear();
+            self.decimators.clear();
+        }
+        else
+        {
+            // Every filter exists, the factor being at least one
+            self.interpolators = self.inputs.iter().flat_map(|_| Interpolator::new(factor)).collect();
+            self.decimators = self.outputs.iter().flat_map(|_| Decimator::new(factor)).collect();
+        }
+        Ok(())
+    }
+
+    /// Samples from an input to its effect on the outputs
+    pub fn latency(&self) -> usize
+    {
+        let interpolator = self.interpolators.first().map(|i| i.delay()).unwrap_or(0);
+        let decimator = self.decimators.first().map(|d| d.delay()).unwrap_or(0);
+        interpolator + decimator
+    }
+
+    /// Time of the next sample
+    pub fn time(&self) -> Scalar
+    {
+        self.simulation.time
+    }
+
+    pub fn snapshot(&self) -> TransientState
+    {
+        self.simulation.snapshot()
+    }
+
+    /// Carries on from a snapshot, e.g. of a settled circuit
+    pub fn restore(&mut self, state: &TransientState) -> Result<(), SimulationError>
+    {
+        self.simulation.restore(state)
+    }
+
+    /// Simulates one step per frame. `input` has a sample for
+    /// each input channel per frame, and `output` is filled
+    /// with a sample for each output channel per frame.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), SimulationError>
+    {
+        let frames = if self.inputs.is_empty() { output.len() / self.outputs.len().max(1) } else { input.len() / self.inputs.len() };
+        if (input.len() != frames * self.inputs.len()) || (output.len() != frames * self.outputs.len())
+        {
+            return Err(SimulationError::InvalidFrames { input: input.len(), output: output.len() });
+        }
+
+        let inputs = input.chunks_exact(self.inputs.len().max(1));
+        let outputs = output.chunks_exact_mut(self.outputs.len().max(1));
+
+        let factor = self.factor;
+
+        for (frame, (input, output)) in inputs.zip(outputs).take(frames).enumerate()
+        {
+            for (channel, sample) in input.iter().enumerate()
+            {
+                let steps = &mut self.oversampled_inputs[channel * factor..(channel + 1) * factor];
+                match self.interpolators.get_mut(channel)
+                {
+                    Some(interpolator) =>
+                    {
+                        // Always one frame, so always filled
+                        let _ = interpolator.process(*sample as Scalar, steps);
+                    },
+                    None => steps[0] = *sample as Scalar,
+                }
+            }
+
+            for step in 0..factor
+            {
+                let time = self.simulation.time + ((frame * factor + step) as Scalar) * self.delta_t;
+
+                for (channel, index) in self.inputs.iter().enumerate()
+                {
+                    if let Element::Voltage { voltage: Exp::Value(value), .. } = &mut self.simulation.elements[*index]
+                    {
+                        *value = self.oversampled_inputs[channel * factor + step];
+                    }
+                }
+
+                let first = self.simulation.solved.is_none();
+                if let Err(error) = self.simulation.solve(time, self.delta_t)
+                {
+                    self.simulation.time = time;
+                    return Err(error);
+                }
+
+                let values = self.simulation.accept(time, self.delta_t);
+                if first
+                {
+                    // Start the filters settled
+                    for (decimator, index) in self.decimators.iter_mut().zip(self.outputs.iter())
+                    {
+                        decimator.prime(values[*index]);
+                    }
+                }
+                for (channel, index) in self.outputs.iter().enumerate()
+                {
+                    self.oversampled_outputs[channel * factor + step] = values[*index];
+                }
+            }
+
+            for (channel, sample) in output.iter_mut().enumerate()
+            {
+                let steps = &self.oversampled_outputs[channel * factor..(channel + 1) * factor];
+                *sample = self.decimators.get_mut(channel)
+                    .and_then(|decimator| decimator.process(steps))
+                    .unwrap_or(steps[0]) as f32;
+            }
+        }
+
+        self.simulation.time += ((frames * factor) as Scalar) * self.delta_t;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::{Device, Netlist};
+
+    #[test]
+    fn blocks_match_simulate()
+    {
+        let circuit = "R1 1 2 1k\nC1 2 0 100n\nD1 2 0\nD2 0 2";
+        let driven = format!("V1 1 0 2*sin(100000*t)\n{}", circuit).parse::<Netlist>().unwrap();
+        let bound = format!("V1 1 0 0\n{}", circuit).parse::<Netlist>().unwrap();
+
+        let expected = TransientSimulation::new(&driven).unwrap().simulate(1.0 / 48000.0, 480).unwrap();
+
+        let mut processor = TransientProcessor::new(TransientSimulation::new(&bound).unwrap(), 48000.0, &["V1"], &["V_2", "I_D1"]).unwrap();
+        let Some(Device::Voltage { voltage, .. }) = driven.device("V1") else { panic!() };
+        let input = (0..480).map(|i| voltage.calc(i as Scalar / 48000.0) as f32).collect::<Vec<_>>();
+        let mut output = vec![0.0; 960];
+
+        for (input, output) in input.chunks(64).zip(output.chunks_mut(128))
+        {
+            processor.process(input, output).unwrap();
+        }
+
+        for i in 0..480
+        {
+            assert!((output[2 * i] as Scalar - expected["V_2"][i]).abs() < 1e-4);
+            assert!((output[2 * i + 1] as Scalar - expected["I_D1"][i]).abs() < 1e-5);
+        }
+
+        // Oversampled, the output is delayed by the filters
+        let expected = TransientSimulation::new(&driven).unwrap().simulate_oversampled(1.0 / 48000.0, 480, 4).unwrap();
+
+        let mut processor = TransientProcessor::new(TransientSimulation::new(&bound).unwrap(), 48000.0, &["V1"], &["V_2"]).unwrap();
+        processor.set_oversampling(4).unwrap();
+        let mut output = vec![0.0; 480];
+        processor.process(&input, &mut output).unwrap();
+
+        let latency = processor.latency();
+        for i in 50..(480 - latency)
+        {
+            assert!((output[i + latency] as Scalar - expected["V_2"][i]).abs() < 1e-3);
+        }
+
+        let error = processor.process(&input[..10], &mut output[..11]);
+        assert_eq!(error, Err(SimulationError::InvalidFrames { input: 10, output: 11 }));
+
+        // The netlist's solver is kept
+        assert!(processor.simulation.sparse.is_none());
+        let sparse = format!("V1 1 0 0\n{}\n.OPTIONS SOLVER=SPARSE", circuit).parse::<Netlist>().unwrap();
+        let processor = TransientProcessor::new(TransientSimulation::new(&sparse).unwrap(), 48000.0, &["V1"], &["V_2"]).unwrap();
+        assert!(processor.simulation.sparse.is_some());
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use crate::netlist::Scalar;
+use super::{CapacitorHistory, Element, SimulationError, TransientSimulation};
+
+/// Everything a transient simulation needs to carry on from
+/// where it was - the time, the last solution, and each
+/// capacitor's history and diode's linearisation. Devices and
+/// values are kept by name, so a snapshot can be restored into
+/// a simulation of a tweaked copy of the netlist.
+///
+/// Saved as text, one item per line, with values written so
+/// they read back exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransientState
+{
+    time: Scalar,
+    solved: Option<Scalar>,
+    next_step: Option<Scalar>,
+    values: Vec<(String, Scalar)>,
+    devices: Vec<(String, DeviceState)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DeviceState
+{
+    Capacitor(CapacitorHistory),
+    Diode{junction_voltage: Scalar},
+}
+
+impl TransientSimulation
+{
+    pub fn snapshot(&self) -> TransientState
+    {
+        let values = match self.solved
+        {
+            Some(_) => self.names.iter().cloned().zip(self.values.iter().copied()).collect(),
+            None => Vec::new(),
+        };
+
+        let devices = self.elements.iter()
+            .filter_map(|element| match element
+            {
+                Element::Capacitor { name, history, .. } => Some((name.clone(), DeviceState::Capacitor(history.clone()))),
+                Element::Diode { name, junction_voltage, .. } => Some((name.clone(), DeviceState::Diode { junction_voltage: *junction_voltage })),
+                _ => None,
+            })
+            .collect();
+
+        TransientState { time: self.time, solved: self.solved, next_step: self.next_step, values, devices }
+    }
+
+    /// Carries on from a snapshot. Every capacitor and diode,
+    /// and every value if a step had been solved, must be in
+    /// the snapshot.
+    pub fn restore(&mut self, state: &TransientState) -> Result<(), SimulationError>
+    {
+        let missing = |name: &str| SimulationError::Unknown { name: name.to_owned() };
+
+        let values = match state.solved
+        {
+            Some(_) => self.names.iter()
+                .map(|name| state.values.iter().find(|(n, _)| n == name).map(|(_, v)| *v).ok_or_else(|| missing(name)))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+
+        // Check everything is there before changing anything
+
+        let device = |name: &str| state.devices.iter().find(|(n, _)| n == name).map(|(_, d)| d);
+        for element in self.elements.iter()
+        {
+            match (element, element.name().map(device))
+            {
+                (Element::Capacitor { .. }, Some(Some(DeviceState::Capacitor(_)))) => (),
+                (Element::Diode { .. }, Some(Some(DeviceState::Diode { .. }))) => (),
+                (Element::Capacitor { name, .. } | Element::Diode { name, .. }, _) => return Err(missing(name)),
+                _ => (),
+            }
+        }
+
+        for element in self.elements.iter_mut()
+        {
+            match element
+            {
+                Element::Capacitor { name, history, .. } =>
+                {
+                    if let Some(DeviceState::Capacitor(saved)) = device(name)
+                    {
+                        *history = saved.clone();
+                    }
+                },
+                Element::Diode { name, junction_voltage, .. } =>
+                {
+                    if let Some(DeviceState::Diode { junction_voltage: saved }) = device(name)
+                    {
+                        *junction_voltage = *saved;
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        self.time = state.time;
+        self.solved = state.solved;
+        self.next_step = state.next_step;
+        self.values = values;
+        self.factored = None;
+        self.partial = None;
+        Ok(())
+    }
+}
+
+impl Display for TransientState
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult
+    {
+        writeln!(f, "TIME {:e}", self.time)?;
+        if let Some(solved) = self.solved
+        {
+            writeln!(f, "SOLVED {:e}", solved)?;
+        }
+        if let Some(next_step) = self.next_step
+        {
+            writeln!(f, "NEXT_STEP {:e}", next_step)?;
+        }
+        for (name, value) in self.values.iter()
+        {
+            writeln!(f, "VALUE {} {:e}", name, value)?;
+        }
+        for (name, device) in self.devices.iter()
+        {
+            match device
+            {
+                DeviceState::Capacitor(history) =>
+                {
+                    let [t0, t1, t2] = history.times;
+                    let [v0, v1, v2] = history.voltages;
+                    writeln!(f, "CAPACITOR {} {} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {}",
+                        name, history.steps, t0, t1, t2, v0, v1, v2, history.current, history.current_change, history.alternations)?;
+                },
+                DeviceState::Diode { junction_voltage } =>
+                {
+                    writeln!(f, "DIODE {} {:e}", name, junction_voltage)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TransientState
+{
+    type Err = SimulationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut state = TransientState { time: 0.0, solved: None, next_step: None, values: Vec::new(), devices: Vec::new() };
+        let mut has_time = false;
+
+        for (index, line) in s.lines().enumerate()
+        {
+            let invalid = |reason: &str| SimulationError::InvalidSnapshot { line: index + 1, reason: reason.to_owned() };
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let Some((kind, fields)) = fields.split_first() else { continue; };
+
+            let scalar = |i: usize| fields.get(i).and_then(|f| f.parse::<Scalar>().ok()).ok_or_else(|| invalid("Expected a value"));
+            let count = |i: usize| fields.get(i).and_then(|f| f.parse::<usize>().ok()).ok_or_else(|| invalid("Expected a count"));
+            let name = || fields.first().map(|f| f.to_string()).ok_or_else(|| invalid("Expected a name"));
+
+            let expected = match *kind
+            {
+                "TIME" =>
+                {
+                    state.time = scalar(0)?;
+                    has_time = true;
+                    1
+                },
+                "SOLVED" =>
+                {
+                    state.solved = Some(scalar(0)?);
+                    1
+                },
+                "NEXT_STEP" =>
+                {
+                    state.next_step = Some(scalar(0)?);
+                    1
+                },
+                "VALUE" =>
+                {
+                    state.values.push((name()?, scalar(1)?));
+                    2
+                },
+                "CAPACITOR" =>
+                {
+                    let history = CapacitorHistory
+                    {
+                        steps: count(1)?,
+                        times: [scalar(2)?, scalar(3)?, scalar(4)?],
+                        voltages: [scalar(5)?, scalar(6)?, scalar(7)?],
+                        current: scalar(8)?,
+                        current_change: scalar(9)?,
+                        alternations: count(10)?,
+                    };
+                    state.devices.push((name()?, DeviceState::Capacitor(history)));
+                    11
+                },
+                "DIODE" =>
+                {
+                    state.devices.push((name()?, DeviceState::Diode { junction_voltage: scalar(1)? }));
+                    2
+                },
+                _ => return Err(invalid(&format!("Unknown item \"{}\"", kind))),
+            };
+
+            if fields.len() != expected
+            {
+                return Err(invalid(&format!("Expected {} fields after {}", expected, kind)));
+            }
+        }
+
+        if !has_time
+        {
+            return Err(SimulationError::InvalidSnapshot { line: 0, reason: "No TIME".to_owned() });
+        }
+
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::netlist::Netlist;
+
+    #[test]
+    fn restored_run_matches()
+    {
+        let netlist = "V1 1 0 PULSE(0 2 10u 1u 1u 30u 60u)\nR1 1 2 1k\nC1 2 0 10n\nD1 2 3\nR2 3 0 10k\nC2 3 0 1n".parse::<Netlist>().unwrap();
+
+        let mut straight = TransientSimulation::new(&netlist).unwrap();
+        straight.simulate(1e-6, 100).unwrap();
+        let expected = straight.simulate(1e-6, 100).unwrap();
+
+        let mut first = TransientSimulation::new(&netlist).unwrap();
+        first.simulate(1e-6, 100).unwrap();
+        let saved = first.snapshot().to_string();
+
+        let mut resumed = TransientSimulation::new(&netlist).unwrap();
+        resumed.restore(&saved.parse().unwrap()).unwrap();
+        assert_eq!(resumed.simulate(1e-6, 100).unwrap(), expected);
+
+        // Another netlist needs the same devices
+        let other = "V1 1 0 1\nR1 1 2 1k\nC3 2 0 10n".parse::<Netlist>().unwrap();
+        assert!(TransientSimulation::new(&other).unwrap().restore(&saved.parse().unwrap()).is_err());
+    }
+}
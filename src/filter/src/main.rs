@@ -2,7 +2,7 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::time::Instant;
 
-use filter_lib::{netlist::{Netlist, ParseError}, sim::transient::TransientSimulation};
+use filter_lib::{io::{csv::{self, CsvFormat}, wav::{self, SampleFormat, WavScaling}}, netlist::{Netlist, ParseError, Probe}, sim::{analysis::AnalysisResults, fourier, measure, transient::TransientSimulation, SimulationError}};
 
 const NETLIST_FILE: &str = r#"
 V1 1 0 4*sin(1000+10000*t)+30*t
@@ -14,28 +14,71 @@ D2 0 2
 Rd 2 3 10000000
 E1 4 0 2 3 1000000
 Rg1 4 3 3000
-Rg2 3 0 1000"#;
+Rg2 3 0 1000
+.TRAN 20.8333u 125m
+.MEAS TRAN first_clip WHEN V(2)=0.5 RISE=1
+.MEAS TRAN clip_pp PP V(2) FROM=25m
+.MEAS TRAN out_rms RMS V(4) FROM=25m
+.MEAS TRAN out_max MAX V(4)
+.FOUR 80 V(2) V(4)"#;
 
 fn main() -> Result<(), ParseError>
 {
     let netlist = NETLIST_FILE.parse::<Netlist>()?;
 
-    let mut trans = TransientSimulation::new(&netlist);
+    let mut trans = TransientSimulation::new(&netlist).unwrap_or_else(|err| fail(err));
+
+    let probes = ["V(1)", "V(2)", "V(3)", "V(4)"].iter()
+        .map(|probe| probe.parse::<Probe>())
+        .collect::<Result<Vec<_>, _>>()?;
+    trans.set_probes(&probes).unwrap_or_else(|err| fail(err));
 
     let mut graph = filter_lib::graph::Graph::new();
 
     let steps = 6000;
+    let delta_t = 1.0 / 48000.0;
 
     let start = Instant::now();
 
-    let results = trans.simulate(1.0/48000.0, steps);
+    // Oversampled so the clipping diodes don't alias
+    let results = trans.simulate_oversampled(delta_t, steps, 4).unwrap_or_else(|err| fail(err));
 
     let duration = start.elapsed();
     println!("Solved {} steps in  {:?}", steps, duration);
 
-    for var in ["V_1", "V_2", "V_3", "V_4"]
+    for (time, method) in trans.convergence_aids()
+    {
+        println!("Needed {} at time={}", method, time);
+    }
+
+    let measured = measure::analyse(&netlist, &AnalysisResults::Transient(results.clone()));
+    for measure in netlist.measures()
     {
-        graph.add_trace(results.get(var).unwrap(), 5.0, var, "V");
+        match measured.get(&measure.name)
+        {
+            Some(value) => println!("{} = {}", measure.name, netlist.options().format(*value)),
+            None => println!("{} failed", measure.name),
+        }
+    }
+
+    let format = |value: f64| netlist.options().format(value);
+    for (four, analysed) in netlist.fourier().iter().zip(fourier::analyse_all(&netlist, &results))
+    {
+        for (output, reference) in four.outputs.iter()
+        {
+            let name = Probe::Voltage { node: output.clone(), reference: reference.clone() }.to_string();
+            match analysed.get(&name)
+            {
+                Some(result) => println!("{} at {}Hz: magnitude = {}, THD = {}%", name, four.fundamental,
+                    format(result.harmonics[0].magnitude), format(100.0 * result.thd)),
+                None => println!("{} Fourier analysis failed", name),
+            }
+        }
+    }
+
+    for signal in results.signals()
+    {
+        graph.add_trace(&signal.values, 5.0, &signal.name, &signal.unit.to_string());
     }
 
     let svg = graph.to_svg();
@@ -43,5 +86,20 @@ fn main() -> Result<(), ParseError>
     let mut file = File::create("results.svg").unwrap();
     file.write_all(svg.as_bytes()).unwrap();
 
+    // For analysis scripts, which can't read the SVG
+    let format = CsvFormat { precision: netlist.options().precision, ..CsvFormat::CSV };
+    let mut file = std::io::BufWriter::new(File::create("results.csv").unwrap());
+    csv::write_transient(&mut file, &results, &format).unwrap();
+
+    // And to listen to the output
+    let mut file = std::io::BufWriter::new(File::create("results.wav").unwrap());
+    wav::write_signals(&mut file, &results, &["V(4)"], SampleFormat::Pcm16, WavScaling::Normalise).unwrap();
+
     Ok(())
 }
+
+fn fail(err: SimulationError) -> !
+{
+    eprintln!("error: {}", err);
+    std::process::exit(1);
+}